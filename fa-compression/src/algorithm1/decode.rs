@@ -71,6 +71,74 @@ pub fn decode(input: &[u8]) -> String {
     result
 }
 
+/// Checks if the encoded annotations contain an annotation, without decoding all annotations.
+///
+/// The encoded characters are compared one by one with the annotation, so no string has to be
+/// allocated. The scan stops as soon as the annotations of the type (EC, GO or IPR) of the
+/// requested annotation are passed.
+///
+/// # Arguments
+///
+/// * `input` - The byte array with the encoded annotations.
+/// * `annotation` - The annotation to look for, including its prefix (e.g., "GO:0009279").
+///
+/// # Returns
+///
+/// True if `annotation` is one of the encoded annotations, false otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use fa_compression::algorithm1::{contains, encode};
+///
+/// let input = encode("IPR:IPR016364;EC:1.1.1.-;GO:0009279");
+/// assert!(contains(&input, "GO:0009279"));
+/// assert!(!contains(&input, "GO:000927"));
+/// ```
+pub fn contains(input: &[u8], annotation: &str) -> bool {
+    // annotations with an unknown prefix can never be part of the encoded annotations
+    let Some((section, value)) = PREFIXES
+        .iter()
+        .enumerate()
+        .find_map(|(i, prefix)| annotation.strip_prefix(prefix).map(|value| (i, value.as_bytes())))
+    else {
+        return false;
+    };
+
+    let mut current_section = 0;
+    // the number of characters of the current annotation that match `value`, or None if it does not match
+    let mut matched = Some(0);
+    for character in input
+        .iter()
+        .flat_map(|&byte| [byte >> 4, byte & 0b1111])
+        .map(CharacterSet::decode)
+    {
+        match character {
+            // padding of the last byte
+            '$' => continue,
+            ',' | ';' => {
+                if current_section == section && matched == Some(value.len()) {
+                    return true;
+                }
+                if character == ',' {
+                    current_section += 1;
+                    if current_section > section {
+                        return false;
+                    }
+                }
+                matched = Some(0);
+            }
+            c => {
+                matched = matched
+                    .filter(|&m| m < value.len() && value[m] == c as u8)
+                    .map(|m| m + 1);
+            }
+        }
+    }
+
+    current_section == section && matched == Some(value.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,4 +196,27 @@ mod tests {
             "EC:1.1.1.-;GO:0009279;IPR:IPR016364;IPR:IPR032635;IPR:IPR008816"
         )
     }
+
+    #[test]
+    fn test_contains() {
+        let input = &[44, 44, 44, 189, 17, 26, 56, 173, 18, 116, 117, 225, 67, 116, 110, 17, 153, 39];
+
+        assert!(contains(input, "EC:1.1.1.-"));
+        assert!(contains(input, "GO:0009279"));
+        assert!(contains(input, "IPR:IPR016364"));
+        assert!(contains(input, "IPR:IPR008816"));
+
+        // only complete annotations of the right type match
+        assert!(!contains(input, "GO:000927"));
+        assert!(!contains(input, "EC:1.1.1"));
+        assert!(!contains(input, "IPR:IPR0163641"));
+        assert!(!contains(input, "EC:0009279"));
+        assert!(!contains(input, "KO:0009279"));
+    }
+
+    #[test]
+    fn test_contains_empty() {
+        assert!(!contains(&[], "GO:0009279"));
+        assert!(!contains(&[209, 17, 163, 138, 208], "IPR:IPR016364"));
+    }
 }
@@ -1,10 +1,38 @@
 //! This module contains the function to encode the input string into a compressed byte vector.
 
+use std::{
+    error::Error,
+    fmt::{
+        Display,
+        Formatter
+    }
+};
+
 use super::{
     CharacterSet,
     Encode
 };
 
+/// Error returned by `encode_strict` when an annotation is not an InterPro, Gene Ontology or
+/// Enzyme Commission annotation.
+#[derive(Debug, PartialEq)]
+pub struct UnknownAnnotationError {
+    /// The first annotation with an unknown prefix.
+    pub annotation: String
+}
+
+impl Display for UnknownAnnotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Annotation {} can not be encoded, only IPR, GO and EC annotations are supported",
+            self.annotation
+        )
+    }
+}
+
+impl Error for UnknownAnnotationError {}
+
 /// Encodes the input string into a compressed byte vector.
 ///
 /// The input string is expected to contain annotations separated by semicolons (;).
@@ -30,9 +58,102 @@ use super::{
 ///
 /// assert_eq!(encoded, vec![ 44, 44, 44, 189, 17, 26, 56, 173, 18, 116, 117 ]);
 /// ```
+///
+/// Annotations of any other type are silently dropped, use `encode_with_report` or
+/// `encode_strict` to detect them.
+///
+/// The order of the annotations is not kept: decoding the result gives the EC annotations first,
+/// then the GO annotations and then the IPR annotations, see `canonicalize`.
 pub fn encode(input: &str) -> Vec<u8> {
+    encode_with_report(input).0
+}
+
+/// Encodes the input string into a compressed byte vector, and reports the number of annotations
+/// that could not be encoded.
+///
+/// The annotations are encoded exactly like `encode` does, annotations that are not an InterPro
+/// (IPR), Gene Ontology (GO) or Enzyme Commission (EC) annotation are dropped and counted.
+///
+/// # Arguments
+///
+/// * `input` - The input string containing annotations.
+///
+/// # Returns
+///
+/// A compressed byte vector representing the encoded annotations, together with the number of
+/// dropped annotations.
+///
+/// # Examples
+///
+/// ```
+/// use fa_compression::algorithm1::{encode, encode_with_report};
+///
+/// let (encoded, dropped) = encode_with_report("EC:1.1.1.-;KO:K00001");
+///
+/// assert_eq!(encoded, encode("EC:1.1.1.-"));
+/// assert_eq!(dropped, 1);
+/// ```
+pub fn encode_with_report(input: &str) -> (Vec<u8>, usize) {
+    let mut dropped = 0;
+    let encoded = encode_annotations(input, |_| {
+        dropped += 1;
+        Ok(())
+    });
+
+    // the callback never returns an error
+    (encoded.unwrap_or_default(), dropped)
+}
+
+/// Encodes the input string into a compressed byte vector, failing on annotations that can not be
+/// encoded.
+///
+/// # Arguments
+///
+/// * `input` - The input string containing annotations.
+///
+/// # Returns
+///
+/// A compressed byte vector representing the encoded annotations.
+///
+/// # Errors
+///
+/// Returns an `UnknownAnnotationError` with the first annotation that is not an InterPro (IPR),
+/// Gene Ontology (GO) or Enzyme Commission (EC) annotation.
+///
+/// # Examples
+///
+/// ```
+/// use fa_compression::algorithm1::encode_strict;
+///
+/// assert!(encode_strict("EC:1.1.1.-;GO:0009279").is_ok());
+/// assert!(encode_strict("EC:1.1.1.-;KO:K00001").is_err());
+/// ```
+pub fn encode_strict(input: &str) -> Result<Vec<u8>, UnknownAnnotationError> {
+    encode_annotations(input, |annotation| {
+        Err(UnknownAnnotationError {
+            annotation: annotation.to_string()
+        })
+    })
+}
+
+/// Encodes the input string into a compressed byte vector.
+///
+/// # Arguments
+///
+/// * `input` - The input string containing annotations.
+/// * `unknown` - Called with every annotation that can not be encoded, the encoding stops if it
+///   returns an error.
+///
+/// # Returns
+///
+/// A compressed byte vector representing the encoded annotations, or the first error returned by
+/// `unknown`.
+fn encode_annotations(
+    input: &str,
+    mut unknown: impl FnMut(&str) -> Result<(), UnknownAnnotationError>
+) -> Result<Vec<u8>, UnknownAnnotationError> {
     if input.is_empty() {
-        return Vec::new();
+        return Ok(Vec::new());
     }
 
     // ==========================================================================================
@@ -55,6 +176,8 @@ pub fn encode(input: &str) -> Vec<u8> {
             gos.push(&annotation[3 ..]);
         } else if annotation.starts_with("EC") {
             ecs.push(&annotation[3 ..]);
+        } else if !annotation.is_empty() {
+            unknown(annotation)?;
         }
     }
 
@@ -80,12 +203,13 @@ pub fn encode(input: &str) -> Vec<u8> {
         }
     }
 
-    encoded
+    Ok(encoded)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::algorithm1::decode;
 
     #[test]
     fn test_encode_empty() {
@@ -138,4 +262,29 @@ mod tests {
             vec![44, 44, 44, 189, 17, 26, 56, 173, 18, 116, 117, 225, 67, 116, 110, 17, 153, 39]
         )
     }
+
+    #[test]
+    fn test_encode_unknown_annotation() {
+        // the annotation is reported instead of silently dropped
+        let (encoded, dropped) = encode_with_report("KO:K00001");
+        assert_eq!(decode(&encoded), "");
+        assert_eq!(dropped, 1);
+
+        assert_eq!(
+            encode_with_report("IPR:IPR016364;KO:K00001;EC:1.1.1.-;KO:K00002"),
+            (encode("IPR:IPR016364;EC:1.1.1.-"), 2)
+        );
+        assert_eq!(encode_with_report("GO:0009279"), (encode("GO:0009279"), 0));
+    }
+
+    #[test]
+    fn test_encode_strict() {
+        assert_eq!(encode_strict("GO:0009279;EC:1.1.1.-"), Ok(encode("GO:0009279;EC:1.1.1.-")));
+        assert_eq!(
+            encode_strict("GO:0009279;KO:K00001"),
+            Err(UnknownAnnotationError {
+                annotation: "KO:K00001".to_string()
+            })
+        );
+    }
 }
@@ -5,9 +5,11 @@ use std::ops::BitOr;
 
 mod decode;
 mod encode;
+mod roundtrip;
 
-pub use decode::decode;
-pub use encode::encode;
+pub use decode::{contains, decode};
+pub use encode::{encode, encode_strict, encode_with_report, UnknownAnnotationError};
+pub use roundtrip::{canonicalize, roundtrip_ok};
 
 /// Trait for encoding a value into a character set.
 trait Encode {
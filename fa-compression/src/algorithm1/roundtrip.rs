@@ -0,0 +1,165 @@
+//! This module contains the functions to check that decoding an encoded string gives back the
+//! original annotations.
+
+use super::{
+    decode,
+    encode
+};
+
+/// Converts an annotation string into the canonical form that `decode` returns.
+///
+/// `encode` groups the annotations per type, so the canonical form first lists the Enzyme
+/// Commission (EC) annotations, then the Gene Ontology (GO) annotations and finally the InterPro
+/// (IPR) annotations. Within a type, the annotations keep the order of the input. Empty
+/// annotations are removed.
+///
+/// # Arguments
+///
+/// * `input` - The input string containing annotations separated by semicolons (;).
+///
+/// # Returns
+///
+/// The canonical form of the annotations, or None if the input contains an annotation that is
+/// not a valid IPR, GO or EC annotation.
+///
+/// # Examples
+///
+/// ```
+/// use fa_compression::algorithm1::canonicalize;
+///
+/// let canonical = canonicalize("IPR:IPR016364;GO:0009279;EC:1.1.1.-;GO:0005737");
+///
+/// assert_eq!(
+///     canonical,
+///     Some("EC:1.1.1.-;GO:0009279;GO:0005737;IPR:IPR016364".to_string())
+/// );
+/// assert_eq!(canonicalize("KO:K00001"), None);
+/// ```
+pub fn canonicalize(input: &str) -> Option<String> {
+    let mut ecs: Vec<&str> = Vec::new();
+    let mut gos: Vec<&str> = Vec::new();
+    let mut interpros: Vec<&str> = Vec::new();
+
+    for annotation in input.split(';').filter(|annotation| !annotation.is_empty()) {
+        if is_valid_number(annotation.strip_prefix("EC:"), b".-") {
+            ecs.push(annotation);
+        } else if is_valid_number(annotation.strip_prefix("GO:"), b"") {
+            gos.push(annotation);
+        } else if is_valid_number(annotation.strip_prefix("IPR:IPR"), b"") {
+            interpros.push(annotation);
+        } else {
+            return None;
+        }
+    }
+
+    Some([ecs, gos, interpros].concat().join(";"))
+}
+
+/// Checks if encoding and decoding an annotation string gives back the canonical form of the
+/// annotations.
+///
+/// This is meant as an entry point for fuzzing: it should hold for every input.
+///
+/// # Arguments
+///
+/// * `input` - The input string containing annotations separated by semicolons (;).
+///
+/// # Returns
+///
+/// True if `decode(encode(input))` equals `canonicalize(input)`. Inputs that are not a list of
+/// valid IPR, GO and EC annotations can not be encoded, for those this always returns true.
+///
+/// # Examples
+///
+/// ```
+/// use fa_compression::algorithm1::roundtrip_ok;
+///
+/// assert!(roundtrip_ok("IPR:IPR016364;EC:1.1.1.-;GO:0009279"));
+/// ```
+pub fn roundtrip_ok(input: &str) -> bool {
+    match canonicalize(input) {
+        Some(canonical) => decode(&encode(input)) == canonical,
+        None => true
+    }
+}
+
+/// Checks if the number of an annotation only consists of digits and the allowed extra characters.
+///
+/// # Arguments
+///
+/// * `number` - The annotation without its prefix, or None if the annotation has another prefix.
+/// * `extra` - The characters that are allowed next to the digits.
+///
+/// # Returns
+///
+/// True if the number is not empty and only contains valid characters.
+fn is_valid_number(number: Option<&str>, extra: &[u8]) -> bool {
+    number.is_some_and(|number| {
+        !number.is_empty()
+            && number
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || extra.contains(&byte))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{
+        rngs::StdRng,
+        Rng,
+        SeedableRng
+    };
+
+    use super::*;
+
+    /// Generates a random valid annotation.
+    fn generate_annotation(random: &mut StdRng) -> String {
+        match random.gen_range(0 .. 3) {
+            0 => format!("IPR:IPR{:06}", random.gen_range(0 .. 999999)),
+            1 => format!("GO:{:07}", random.gen_range(0 .. 9999999)),
+            _ => {
+                let parts: Vec<String> = (0 .. 4)
+                    .map(|_| match random.gen_range(0 .. 4) {
+                        0 => "-".to_string(),
+                        _ => random.gen_range(0 .. 200).to_string()
+                    })
+                    .collect();
+                format!("EC:{}", parts.join("."))
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize(""), Some(String::new()));
+        assert_eq!(
+            canonicalize("GO:0009279;;IPR:IPR016364;EC:1.1.1.-;"),
+            Some("EC:1.1.1.-;GO:0009279;IPR:IPR016364".to_string())
+        );
+        assert_eq!(canonicalize("GO:0009279;KO:K00001"), None);
+        assert_eq!(canonicalize("GO:"), None);
+        assert_eq!(canonicalize("GO:00a9279"), None);
+        assert_eq!(canonicalize("IPR:016364"), None);
+    }
+
+    #[test]
+    fn test_roundtrip_random_annotations() {
+        let mut random = StdRng::seed_from_u64(42);
+
+        for _ in 0 .. 1000 {
+            let count = random.gen_range(0 .. 20);
+            let annotations: Vec<String> =
+                (0 .. count).map(|_| generate_annotation(&mut random)).collect();
+            let input = annotations.join(";");
+
+            assert!(canonicalize(&input).is_some(), "{} is not valid", input);
+            assert!(roundtrip_ok(&input), "{} does not round trip", input);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_invalid_input() {
+        assert!(roundtrip_ok("KO:K00001"));
+        assert!(roundtrip_ok("EC"));
+    }
+}
@@ -0,0 +1,112 @@
+//! This module contains the `AminoAcidAlphabet` that defines which bytes can occur in the concatenated text of the proteins.
+
+/// The alphabet of the concatenated text of the proteins: the uppercase residues, the separation character
+/// between 2 proteins and the termination character at the end of the text
+/// The suffix tree, the suffix array and the builders all use this alphabet, so they agree on which bytes are valid
+pub struct AminoAcidAlphabet;
+
+impl AminoAcidAlphabet {
+    /// The residues that can occur in a protein sequence
+    pub const RESIDUES: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    /// The character that separates 2 proteins in the text
+    pub const SEPARATION_CHARACTER: u8 = b'-';
+
+    /// The character that ends the text, this character should be smaller than the separation character and every residue
+    pub const TERMINATION_CHARACTER: u8 = b'$';
+
+    /// All the bytes of the alphabet: the residues followed by the separation character and the termination character
+    pub const SYMBOLS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ-$";
+
+    /// The residues that can not be distinguished from each other (by mass spectrometry), the first residue of a class is its canonical residue
+    pub const EQUIVALENT_RESIDUES: &'static [&'static str] = &["IL"];
+
+    /// Returns true if `character` is a residue
+    #[inline]
+    pub const fn is_residue(character: u8) -> bool {
+        character.is_ascii_uppercase()
+    }
+
+    /// Returns true if `character` is the separation or the termination character
+    #[inline]
+    pub const fn is_sentinel(character: u8) -> bool {
+        character == Self::SEPARATION_CHARACTER || character == Self::TERMINATION_CHARACTER
+    }
+
+    /// Returns true if `character` is part of the alphabet
+    #[inline]
+    pub const fn is_valid(character: u8) -> bool {
+        Self::is_residue(character) || Self::is_sentinel(character)
+    }
+
+    /// Returns the position of `character` in `SYMBOLS`, or None if the character is not part of the alphabet
+    #[inline]
+    pub const fn index(character: u8) -> Option<usize> {
+        if Self::is_residue(character) {
+            Some((character - b'A') as usize)
+        } else if character == Self::SEPARATION_CHARACTER {
+            Some(Self::RESIDUES.len())
+        } else if character == Self::TERMINATION_CHARACTER {
+            Some(Self::RESIDUES.len() + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the canonical residue of `character` for the `EQUIVALENT_RESIDUES`, every L is mapped to an I
+    /// Characters that are not equivalent to another character are returned as they are
+    #[inline]
+    pub const fn canonical(character: u8) -> u8 {
+        match character {
+            b'L' => b'I',
+            _ => character,
+        }
+    }
+
+    /// Replaces every character in `data` by its canonical residue
+    ///
+    /// # Arguments
+    /// * `data` - The text that is canonicalized in place
+    pub fn canonicalize(data: &mut [u8]) {
+        for character in data.iter_mut() {
+            *character = Self::canonical(*character);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(AminoAcidAlphabet::SYMBOLS.len(), AminoAcidAlphabet::RESIDUES.len() + 2);
+        assert!(AminoAcidAlphabet::SYMBOLS.starts_with(AminoAcidAlphabet::RESIDUES));
+
+        // the index of every symbol is its position, every other byte has no index
+        for character in 0..=u8::MAX {
+            let position = AminoAcidAlphabet::SYMBOLS.iter().position(|&symbol| symbol == character);
+            assert_eq!(AminoAcidAlphabet::index(character), position);
+            assert_eq!(AminoAcidAlphabet::is_valid(character), position.is_some());
+        }
+    }
+
+    #[test]
+    fn test_termination_character_is_smallest() {
+        assert!(AminoAcidAlphabet::SYMBOLS
+            .iter()
+            .all(|&symbol| symbol >= AminoAcidAlphabet::TERMINATION_CHARACTER));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut data = b"LIKL-AL$".to_vec();
+        AminoAcidAlphabet::canonicalize(&mut data);
+        assert_eq!(data, b"IIKI-AI$");
+
+        for class in AminoAcidAlphabet::EQUIVALENT_RESIDUES {
+            let canonical = class.as_bytes()[0];
+            assert!(class.bytes().all(|residue| AminoAcidAlphabet::canonical(residue) == canonical));
+        }
+    }
+}
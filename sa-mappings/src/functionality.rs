@@ -1,24 +1,187 @@
 //! This module contains the FunctionAggregator struct that is responsible for aggregating the
 //! functional annotations of proteins.
 
-use std::collections::{HashMap, HashSet};
-use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 
 use crate::proteins::Protein;
 
 /// A struct that represents the functional annotations once aggregated
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FunctionalAggregation {
-    /// A HashMap representing how many GO, EC and IPR terms were found
-    pub counts: HashMap<String, usize>,
-    /// A HashMap representing how often a certain functional annotation was found
-    pub data: HashMap<String, u32>,
+    /// A BTreeMap representing how many GO, EC and IPR terms were found, ordered by key so the JSON output is deterministic
+    pub counts: BTreeMap<String, usize>,
+    /// A BTreeMap representing how often a certain functional annotation was found, ordered by annotation
+    pub data: BTreeMap<String, u32>,
+}
+
+impl FunctionalAggregation {
+    /// Lists the functional annotations from the most to the least frequent
+    ///
+    /// # Returns
+    ///
+    /// Returns every functional annotation with the number of times it was found, sorted descending by that number.
+    /// Annotations that were found equally often are sorted lexicographically, so the order is deterministic
+    pub fn sorted_by_count(&self) -> Vec<(String, u32)> {
+        let mut annotations: Vec<(String, u32)> =
+            self.data.iter().map(|(annotation, &count)| (annotation.clone(), count)).collect();
+        annotations.sort_unstable_by(|(annotation1, count1), (annotation2, count2)| {
+            count2.cmp(count1).then_with(|| annotation1.cmp(annotation2))
+        });
+        annotations
+    }
 }
 
 /// A struct that represents a function aggregator
 pub struct FunctionAggregator {}
 
+/// The types of functional annotations, in the order they are reported
+pub const ANNOTATION_TYPES: [&str; 3] = ["EC", "GO", "IPR"];
+
+/// Determines the type of a functional annotation from its prefix
+///
+/// # Arguments
+/// * `annotation` - A decoded functional annotation, e.g. `GO:0009279`
+///
+/// # Returns
+///
+/// Returns "EC", "GO" or "IPR", or None if the annotation has an unknown prefix
+fn annotation_type(annotation: &str) -> Option<&'static str> {
+    match annotation.chars().next() {
+        Some('E') => Some("EC"),
+        Some('G') => Some("GO"),
+        Some('I') => Some("IPR"),
+        _ => None
+    }
+}
+
+/// A thread-safe cache of decoded functional annotations, indexed by the index of the protein in `Proteins`
+/// This makes sure the annotations of a protein are decoded at most once, e.g. when many peptides of a batch match the same proteins.
+/// A cache created with `default` keeps every decoded protein, so a new cache should be used for every batch of peptides.
+/// A cache created with `with_capacity` evicts the least recently used protein once it is full, so it can be shared by any number of peptides.
+#[derive(Default)]
+pub struct AnnotationCache {
+    /// The decoded functional annotations of the cached proteins
+    decoded: RwLock<DecodedAnnotations>,
+    /// The maximum number of cached proteins, None if the cache is unbounded
+    capacity: Option<usize>,
+    /// The number of times the annotations of a protein were decoded
+    decode_count: AtomicUsize,
+}
+
+/// The decoded functional annotations of an `AnnotationCache`, together with the order in which they were last used
+#[derive(Default)]
+struct DecodedAnnotations {
+    /// The decoded functional annotations and the moment of last use of every cached protein
+    entries: HashMap<usize, (Arc<str>, u64)>,
+    /// The index of every cached protein, ordered by the moment of last use
+    recency: BTreeMap<u64, usize>,
+    /// Counter that is incremented every time a protein is used
+    clock: u64,
+}
+
+impl DecodedAnnotations {
+    /// Retrieves the decoded functional annotations of a cached protein and marks it as the most recently used protein
+    ///
+    /// # Arguments
+    /// * `protein_index` - The index of the protein in `Proteins`
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded functional annotations of the protein, or None if the protein is not cached
+    fn touch(&mut self, protein_index: usize) -> Option<Arc<str>> {
+        let (annotations, last_used) = self.entries.get_mut(&protein_index)?;
+        self.recency.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.recency.insert(self.clock, protein_index);
+        Some(annotations.clone())
+    }
+
+    /// Adds the decoded functional annotations of a protein as the most recently used protein
+    ///
+    /// # Arguments
+    /// * `protein_index` - The index of the protein in `Proteins`
+    /// * `annotations` - The decoded functional annotations of the protein
+    /// * `capacity` - The maximum number of cached proteins, the least recently used protein is evicted if the cache is full
+    fn insert(&mut self, protein_index: usize, annotations: Arc<str>, capacity: Option<usize>) {
+        if capacity.is_some_and(|capacity| self.entries.len() >= capacity) {
+            if let Some((_, evicted)) = self.recency.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(protein_index, (annotations, self.clock));
+        self.recency.insert(self.clock, protein_index);
+    }
+}
+
+impl AnnotationCache {
+    /// Creates an empty cache that holds the annotations of at most `capacity` proteins
+    /// Once the cache is full, the least recently used protein is evicted to make room for a new one
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of cached proteins, at least 1
+    ///
+    /// # Returns
+    ///
+    /// Returns an empty bounded cache
+    pub fn with_capacity(capacity: usize) -> Self {
+        AnnotationCache {
+            capacity: Some(capacity.max(1)),
+            ..Default::default()
+        }
+    }
+
+    /// Retrieves the decoded functional annotations of a protein, the annotations are only decoded if they are not cached yet
+    ///
+    /// # Arguments
+    /// * `protein_index` - The index of the protein in `Proteins`
+    /// * `protein` - The protein itself
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded functional annotations of the protein
+    pub fn get_or_decode(&self, protein_index: usize, protein: &Protein) -> Arc<str> {
+        // an unbounded cache does not need the order of use, so a hit only needs the read lock
+        if self.capacity.is_none() {
+            if let Some((annotations, _)) = self.decoded.read().unwrap().entries.get(&protein_index) {
+                return annotations.clone();
+            }
+        }
+
+        // check the cache again while holding the write lock, another thread could have decoded the protein in the meantime
+        let mut decoded = self.decoded.write().unwrap();
+        if let Some(annotations) = decoded.touch(protein_index) {
+            return annotations;
+        }
+
+        self.decode_count.fetch_add(1, Ordering::Relaxed);
+        let annotations: Arc<str> = Arc::from(protein.get_functional_annotations());
+        decoded.insert(protein_index, annotations.clone(), self.capacity);
+        annotations
+    }
+
+    /// Returns the number of times the annotations of a protein were decoded
+    pub fn decode_count(&self) -> usize {
+        self.decode_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of proteins that are currently cached
+    pub fn len(&self) -> usize {
+        self.decoded.read().unwrap().entries.len()
+    }
+
+    /// Returns true if no proteins are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 impl FunctionAggregator {
     /// Aggregates the functional annotations of proteins
     ///
@@ -29,20 +192,49 @@ impl FunctionAggregator {
     ///
     /// Returns a JSON string containing the aggregated functional annotations
     pub fn aggregate(&self, proteins: Vec<&Protein>) -> FunctionalAggregation {
+        let annotations = proteins.iter().map(|protein| protein.get_functional_annotations());
+        self.aggregate_decoded(&proteins, annotations)
+    }
+
+    /// Aggregates the functional annotations of proteins, using a cache so the annotations of every protein are decoded at most once
+    ///
+    /// # Arguments
+    /// * `proteins` - A vector of proteins, together with their index in `Proteins`
+    /// * `cache` - The cache with the already decoded functional annotations
+    ///
+    /// # Returns
+    ///
+    /// Returns the aggregated functional annotations, identical to the result of `aggregate`
+    pub fn aggregate_cached(&self, proteins: &[(usize, &Protein)], cache: &AnnotationCache) -> FunctionalAggregation {
+        let annotations = proteins.iter().map(|&(index, protein)| cache.get_or_decode(index, protein));
+        let proteins: Vec<&Protein> = proteins.iter().map(|&(_, protein)| protein).collect();
+        self.aggregate_decoded(&proteins, annotations)
+    }
+
+    /// Aggregates the already decoded functional annotations of proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - A vector of proteins
+    /// * `annotations` - The decoded functional annotations of every protein in `proteins`
+    ///
+    /// # Returns
+    ///
+    /// Returns the aggregated functional annotations
+    fn aggregate_decoded<S: AsRef<str>>(&self, proteins: &[&Protein], annotations: impl Iterator<Item = S>) -> FunctionalAggregation {
         // Keep track of the proteins that have a certain annotation
         let mut proteins_with_ec: HashSet<String> = HashSet::new();
         let mut proteins_with_go: HashSet<String> = HashSet::new();
         let mut proteins_with_ipr: HashSet<String> = HashSet::new();
 
         // Keep track of the counts of the different annotations
-        let mut data: HashMap<String, u32> = HashMap::new();
+        let mut data: BTreeMap<String, u32> = BTreeMap::new();
 
-        for protein in proteins.iter() {
-            for annotation in protein.get_functional_annotations().split(';') {
-                match annotation.chars().next() {
-                    Some('E') => proteins_with_ec.insert(protein.uniprot_id.clone()),
-                    Some('G') => proteins_with_go.insert(protein.uniprot_id.clone()),
-                    Some('I') => proteins_with_ipr.insert(protein.uniprot_id.clone()),
+        for (protein, annotations) in proteins.iter().zip(annotations) {
+            for annotation in annotations.as_ref().split(';') {
+                match annotation_type(annotation) {
+                    Some("EC") => proteins_with_ec.insert(protein.uniprot_id.clone()),
+                    Some("GO") => proteins_with_go.insert(protein.uniprot_id.clone()),
+                    Some("IPR") => proteins_with_ipr.insert(protein.uniprot_id.clone()),
                     _ => false
                 };
 
@@ -50,7 +242,7 @@ impl FunctionAggregator {
             }
         }
 
-        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
         counts.insert("all".to_string(), proteins.len());
         counts.insert("EC".to_string(), proteins_with_ec.len());
         counts.insert("GO".to_string(), proteins_with_go.len());
@@ -61,6 +253,29 @@ impl FunctionAggregator {
         FunctionalAggregation { counts, data }
     }
 
+    /// Aggregates the functional annotations of proteins, keeping the annotations of every type separate
+    ///
+    /// # Arguments
+    /// * `proteins` - A list of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns a HashMap with a key for every type in `ANNOTATION_TYPES`, that maps to how often every annotation of that type was found
+    pub fn aggregate_typed(&self, proteins: &[&Protein]) -> HashMap<&'static str, HashMap<String, u32>> {
+        let mut data: HashMap<&'static str, HashMap<String, u32>> =
+            ANNOTATION_TYPES.iter().map(|&annotation_type| (annotation_type, HashMap::new())).collect();
+
+        for protein in proteins {
+            for annotation in protein.get_functional_annotations().split(';') {
+                if let Some(annotations) = annotation_type(annotation).and_then(|annotation_type| data.get_mut(annotation_type)) {
+                    *annotations.entry(annotation.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+
+        data
+    }
+
 
     /// Aggregates the functional annotations of proteins
     ///
@@ -83,4 +298,207 @@ impl FunctionAggregator {
             )
             .collect::<Vec<Vec<String>>>()
     }
+
+    /// Retrieves the functional annotations of proteins, paired with the protein they belong to
+    ///
+    /// # Arguments
+    /// * `proteins` - A vector of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns a list of tuples with the uniprot id of a protein and all its functional annotations
+    pub fn annotations_by_protein(&self, proteins: &[&Protein]) -> Vec<(String, Vec<String>)> {
+        proteins
+            .iter()
+            .map(|&prot| prot.uniprot_id.clone())
+            .zip(self.get_all_functional_annotations(proteins))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fa_compression::algorithm1::encode;
+
+    use super::*;
+
+    #[test]
+    fn test_annotations_by_protein() {
+        let protein1 = Protein {
+            uniprot_id:             "P12345".to_string(),
+            taxon_id:               1,
+            functional_annotations: encode("GO:0009279;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+        let protein2 = Protein {
+            uniprot_id:             "P54321".to_string(),
+            taxon_id:               2,
+            functional_annotations: encode("IPR:IPR016364"),
+            extra_accessions:       vec![]
+        };
+
+        let function_aggregator = FunctionAggregator {};
+        let annotations = function_aggregator.annotations_by_protein(&[&protein1, &protein2]);
+
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].0, "P12345");
+        assert_eq!(annotations[0].1.len(), 2);
+        assert!(annotations[0].1.contains(&"GO:0009279".to_string()));
+        assert!(annotations[0].1.contains(&"EC:1.1.1.-".to_string()));
+        assert_eq!(annotations[1], ("P54321".to_string(), vec!["IPR:IPR016364".to_string()]));
+    }
+
+    #[test]
+    fn test_aggregate_cached() {
+        let protein1 = Protein {
+            uniprot_id:             "P12345".to_string(),
+            taxon_id:               1,
+            functional_annotations: encode("GO:0009279;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+        let protein2 = Protein {
+            uniprot_id:             "P54321".to_string(),
+            taxon_id:               2,
+            functional_annotations: encode("IPR:IPR016364;GO:0009279"),
+            extra_accessions:       vec![]
+        };
+
+        let function_aggregator = FunctionAggregator {};
+        let cache = AnnotationCache::default();
+
+        // 3 peptides that all match the same 2 proteins
+        for _ in 0..3 {
+            let cached = function_aggregator.aggregate_cached(&[(0, &protein1), (1, &protein2)], &cache);
+            let uncached = function_aggregator.aggregate(vec![&protein1, &protein2]);
+            assert_eq!(cached.counts, uncached.counts);
+            assert_eq!(cached.data, uncached.data);
+        }
+
+        // every protein is only decoded once
+        assert_eq!(cache.decode_count(), 2);
+    }
+
+    #[test]
+    fn test_aggregate_typed() {
+        let protein1 = Protein {
+            uniprot_id:             "P12345".to_string(),
+            taxon_id:               1,
+            functional_annotations: encode("GO:0009279;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+        let protein2 = Protein {
+            uniprot_id:             "P54321".to_string(),
+            taxon_id:               2,
+            functional_annotations: encode("IPR:IPR016364;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+
+        let function_aggregator = FunctionAggregator {};
+        let data = function_aggregator.aggregate_typed(&[&protein1, &protein2]);
+
+        assert_eq!(data.len(), 3);
+        assert_eq!(data["EC"], HashMap::from([("EC:1.1.1.-".to_string(), 2)]));
+        assert_eq!(data["GO"], HashMap::from([("GO:0009279".to_string(), 1)]));
+        assert_eq!(data["IPR"], HashMap::from([("IPR:IPR016364".to_string(), 1)]));
+
+        // the typed data contains exactly the annotations of the flattened data
+        let aggregation = function_aggregator.aggregate(vec![&protein1, &protein2]);
+        let flattened: BTreeMap<String, u32> = data.into_values().flatten().collect();
+        assert_eq!(flattened, aggregation.data);
+
+        assert!(function_aggregator.aggregate_typed(&[]).values().all(HashMap::is_empty));
+    }
+
+    #[test]
+    fn test_bounded_annotation_cache() {
+        let proteins: Vec<Protein> = ["GO:0009279;EC:1.1.1.-", "IPR:IPR016364;GO:0009279", "EC:2.7.11.1", "GO:0005737"]
+            .iter()
+            .enumerate()
+            .map(|(i, annotations)| Protein {
+                uniprot_id:             format!("P{}", i),
+                taxon_id:               1,
+                functional_annotations: encode(annotations),
+                extra_accessions:       vec![]
+            })
+            .collect();
+
+        let cache = AnnotationCache::with_capacity(2);
+
+        // the cached decode equals the fresh decode
+        for (i, protein) in proteins.iter().enumerate() {
+            assert_eq!(&*cache.get_or_decode(i, protein), protein.get_functional_annotations());
+            assert_eq!(&*cache.get_or_decode(i, protein), protein.get_functional_annotations());
+        }
+        assert_eq!(cache.decode_count(), 4);
+        assert_eq!(cache.len(), 2);
+
+        // 2 and 3 are cached, using 2 makes 3 the least recently used protein, which is evicted to make room for 0
+        cache.get_or_decode(2, &proteins[2]);
+        cache.get_or_decode(0, &proteins[0]);
+        assert_eq!(cache.decode_count(), 5);
+        cache.get_or_decode(2, &proteins[2]);
+        assert_eq!(cache.decode_count(), 5);
+        cache.get_or_decode(3, &proteins[3]);
+        assert_eq!(cache.decode_count(), 6);
+
+        // a batch of 100 peptides that match the same 2 proteins only decodes them once
+        let cache = AnnotationCache::with_capacity(2);
+        for _ in 0..100 {
+            for (i, protein) in proteins.iter().enumerate().take(2) {
+                assert_eq!(&*cache.get_or_decode(i, protein), protein.get_functional_annotations());
+            }
+        }
+        assert_eq!(cache.decode_count(), 2);
+    }
+
+    #[test]
+    fn test_sorted_by_count() {
+        let aggregation = FunctionalAggregation {
+            counts: BTreeMap::new(),
+            data:   BTreeMap::from([
+                ("IPR:IPR016364".to_string(), 2),
+                ("GO:0009279".to_string(), 5),
+                ("EC:1.1.1.-".to_string(), 2),
+                ("GO:0005737".to_string(), 1),
+                ("EC:2.7.11.1".to_string(), 3)
+            ])
+        };
+
+        assert_eq!(
+            aggregation.sorted_by_count(),
+            vec![
+                ("GO:0009279".to_string(), 5),
+                ("EC:2.7.11.1".to_string(), 3),
+                ("EC:1.1.1.-".to_string(), 2),
+                ("IPR:IPR016364".to_string(), 2),
+                ("GO:0005737".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deterministic_serialization() {
+        let protein1 = Protein {
+            uniprot_id:             "P0".to_string(),
+            taxon_id:               1,
+            functional_annotations: encode("GO:0009279;IPR:IPR016364;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+        let protein2 = Protein {
+            uniprot_id:             "P1".to_string(),
+            taxon_id:               1,
+            functional_annotations: encode("EC:2.7.11.1;GO:0005737;EC:1.1.1.-"),
+            extra_accessions:       vec![]
+        };
+
+        let function_aggregator = FunctionAggregator {};
+        let json1 = serde_json::to_string(&function_aggregator.aggregate(vec![&protein1, &protein2])).unwrap();
+        let json2 = serde_json::to_string(&function_aggregator.aggregate(vec![&protein2, &protein1])).unwrap();
+
+        assert_eq!(json1, json2);
+        assert_eq!(
+            json1,
+            r#"{"counts":{"EC":2,"GO":2,"IPR":1,"all":2},"data":{"EC:1.1.1.-":2,"EC:2.7.11.1":1,"GO:0005737":1,"GO:0009279":1,"IPR:IPR016364":1}}"#
+        );
+    }
 }
@@ -3,6 +3,7 @@
 
 #![warn(missing_docs)]
 
+pub mod alphabet;
 pub mod functionality;
 pub mod proteins;
 pub mod taxonomy;
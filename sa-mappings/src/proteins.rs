@@ -2,25 +2,31 @@
 //! and collections of proteins, respectively.
 
 use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     error::Error,
+    fmt::{Display, Formatter},
     fs::File,
-    io::BufReader,
-    ops::Index,
+    hash::{Hash, Hasher},
+    io::{BufReader, BufWriter, Write},
+    ops::{Deref, Index, Range},
     str::from_utf8
 };
 
 use bytelines::ByteLines;
-use fa_compression::algorithm1::decode;
+use fa_compression::algorithm1::{contains, decode};
+use memmap::Mmap;
 use umgap::taxon::TaxonId;
 
+use crate::alphabet::AminoAcidAlphabet;
 use crate::taxonomy::TaxonAggregator;
 
 /// The separation character used in the input string
-pub static SEPARATION_CHARACTER: u8 = b'-';
+/// This is the single separation character of the project, the suffix tree uses it as well
+pub static SEPARATION_CHARACTER: u8 = AminoAcidAlphabet::SEPARATION_CHARACTER;
 
 /// The termination character used in the input string
 /// This character should be smaller than the separation character
-pub static TERMINATION_CHARACTER: u8 = b'$';
+pub static TERMINATION_CHARACTER: u8 = AminoAcidAlphabet::TERMINATION_CHARACTER;
 
 /// A struct that represents a protein and its linked information
 pub struct Protein {
@@ -31,132 +37,669 @@ pub struct Protein {
     pub taxon_id: TaxonId,
 
     /// The encoded functional annotations of the protein
-    pub functional_annotations: Vec<u8>
+    pub functional_annotations: Vec<u8>,
+
+    /// The uniprot id and taxon id of every other protein with an identical sequence that was collapsed into this protein
+    /// This is only filled in when the proteins are deduplicated, the functional annotations of those proteins are not kept
+    pub extra_accessions: Vec<(String, TaxonId)>
+}
+
+/// The uniprot id, taxon id, sequence and encoded functional annotations of a line in the database file
+type DatabaseLine<'a> = (&'a str, TaxonId, &'a str, &'a [u8]);
+
+/// The layout of the lines of a database file: the delimiter between the fields and the (zero-based) column of every field
+/// The delimiter should not occur inside a field, this includes the encoded functional annotations
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatabaseSchema {
+    /// The byte that separates the fields of a line
+    pub delimiter: u8,
+    /// The column of the uniprot id
+    pub uniprot_col: usize,
+    /// The column of the taxon id
+    pub taxon_col: usize,
+    /// The column of the protein sequence
+    pub sequence_col: usize,
+    /// The column of the encoded functional annotations
+    pub annotation_col: usize
+}
+
+impl Default for DatabaseSchema {
+    /// The default schema is the tab separated uniprot id, taxon id, sequence and functional annotations, in that order
+    fn default() -> Self {
+        DatabaseSchema { delimiter: b'\t', uniprot_col: 0, taxon_col: 1, sequence_col: 2, annotation_col: 3 }
+    }
+}
+
+impl DatabaseSchema {
+    /// Creates a new `DatabaseSchema` from the commandline options
+    ///
+    /// # Arguments
+    /// * `separator` - The character that separates the fields of a line
+    /// * `columns` - The columns of the uniprot id, taxon id, sequence and functional annotations, in that order
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `DatabaseSchema`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the separator is not a single-byte (ASCII) character other than a newline,
+    /// or if there are not exactly 4 distinct columns
+    pub fn try_new(separator: char, columns: &[usize]) -> Result<Self, Box<dyn Error>> {
+        if !separator.is_ascii() || separator == '\n' {
+            return Err(format!("The separator {:?} is not an ASCII character other than a newline", separator).into());
+        }
+        let &[uniprot_col, taxon_col, sequence_col, annotation_col] = columns else {
+            return Err(format!("Expected the columns of the uniprot id, taxon id, sequence and functional annotations, but got {} columns", columns.len()).into());
+        };
+        if columns.iter().collect::<HashSet<_>>().len() != columns.len() {
+            return Err(format!("The columns {:?} are not distinct", columns).into());
+        }
+
+        Ok(DatabaseSchema { delimiter: separator as u8, uniprot_col, taxon_col, sequence_col, annotation_col })
+    }
+}
+
+/// The options that determine how a database file is parsed and which of its proteins are kept
+/// The default options parse the default schema, keep every protein and fail on the first malformed line
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions<'a> {
+    /// If set, only the proteins of these taxa are kept, e.g. to build an organism-specific index
+    pub keep_taxa: Option<&'a HashSet<TaxonId>>,
+    /// If set, the proteins with a non-empty value in this (zero-based) column are dropped as fragments
+    pub fragment_column: Option<usize>,
+    /// The delimiter and the columns of the fields in the database file
+    pub schema: DatabaseSchema,
+    /// If set, malformed lines are skipped as long as their ratio does not exceed this value (between 0 and 1),
+    /// otherwise a malformed line is an error
+    pub max_skip_ratio: Option<f64>,
+    /// If true, proteins with the same sequence as an earlier protein are collapsed into that protein: only the first protein
+    /// of every sequence is kept, the uniprot ids and taxon ids of the other proteins are stored in its `extra_accessions`
+    pub deduplicate: bool
+}
+
+/// The error returned when the ratio of malformed lines in a database file exceeds the allowed ratio
+#[derive(Debug)]
+pub struct MalformedLinesError {
+    /// The number of malformed lines that were skipped
+    pub skipped_lines: usize,
+    /// The total number of lines in the database file
+    pub total_lines: usize,
+    /// The maximum ratio of malformed lines that was allowed
+    pub max_skip_ratio: f64
+}
+
+impl Display for MalformedLinesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of the {} lines in the database file are malformed, which exceeds the allowed ratio of {}",
+            self.skipped_lines, self.total_lines, self.max_skip_ratio
+        )
+    }
+}
+
+impl Error for MalformedLinesError {}
+
+/// Checks that a text consists of `protein_count` proteins concatenated with the `SEPARATION_CHARACTER` and ended with the
+/// `TERMINATION_CHARACTER`, e.g. to detect a text that was concatenated with another separation character
+///
+/// # Arguments
+/// * `text` - The concatenated protein sequences
+/// * `protein_count` - The number of proteins that the text should contain
+///
+/// # Returns
+///
+/// Returns () if the text is valid
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the text does not end with the termination character, or if the number of
+/// separation characters does not match the number of proteins
+pub fn validate_text(text: &[u8], protein_count: usize) -> Result<(), Box<dyn Error>> {
+    if text.last() != Some(&TERMINATION_CHARACTER) {
+        return Err("the text does not end with the termination character".into());
+    }
+
+    let separator_count = text.iter().filter(|&&c| c == SEPARATION_CHARACTER).count();
+    if separator_count + 1 != protein_count {
+        return Err(format!(
+            "the text contains {} proteins separated by {:?}, but {} proteins are expected",
+            separator_count + 1,
+            SEPARATION_CHARACTER as char,
+            protein_count
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// A report of the lines of a database file that did not end up in the proteins
+#[derive(Debug, Default, PartialEq)]
+pub struct DatabaseFileReport {
+    /// The number of malformed lines that were skipped
+    pub skipped_lines: usize,
+    /// The number of proteins that were dropped because their taxon is not part of the taxonomy
+    pub missing_taxon_proteins: usize,
+    /// The taxon ids that are referenced by the database file but are not part of the taxonomy
+    pub missing_taxa: HashSet<TaxonId>,
+    /// The number of proteins that were dropped because they are flagged as a fragment
+    pub fragment_proteins: usize
+}
+
+/// Checks if the number of skipped malformed lines of a database file is acceptable
+///
+/// # Arguments
+/// * `skipped_lines` - The number of malformed lines that were skipped
+/// * `total_lines` - The total number of lines in the database file
+/// * `max_skip_ratio` - The maximum allowed ratio of malformed lines, between 0 and 1
+///
+/// # Returns
+///
+/// Returns () if the ratio of skipped lines does not exceed `max_skip_ratio`
+///
+/// # Errors
+///
+/// Returns a `MalformedLinesError` if the ratio of skipped lines exceeds `max_skip_ratio`
+pub fn check_skip_ratio(skipped_lines: usize, total_lines: usize, max_skip_ratio: f64) -> Result<(), MalformedLinesError> {
+    if skipped_lines as f64 > max_skip_ratio * total_lines as f64 {
+        return Err(MalformedLinesError { skipped_lines, total_lines, max_skip_ratio });
+    }
+
+    Ok(())
+}
+
+/// A struct that maps the uniprot id of every protein to its position in a `Proteins` collection
+pub struct AccessionIndex {
+    /// For every uniprot id, the index of the protein and the range of its sequence in the input string
+    positions: HashMap<String, (usize, Range<usize>)>
+}
+
+/// The concatenated sequences of all proteins, either kept in memory or memory-mapped from a file
+#[derive(Debug)]
+pub enum ProteinText {
+    /// The text is completely loaded in memory
+    InMemory(Vec<u8>),
+    /// The text is memory-mapped from a file, so it is only loaded in memory by the OS when needed
+    /// The mapping is read-only and shared, so the pages are never copied on write and the page cache of the file is shared
+    /// by every process that maps the same file
+    Mapped(Mmap)
 }
 
 /// A struct that represents a collection of proteins
 pub struct Proteins {
     /// The input string containing all proteins
-    pub input_string: Vec<u8>,
+    pub input_string: ProteinText,
 
     /// The proteins in the input string
     pub proteins: Vec<Protein>
 }
 
+impl ProteinText {
+    /// Returns true if the text is memory-mapped from a file
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, ProteinText::Mapped(_))
+    }
+
+    /// Returns a mutable reference to the text, a memory-mapped text is copied into memory first
+    /// The mapped file itself is never modified, the copy is private to this process
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let ProteinText::Mapped(mmap) = self {
+            *self = ProteinText::InMemory(mmap.to_vec());
+        }
+
+        match self {
+            ProteinText::InMemory(text) => text,
+            ProteinText::Mapped(_) => unreachable!()
+        }
+    }
+}
+
+impl Deref for ProteinText {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ProteinText::InMemory(text) => text,
+            ProteinText::Mapped(mmap) => mmap
+        }
+    }
+}
+
+impl From<Vec<u8>> for ProteinText {
+    fn from(text: Vec<u8>) -> Self {
+        ProteinText::InMemory(text)
+    }
+}
+
+impl PartialEq<&[u8]> for ProteinText {
+    fn eq(&self, other: &&[u8]) -> bool {
+        **self == **other
+    }
+}
+
 impl Protein {
     /// Returns the decoded functional annotations of the protein
     pub fn get_functional_annotations(&self) -> String {
         decode(&self.functional_annotations)
     }
+
+    /// Checks if the protein has a functional annotation, without decoding all functional annotations
+    ///
+    /// # Arguments
+    /// * `annotation` - The annotation including its prefix, e.g. `GO:0009279`
+    ///
+    /// # Returns
+    ///
+    /// Returns true if `annotation` is one of the functional annotations of the protein
+    pub fn has_functional_annotation(&self, annotation: &str) -> bool {
+        contains(&self.functional_annotations, annotation)
+    }
+
+    /// Returns an iterator over the uniprot ids and taxon ids of the protein and the proteins collapsed into it
+    pub fn accessions(&self) -> impl Iterator<Item = (&str, TaxonId)> {
+        std::iter::once((self.uniprot_id.as_str(), self.taxon_id))
+            .chain(self.extra_accessions.iter().map(|(uniprot_id, taxon_id)| (uniprot_id.as_str(), *taxon_id)))
+    }
+
+    /// Returns an iterator over the taxon ids of the protein and the proteins collapsed into it
+    pub fn taxa(&self) -> impl Iterator<Item = TaxonId> + '_ {
+        self.accessions().map(|(_, taxon_id)| taxon_id)
+    }
 }
 
 impl Proteins {
-    /// Creates a new `Proteins` struct from a database file and a `TaxonAggregator`
+    /// Creates a new `Proteins` struct from a database file and a `TaxonAggregator`, together with a report of the lines that were not kept
+    /// Proteins whose taxon is not part of the taxonomy are dropped, the report allows the caller to warn or abort when too many are dropped
     ///
     /// # Arguments
     /// * `file` - The path to the database file
     /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `options` - The layout of the database file and the proteins that are kept, see `LoadOptions`
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the `Proteins` struct
+    /// Returns a `Result` containing the `Proteins` struct and a `DatabaseFileReport` with the number of skipped malformed lines,
+    /// the number of proteins that were dropped because of a missing taxon, the set of missing taxon ids and the number of dropped fragments
     ///
     /// # Errors
     ///
-    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file
+    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file or if a line of the database file is malformed,
+    /// or a `MalformedLinesError` if malformed lines are skipped and their ratio exceeds `options.max_skip_ratio`
     pub fn try_from_database_file(
         file: &str,
-        taxon_aggregator: &TaxonAggregator
-    ) -> Result<Self, Box<dyn Error>> {
-        let mut input_string: String = String::new();
+        taxon_aggregator: &TaxonAggregator,
+        options: &LoadOptions
+    ) -> Result<(Self, DatabaseFileReport), Box<dyn Error>> {
         let mut proteins: Vec<Protein> = Vec::new();
+        let mut duplicates: Vec<(usize, String, TaxonId)> = Vec::new();
+
+        let (input_string, report) = Self::read_database_file(
+            file,
+            taxon_aggregator,
+            options,
+            |uniprot_id, taxon_id, functional_annotations| {
+                proteins.push(Protein {
+                    uniprot_id: uniprot_id.to_string(),
+                    taxon_id,
+                    functional_annotations: functional_annotations.to_vec(),
+                    extra_accessions: vec![]
+                })
+            },
+            |index, uniprot_id, taxon_id| duplicates.push((index, uniprot_id.to_string(), taxon_id))
+        )?;
+
+        for (index, uniprot_id, taxon_id) in duplicates {
+            proteins[index].extra_accessions.push((uniprot_id, taxon_id));
+        }
+
+        proteins.shrink_to_fit();
+        Ok((Self { input_string: input_string.into(), proteins }, report))
+    }
+
+    /// Reads a database file and concatenates the sequences of the proteins that are kept
+    ///
+    /// # Arguments
+    /// * `file` - The path to the database file
+    /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `options` - The layout of the database file and the proteins that are kept, with `deduplicate` the sequence of a protein
+    ///   is only added once and later proteins with the same sequence are passed to `add_duplicate`
+    /// * `add_protein` - Called with the uniprot id, taxon id and encoded functional annotations of every protein that is kept
+    /// * `add_duplicate` - Called with the index of the earlier protein, the uniprot id and the taxon id of every collapsed protein
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the concatenated sequences and a `DatabaseFileReport` of the lines that were not kept
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file or if there are too many malformed lines
+    fn read_database_file(
+        file: &str,
+        taxon_aggregator: &TaxonAggregator,
+        options: &LoadOptions,
+        mut add_protein: impl FnMut(&str, TaxonId, &[u8]),
+        mut add_duplicate: impl FnMut(usize, &str, TaxonId)
+    ) -> Result<(Vec<u8>, DatabaseFileReport), Box<dyn Error>> {
+        let mut input_string: String = String::new();
+        let mut total_lines = 0;
+        let mut report = DatabaseFileReport::default();
+
+        // only the hashes of the sequences are stored, the candidates with the same hash are compared with the input string
+        let mut proteins_by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut sequence_ranges: Vec<Range<usize>> = Vec::new();
 
         let file = File::open(file)?;
-        
+
         // Read the lines as bytes, since the input string is not guaranteed to be utf8
         // because of the encoded functional annotations
         let mut lines = ByteLines::new(BufReader::new(file));
 
         while let Some(Ok(line)) = lines.next() {
-            let mut fields = line.split(|b| *b == b'\t');
+            total_lines += 1;
 
-            // uniprot_id, taxon_id and sequence should always contain valid utf8
-            let uniprot_id = from_utf8(fields.next().unwrap())?;
-            let taxon_id = from_utf8(fields.next().unwrap())?.parse::<TaxonId>()?;
-            let sequence = from_utf8(fields.next().unwrap())?;
-            let functional_annotations: Vec<u8> = fields.next().unwrap().to_vec();
+            let (uniprot_id, taxon_id, sequence, functional_annotations) = match Self::parse_database_line(line, &options.schema) {
+                Ok(fields) => fields,
+                Err(_) if options.max_skip_ratio.is_some() => {
+                    report.skipped_lines += 1;
+                    continue;
+                }
+                Err(e) => return Err(format!("Line {} of the database file is malformed: {}", total_lines, e).into())
+            };
 
             if !taxon_aggregator.taxon_exists(taxon_id) {
+                report.missing_taxon_proteins += 1;
+                report.missing_taxa.insert(taxon_id);
+                continue;
+            }
+
+            if options.keep_taxa.is_some_and(|keep_taxa| !keep_taxa.contains(&taxon_id)) {
                 continue;
             }
 
-            input_string.push_str(&sequence.to_uppercase());
+            if options.fragment_column.is_some_and(|column| Self::is_fragment(line, options.schema.delimiter, column)) {
+                report.fragment_proteins += 1;
+                continue;
+            }
+
+            let sequence = sequence.to_uppercase();
+
+            if options.deduplicate {
+                let mut hasher = DefaultHasher::new();
+                sequence.hash(&mut hasher);
+
+                let candidates = proteins_by_hash.entry(hasher.finish()).or_default();
+                if let Some(&index) = candidates.iter().find(|&&index| input_string[sequence_ranges[index].clone()] == sequence) {
+                    add_duplicate(index, uniprot_id, taxon_id);
+                    continue;
+                }
+
+                candidates.push(sequence_ranges.len());
+                sequence_ranges.push(input_string.len()..input_string.len() + sequence.len());
+            }
+
+            input_string.push_str(&sequence);
             input_string.push(SEPARATION_CHARACTER.into());
 
-            proteins.push(Protein {
-                uniprot_id: uniprot_id.to_string(),
-                taxon_id,
-                functional_annotations
-            });
+            add_protein(uniprot_id, taxon_id, functional_annotations);
+        }
 
+        if let Some(max_skip_ratio) = options.max_skip_ratio {
+            check_skip_ratio(report.skipped_lines, total_lines, max_skip_ratio)?;
         }
 
         input_string.pop();
         input_string.push(TERMINATION_CHARACTER.into());
         input_string.shrink_to_fit();
+        Ok((input_string.into_bytes(), report))
+    }
+
+    /// Parses a line of the database file into its fields
+    /// Any columns that are not part of the schema are ignored, so the database can carry extra metadata
+    ///
+    /// # Arguments
+    /// * `line` - A line of the database file
+    /// * `schema` - The delimiter and the columns of the fields in the line
+    ///
+    /// # Returns
+    ///
+    /// Returns the uniprot id, taxon id, sequence and encoded functional annotations of the protein
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if a field is missing or if the uniprot id, taxon id or sequence can not be parsed
+    fn parse_database_line<'a>(line: &'a [u8], schema: &DatabaseSchema) -> Result<DatabaseLine<'a>, Box<dyn Error>> {
+        let columns = [schema.uniprot_col, schema.taxon_col, schema.sequence_col, schema.annotation_col];
+        let last_column = columns.into_iter().max().unwrap_or_default();
+
+        let mut fields: [Option<&[u8]>; 4] = [None; 4];
+        for (column, field) in line.split(|b| *b == schema.delimiter).take(last_column + 1).enumerate() {
+            if let Some(index) = columns.iter().position(|&schema_column| schema_column == column) {
+                fields[index] = Some(field);
+            }
+        }
+        let field = |index: usize, name: &str| fields[index].ok_or_else(|| format!("the {} is missing", name));
+
+        // uniprot_id, taxon_id and sequence should always contain valid utf8
+        let uniprot_id = from_utf8(field(0, "uniprot id")?)?;
+        let taxon_id = from_utf8(field(1, "taxon id")?)?.parse::<TaxonId>()?;
+        let sequence = from_utf8(field(2, "sequence")?)?;
+        let functional_annotations = field(3, "functional annotations")?;
+
+        Ok((uniprot_id, taxon_id, sequence, functional_annotations))
+    }
+
+    /// Checks if a line of the database file is flagged as a fragment
+    /// UniProt leaves the fragment column empty for complete sequences, so any other value flags a fragment
+    ///
+    /// # Arguments
+    /// * `line` - A line of the database file
+    /// * `delimiter` - The byte that separates the fields of the line
+    /// * `fragment_column` - The (zero-based) column of the line that contains the fragment flag
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the fragment column of the line is not empty, a line without the column is not a fragment
+    fn is_fragment(line: &[u8], delimiter: u8, fragment_column: usize) -> bool {
+        line.split(|b| *b == delimiter)
+            .nth(fragment_column)
+            .is_some_and(|flag| !flag.trim_ascii().is_empty())
+    }
+
+    /// Creates a new `Proteins` struct from the files written by `write_mmap_files`
+    /// The concatenated text is memory-mapped, only the uniprot ids, taxon ids and functional annotations are loaded in memory
+    ///
+    /// # Arguments
+    /// * `text_path` - The path to the file with the concatenated protein sequences
+    /// * `metadata_path` - The path to the file with the uniprot id, taxon id and functional annotations of every protein
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `Proteins` struct
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an error occurred while mapping the text or reading the metadata file,
+    /// or if the text is not separated in the same number of proteins as the metadata file (see `validate_text`)
+    pub fn try_from_mmap(text_path: &str, metadata_path: &str) -> Result<Self, Box<dyn Error>> {
+        let text_file = File::open(text_path)?;
+        // the text file is written once when building the index and is read-only afterwards,
+        // the mapping is only invalid if the file is modified while it is mapped
+        // `Mmap::map` creates a read-only shared mapping: no page is ever dirtied, so all processes that map the file
+        // use the same pages of the OS page cache instead of a private copy of the text
+        let input_string = unsafe { Mmap::map(&text_file)? };
+
+        let mut proteins: Vec<Protein> = Vec::new();
+        let mut lines = ByteLines::new(BufReader::new(File::open(metadata_path)?));
+        while let Some(Ok(line)) = lines.next() {
+            // the functional annotations are the last field, since they are not guaranteed to be utf8
+            let mut fields = line.splitn(3, |b| *b == b'\t');
+
+            let mut uniprot_ids = from_utf8(fields.next().unwrap())?.split(',');
+            let uniprot_id = uniprot_ids.next().unwrap_or_default();
+            let extra_accessions = uniprot_ids
+                .map(|accession| {
+                    let (uniprot_id, taxon_id) =
+                        accession.split_once(':').ok_or("Missing taxon id of a collapsed protein in the metadata file")?;
+                    Ok((uniprot_id.to_string(), taxon_id.parse::<TaxonId>()?))
+                })
+                .collect::<Result<Vec<(String, TaxonId)>, Box<dyn Error>>>()?;
+            let taxon_id = from_utf8(fields.next().ok_or("Missing taxon id in the metadata file")?)?.parse::<TaxonId>()?;
+            let functional_annotations: Vec<u8> = fields.next().unwrap_or_default().to_vec();
+
+            proteins.push(Protein {
+                uniprot_id: uniprot_id.to_string(),
+                taxon_id,
+                functional_annotations,
+                extra_accessions
+            });
+        }
+
+        validate_text(&input_string, proteins.len()).map_err(|e| format!("The text in {} is invalid: {}", text_path, e))?;
+
         proteins.shrink_to_fit();
         Ok(Self {
-            input_string: input_string.into_bytes(),
+            input_string: ProteinText::Mapped(input_string),
             proteins
         })
     }
 
+    /// Writes the proteins to the 2 files that can be memory-mapped with `try_from_mmap`
+    /// This is done once when building the index: the text file contains the concatenated protein sequences exactly like
+    /// `input_string`, the metadata file contains a line `uniprot_id\ttaxon_id\tfunctional_annotations` for every protein,
+    /// where the collapsed proteins of a deduplicated protein are appended to its uniprot id as `,uniprot_id:taxon_id`
+    ///
+    /// # Arguments
+    /// * `text_path` - The path to the file where the concatenated protein sequences are written to
+    /// * `metadata_path` - The path to the file where the uniprot id, taxon id and functional annotations of every protein are written to
+    ///
+    /// # Returns
+    ///
+    /// Returns () if writing both files succeeded
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an error occurred while writing the files
+    pub fn write_mmap_files(&self, text_path: &str, metadata_path: &str) -> Result<(), Box<dyn Error>> {
+        let mut text_file = BufWriter::new(File::create(text_path)?);
+        text_file.write_all(&self.input_string)?;
+        text_file.flush()?;
+
+        let mut metadata_file = BufWriter::new(File::create(metadata_path)?);
+        for protein in self.iter() {
+            // the collapsed proteins are appended to the uniprot id as `,uniprot_id:taxon_id`
+            let mut uniprot_ids = protein.uniprot_id.clone();
+            for (uniprot_id, taxon_id) in &protein.extra_accessions {
+                uniprot_ids.push_str(&format!(",{}:{}", uniprot_id, taxon_id));
+            }
+            metadata_file.write_all(format!("{}\t{}\t", uniprot_ids, protein.taxon_id).as_bytes())?;
+            metadata_file.write_all(&protein.functional_annotations)?;
+            metadata_file.write_all(b"\n")?;
+        }
+        metadata_file.flush()?;
+
+        Ok(())
+    }
+
     /// Creates a `vec<u8>` which represents all the proteins concatenated from the database file
     ///
     /// # Arguments
-    /// * `file` - The path to the database file
+    /// * `database_file` - The path to the database file
     /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `options` - The layout of the database file and the proteins that are kept, this should be the same as used for `try_from_database_file`
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the `Vec<u8>`
+    /// Returns a `Result` containing the `Vec<u8>` and a `DatabaseFileReport` of the lines that were not kept
     ///
     /// # Errors
     ///
-    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file
-    pub fn try_from_database_file_without_annotations(database_file: &str, taxon_aggregator: &TaxonAggregator) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut input_string: String = String::new();
+    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file or if a line of the database file is malformed,
+    /// or a `MalformedLinesError` if malformed lines are skipped and their ratio exceeds `options.max_skip_ratio`
+    pub fn try_from_database_file_without_annotations(
+        database_file: &str,
+        taxon_aggregator: &TaxonAggregator,
+        options: &LoadOptions
+    ) -> Result<(Vec<u8>, DatabaseFileReport), Box<dyn Error>> {
+        // only the sequences are needed, the other parts of the proteins are ignored
+        Self::read_database_file(
+            database_file,
+            taxon_aggregator,
+            options,
+            |_, _, _| {},
+            |_, _, _| {}
+        )
+    }
 
-        let file = File::open(database_file)?;
+    /// Returns the number of proteins
+    pub fn len(&self) -> usize {
+        self.proteins.len()
+    }
 
-        // Read the lines as bytes, since the input string is not guaranteed to be utf8
-        // because of the encoded functional annotations
-        let mut lines = ByteLines::new(BufReader::new(file));
+    /// Returns true if there are no proteins
+    pub fn is_empty(&self) -> bool {
+        self.proteins.is_empty()
+    }
 
-        while let Some(Ok(line)) = lines.next() {
-            let mut fields = line.split(|b| *b == b'\t');
+    /// Returns an iterator over the proteins
+    pub fn iter(&self) -> impl Iterator<Item = &Protein> {
+        self.proteins.iter()
+    }
 
-            // only get the taxon id and sequence from each line, we don't need the other parts
-            fields.next();
-            let taxon_id = from_utf8(fields.next().unwrap())?.parse::<TaxonId>()?;
-            let sequence = from_utf8(fields.next().unwrap())?;
-            fields.next();
+    /// Counts the proteins per taxon, the proteins collapsed by deduplication are counted as well
+    ///
+    /// # Returns
+    ///
+    /// Returns a HashMap with every taxon that occurs in the proteins, together with the number of proteins of that taxon
+    pub fn taxon_histogram(&self) -> HashMap<TaxonId, usize> {
+        let mut histogram: HashMap<TaxonId, usize> = HashMap::new();
+        for taxon_id in self.iter().flat_map(Protein::taxa) {
+            *histogram.entry(taxon_id).or_insert(0) += 1;
+        }
+        histogram
+    }
+}
 
-            if !taxon_aggregator.taxon_exists(taxon_id) {
-                continue;
-            }
+impl AccessionIndex {
+    /// Creates a new `AccessionIndex` for a collection of proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - The proteins that are indexed
+    ///
+    /// # Returns
+    ///
+    /// Returns the `AccessionIndex` that contains every protein of `proteins`
+    pub fn new(proteins: &Proteins) -> Self {
+        let mut positions = HashMap::with_capacity(proteins.len());
 
-            input_string.push_str(&sequence.to_uppercase());
-            input_string.push(SEPARATION_CHARACTER.into());
+        let mut start = 0;
+        for (index, protein) in proteins.iter().enumerate() {
+            let length = proteins.input_string[start..]
+                .iter()
+                .position(|&c| c == SEPARATION_CHARACTER || c == TERMINATION_CHARACTER)
+                .unwrap_or(proteins.input_string.len() - start);
+            for (uniprot_id, _) in protein.accessions() {
+                positions.insert(uniprot_id.to_string(), (index, start..start + length));
+            }
+            start += length + 1;
         }
 
-        input_string.pop();
-        input_string.push(TERMINATION_CHARACTER.into());
+        Self { positions }
+    }
 
-        input_string.shrink_to_fit();
-        Ok(input_string.into_bytes())
+    /// Looks up a protein by its uniprot id
+    ///
+    /// # Arguments
+    /// * `uniprot_id` - The uniprot id of the protein
+    ///
+    /// # Returns
+    ///
+    /// Returns the index of the protein and the range of its sequence in the input string,
+    /// or None if there is no protein with this uniprot id
+    pub fn get(&self, uniprot_id: &str) -> Option<(usize, Range<usize>)> {
+        self.positions.get(uniprot_id).cloned()
     }
-    
 }
 
 impl Index<usize> for Proteins {
@@ -236,7 +779,8 @@ mod tests {
         let protein = Protein {
             uniprot_id:             "P12345".to_string(),
             taxon_id:               1,
-            functional_annotations: vec![0xD1, 0x11]
+            functional_annotations: vec![0xD1, 0x11],
+            extra_accessions: vec![]
         };
 
         assert_eq!(protein.uniprot_id, "P12345");
@@ -249,17 +793,20 @@ mod tests {
         let proteins = Proteins {
             input_string: "MLPGLALLLLAAWTARALEV-PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG"
                 .as_bytes()
-                .to_vec(),
+                .to_vec()
+                .into(),
             proteins:     vec![
                 Protein {
                     uniprot_id:             "P12345".to_string(),
                     taxon_id:               1,
-                    functional_annotations: vec![0xD1, 0x11]
+                    functional_annotations: vec![0xD1, 0x11],
+                    extra_accessions: vec![]
                 },
                 Protein {
                     uniprot_id:             "P54321".to_string(),
                     taxon_id:               2,
-                    functional_annotations: vec![0xD1, 0x11]
+                    functional_annotations: vec![0xD1, 0x11],
+                    extra_accessions: vec![]
                 },
             ]
         };
@@ -277,6 +824,151 @@ mod tests {
         assert_eq!(proteins.proteins[1].functional_annotations, vec![0xD1, 0x11]);
     }
 
+    #[test]
+    fn test_len_and_iter() {
+        let mut proteins = Proteins {
+            input_string: "MLPGLALLLL-PTDGNAGLLAE$".as_bytes().to_vec().into(),
+            proteins:     vec![
+                Protein {
+                    uniprot_id:             "P12345".to_string(),
+                    taxon_id:               1,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![]
+                },
+                Protein {
+                    uniprot_id:             "P54321".to_string(),
+                    taxon_id:               2,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![]
+                },
+            ]
+        };
+
+        assert_eq!(proteins.len(), 2);
+        assert!(!proteins.is_empty());
+        let ids: Vec<&str> = proteins.iter().map(|protein| protein.uniprot_id.as_str()).collect();
+        assert_eq!(ids, vec!["P12345", "P54321"]);
+
+        proteins.proteins.clear();
+        assert!(proteins.is_empty());
+        assert_eq!(proteins.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_accession_index() {
+        let proteins = Proteins {
+            input_string: "MLPGLALLLL-PTDGNAGLLAE$".as_bytes().to_vec().into(),
+            proteins:     vec![
+                Protein {
+                    uniprot_id:             "P12345".to_string(),
+                    taxon_id:               1,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![]
+                },
+                Protein {
+                    uniprot_id:             "P54321".to_string(),
+                    taxon_id:               2,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![]
+                },
+            ]
+        };
+
+        let accession_index = AccessionIndex::new(&proteins);
+
+        let (index, range) = accession_index.get("P54321").unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(&proteins.input_string[range], "PTDGNAGLLAE".as_bytes());
+        assert_eq!(accession_index.get("P12345"), Some((0, 0..10)));
+        assert!(accession_index.get("Q00000").is_none());
+    }
+
+    #[test]
+    fn test_mmap_round_trip() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_mmap_round_trip").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let text_file = tmp_dir.path().join("text.bin");
+        let metadata_file = tmp_dir.path().join("metadata.tsv");
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let (proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default())
+                .unwrap();
+
+        proteins.write_mmap_files(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).unwrap();
+        let mapped_proteins =
+            Proteins::try_from_mmap(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).unwrap();
+
+        assert!(matches!(mapped_proteins.input_string, ProteinText::Mapped(_)));
+        assert_eq!(mapped_proteins.input_string, &proteins.input_string[..]);
+        assert_eq!(mapped_proteins.len(), proteins.len());
+        for (mapped_protein, protein) in mapped_proteins.iter().zip(proteins.iter()) {
+            assert_eq!(mapped_protein.uniprot_id, protein.uniprot_id);
+            assert_eq!(mapped_protein.taxon_id, protein.taxon_id);
+            assert_eq!(mapped_protein.functional_annotations, protein.functional_annotations);
+        }
+    }
+
+    #[test]
+    fn test_validate_text() {
+        assert!(validate_text(b"MLPG-PTDG-KWDS$", 3).is_ok());
+        assert!(validate_text(b"MLPG$", 1).is_ok());
+
+        // a text that is concatenated with another separation character contains too few proteins
+        assert!(validate_text(b"MLPG#PTDG#KWDS$", 3).is_err());
+        assert!(validate_text(b"MLPG-PTDG-KWDS", 3).is_err());
+        assert!(validate_text(b"", 0).is_err());
+    }
+
+    #[test]
+    fn test_mmap_other_separator() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_mmap_other_separator").unwrap();
+
+        let text_file = tmp_dir.path().join("text.bin");
+        let metadata_file = tmp_dir.path().join("metadata.tsv");
+        std::fs::write(&text_file, "MLPGLALLLL#PTDGNAGLLAE$").unwrap();
+        std::fs::write(&metadata_file, "P12345\t1\t\nP54321\t2\t\n").unwrap();
+
+        let error = Proteins::try_from_mmap(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).err().unwrap();
+        assert!(error.to_string().contains("2 proteins are expected"));
+
+        std::fs::write(&text_file, "MLPGLALLLL-PTDGNAGLLAE$").unwrap();
+        assert!(Proteins::try_from_mmap(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_taxon_histogram() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_taxon_histogram").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let (mut proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default())
+                .unwrap();
+
+        let histogram = proteins.taxon_histogram();
+        assert_eq!(histogram, HashMap::from([(1, 1), (2, 1), (6, 1), (17, 1)]));
+
+        proteins.proteins[0].taxon_id = 6;
+        let histogram = proteins.taxon_histogram();
+        assert_eq!(histogram, HashMap::from([(2, 1), (6, 2), (17, 1)]));
+    }
+
     #[test]
     fn test_get_taxon() {
         // Create a temporary directory for this test
@@ -290,12 +982,12 @@ mod tests {
             AggregationMethod::Lca
         )
         .unwrap();
-        let proteins =
-            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator)
+        let (proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default())
                 .unwrap();
 
         let taxa = vec![1, 2, 6, 17];
-        for (i, protein) in proteins.proteins.iter().enumerate() {
+        for (i, protein) in proteins.iter().enumerate() {
             assert_eq!(protein.taxon_id, taxa[i]);
         }
     }
@@ -313,11 +1005,11 @@ mod tests {
             AggregationMethod::Lca
         )
         .unwrap();
-        let proteins =
-            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator)
+        let (proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default())
                 .unwrap();
 
-        for protein in proteins.proteins.iter() {
+        for protein in proteins.iter() {
             assert_eq!(
                 decode(&protein.functional_annotations),
                 "GO:0009279;IPR:IPR016364;IPR:IPR008816"
@@ -338,8 +1030,8 @@ mod tests {
             AggregationMethod::Lca
         )
             .unwrap();
-        let proteins =
-            Proteins::try_from_database_file_without_annotations(database_file.to_str().unwrap(), &taxon_aggregator)
+        let (proteins, _) =
+            Proteins::try_from_database_file_without_annotations(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default())
                 .unwrap();
         
         let sep_char = SEPARATION_CHARACTER as char;
@@ -347,4 +1039,274 @@ mod tests {
         let expected = format!("MLPGLALLLLAAWTARALEV{}PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG{}KWDSDPSGTKTCIDT{}KEGILQYCQEVYPELQITNVVEANQPVTIQNWCKRGRKQCKTHPH{}", sep_char, sep_char, sep_char, end_char);
         assert_eq!(proteins, expected.as_bytes());
     }
+
+    #[test]
+    fn test_keep_taxa() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_keep_taxa").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+        let keep_taxa = HashSet::from([2, 17]);
+
+        let (proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions { keep_taxa: Some(&keep_taxa), ..Default::default() })
+                .unwrap();
+        let (text, _) =
+            Proteins::try_from_database_file_without_annotations(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions { keep_taxa: Some(&keep_taxa), ..Default::default() })
+                .unwrap();
+
+        let sep_char = SEPARATION_CHARACTER as char;
+        let end_char = TERMINATION_CHARACTER as char;
+        let expected = format!("PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG{}KEGILQYCQEVYPELQITNVVEANQPVTIQNWCKRGRKQCKTHPH{}", sep_char, end_char);
+        assert_eq!(text, expected.as_bytes());
+        assert_eq!(proteins.input_string, expected.as_bytes());
+        let ids: Vec<&str> = proteins.iter().map(|protein| protein.uniprot_id.as_str()).collect();
+        assert_eq!(ids, vec!["P54321", "P13579"]);
+    }
+
+    #[test]
+    fn test_skip_malformed_lines() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_skip_malformed_lines").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        // add a truncated line to the database file
+        let mut file = std::fs::OpenOptions::new().append(true).open(&database_file).unwrap();
+        file.write_all("P24680\t2".as_bytes()).unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+        let database_file = database_file.to_str().unwrap();
+
+        // the truncated line is an error instead of a panic by default
+        assert!(Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions::default()).is_err());
+        assert!(Proteins::try_from_database_file_without_annotations(database_file, &taxon_aggregator, &LoadOptions::default()).is_err());
+
+        let options = LoadOptions { max_skip_ratio: Some(0.5), ..Default::default() };
+        let (proteins, report) = Proteins::try_from_database_file(database_file, &taxon_aggregator, &options).unwrap();
+        let (text, text_report) = Proteins::try_from_database_file_without_annotations(database_file, &taxon_aggregator, &options).unwrap();
+        assert_eq!(report.skipped_lines, 1);
+        assert_eq!(text_report.skipped_lines, 1);
+        assert_eq!(proteins.len(), 4);
+        assert_eq!(proteins.input_string, text.as_slice());
+
+        // 1 malformed line out of 5 exceeds a ratio of 0.1
+        let error = Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions { max_skip_ratio: Some(0.1), ..Default::default() })
+            .err()
+            .unwrap();
+        let error = error.downcast_ref::<MalformedLinesError>().unwrap();
+        assert_eq!((error.skipped_lines, error.total_lines), (1, 5));
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_extra_columns").unwrap();
+
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = File::create(&database_file).unwrap();
+        file.write_all("P12345\t1\tMLPGLALLLLAAWTARALEV\t".as_bytes()).unwrap();
+        file.write_all(&[0xD1, 0x11, 0xA3, 0x8A, 0xD1, 0x27, 0x47, 0x5E, 0x11, 0x99, 0x27]).unwrap();
+        file.write_all("\treviewed\t2024-01-01\n".as_bytes()).unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+
+        let (proteins, _) = Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default()).unwrap();
+
+        let protein = &proteins[0];
+        assert_eq!(protein.uniprot_id, "P12345");
+        assert_eq!(protein.taxon_id, 1);
+        assert_eq!(proteins.input_string, "MLPGLALLLLAAWTARALEV$".as_bytes());
+        assert_eq!(decode(&protein.functional_annotations), "GO:0009279;IPR:IPR016364;IPR:IPR008816");
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_deduplicate").unwrap();
+
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = File::create(&database_file).unwrap();
+        file.write_all("P12345\t7\tMLPGLALLLLAAWTARALEV\t".as_bytes()).unwrap();
+        file.write_all(&[0xD1, 0x11, 0xA3, 0x8A, 0xD1, 0x27, 0x47, 0x5E, 0x11, 0x99, 0x27]).unwrap();
+        file.write_all("\nP67890\t2\tKWDSDPSGTKTCIDT\t\n".as_bytes()).unwrap();
+        file.write_all("P54321\t9\tmlpglallllaawtaralev\t\n".as_bytes()).unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let text_file = tmp_dir.path().join("text.bin");
+        let metadata_file = tmp_dir.path().join("metadata.tsv");
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+
+        let (proteins, _) = Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default()).unwrap();
+        assert_eq!(proteins.len(), 3);
+
+        let (proteins, _) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions { deduplicate: true, ..Default::default() }).unwrap();
+        assert_eq!(proteins.len(), 2);
+        assert_eq!(proteins.input_string, "MLPGLALLLLAAWTARALEV-KWDSDPSGTKTCIDT$".as_bytes());
+        assert_eq!(proteins[0].uniprot_id, "P12345");
+        assert_eq!(proteins[0].extra_accessions, vec![("P54321".to_string(), 9)]);
+        assert_eq!(proteins[0].taxa().collect::<Vec<TaxonId>>(), vec![7, 9]);
+        assert!(proteins[1].extra_accessions.is_empty());
+        assert_eq!(proteins.taxon_histogram(), HashMap::from([(2, 1), (7, 1), (9, 1)]));
+
+        // the collapsed protein is found under its own uniprot id as well
+        let accession_index = AccessionIndex::new(&proteins);
+        assert_eq!(accession_index.get("P54321"), accession_index.get("P12345"));
+
+        let (text, _) = Proteins::try_from_database_file_without_annotations(
+            database_file.to_str().unwrap(),
+            &taxon_aggregator,
+            &LoadOptions { deduplicate: true, ..Default::default() }
+        )
+            .unwrap();
+        assert_eq!(proteins.input_string, &text[..]);
+
+        proteins.write_mmap_files(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).unwrap();
+        let mapped_proteins =
+            Proteins::try_from_mmap(text_file.to_str().unwrap(), metadata_file.to_str().unwrap()).unwrap();
+        assert_eq!(mapped_proteins[0].uniprot_id, "P12345");
+        assert_eq!(mapped_proteins[0].extra_accessions, proteins[0].extra_accessions);
+        assert_eq!(mapped_proteins[0].functional_annotations, proteins[0].functional_annotations);
+    }
+
+    #[test]
+    fn test_missing_taxa_report() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_missing_taxa_report").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        // add proteins of taxa that are not part of the taxonomy
+        let mut file = std::fs::OpenOptions::new().append(true).open(&database_file).unwrap();
+        file.write_all("P24680\t42\tMLPGLALLLLAAWTARALEV\t\n".as_bytes()).unwrap();
+        file.write_all("P97531\t42\tKWDSDPSGTKTCIDT\t\n".as_bytes()).unwrap();
+        file.write_all("P86420\t99\tKWDSDPSGTKTCIDT\t\n".as_bytes()).unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+
+        let (proteins, report) =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &LoadOptions::default()).unwrap();
+        assert_eq!(proteins.len(), 4);
+        assert_eq!(report.skipped_lines, 0);
+        assert_eq!(report.missing_taxon_proteins, 3);
+        assert_eq!(report.missing_taxa, HashSet::from([42, 99]));
+
+        // proteins that are filtered out by keep_taxa are not reported as missing
+        let keep_taxa = HashSet::from([1]);
+        let (proteins, report) = Proteins::try_from_database_file(
+            database_file.to_str().unwrap(),
+            &taxon_aggregator,
+            &LoadOptions { keep_taxa: Some(&keep_taxa), ..Default::default() }
+        )
+            .unwrap();
+        assert_eq!(proteins.len(), 1);
+        assert_eq!(report.missing_taxon_proteins, 3);
+    }
+
+    #[test]
+    fn test_exclude_fragments() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_exclude_fragments").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        // add a protein that is flagged as a fragment and one with an empty fragment column
+        let mut file = std::fs::OpenOptions::new().append(true).open(&database_file).unwrap();
+        file.write_all("P24680\t1\tMLPGLALLLLAAWTARALEV\t\tfragment\n".as_bytes()).unwrap();
+        file.write_all("P97531\t1\tKWDSDPSGTKTCIDT\t\t\n".as_bytes()).unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+        let database_file = database_file.to_str().unwrap();
+
+        // the fragment is kept when the option is off
+        let (proteins, _) = Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+        assert_eq!(proteins.len(), 6);
+        assert!(proteins.iter().any(|protein| protein.uniprot_id == "P24680"));
+
+        // and excluded when the option is on
+        let (proteins, _) = Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions { fragment_column: Some(4), ..Default::default() }).unwrap();
+        assert_eq!(proteins.len(), 5);
+        assert!(proteins.iter().all(|protein| protein.uniprot_id != "P24680"));
+        assert!(proteins.iter().any(|protein| protein.uniprot_id == "P97531"));
+
+        // the text used to build the index drops the same proteins
+        let (text, _) = Proteins::try_from_database_file_without_annotations(database_file, &taxon_aggregator, &LoadOptions { fragment_column: Some(4), ..Default::default() }).unwrap();
+        assert_eq!(text, proteins.input_string.to_vec());
+    }
+
+    #[test]
+    fn test_database_schema() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_database_schema").unwrap();
+
+        // a comma separated database with the columns sequence, uniprot id, functional annotations and taxon id
+        let database_file = tmp_dir.path().join("database.csv");
+        let mut file = File::create(&database_file).unwrap();
+        file.write_all("MLPGLALLLLAAWTARALEV,P12345,".as_bytes()).unwrap();
+        file.write_all(&[0xD1, 0x11, 0xA3, 0x8A, 0xD1, 0x27, 0x47, 0x5E, 0x11, 0x99, 0x27]).unwrap();
+        file.write_all(",1,fragment\n".as_bytes()).unwrap();
+        file.write_all("KWDSDPSGTKTCIDT,P67890,,6,\n".as_bytes()).unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+            .unwrap();
+        let database_file = database_file.to_str().unwrap();
+        let schema = DatabaseSchema::try_new(',', &[1, 3, 0, 2]).unwrap();
+
+        let (proteins, _) = Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions { schema: schema.clone(), ..Default::default() }).unwrap();
+        assert_eq!(proteins.len(), 2);
+        assert_eq!(proteins[0].uniprot_id, "P12345");
+        assert_eq!(proteins[0].taxon_id, 1);
+        assert_eq!(decode(&proteins[0].functional_annotations), "GO:0009279;IPR:IPR016364;IPR:IPR008816");
+        assert_eq!(proteins[1].uniprot_id, "P67890");
+        assert_eq!(proteins[1].taxon_id, 6);
+        assert_eq!(proteins.input_string, "MLPGLALLLLAAWTARALEV-KWDSDPSGTKTCIDT$".as_bytes());
+
+        // the fragment column is split with the delimiter of the schema as well
+        let (text, _) = Proteins::try_from_database_file_without_annotations(database_file, &taxon_aggregator, &LoadOptions { fragment_column: Some(4), schema: schema.clone(), ..Default::default() }).unwrap();
+        assert_eq!(text, "KWDSDPSGTKTCIDT$".as_bytes());
+
+        // the default schema can not parse the file
+        assert!(Proteins::try_from_database_file(database_file, &taxon_aggregator, &LoadOptions::default()).is_err());
+
+        assert_eq!(DatabaseSchema::try_new('\t', &[0, 1, 2, 3]).unwrap(), DatabaseSchema::default());
+        assert!(DatabaseSchema::try_new(',', &[0, 1, 2]).is_err());
+        assert!(DatabaseSchema::try_new(',', &[0, 1, 1, 3]).is_err());
+        assert!(DatabaseSchema::try_new('é', &[0, 1, 2, 3]).is_err());
+    }
 }
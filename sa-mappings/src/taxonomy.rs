@@ -9,6 +9,7 @@ use umgap::{
         count,
         MultiThreadSafeAggregator
     },
+    rank::Rank,
     rmq::{
         lca::LCACalculator,
         mix::MixCalculator
@@ -29,6 +30,10 @@ pub struct TaxonAggregator {
     /// The aggregator used to aggregate taxon IDs.
     aggregator: Box<dyn MultiThreadSafeAggregator>,
 
+    /// The maximum number of taxon IDs that are aggregated with the LCA aggregator instead of `aggregator`, together with that
+    /// LCA aggregator. This is only set for the hybrid aggregation method.
+    low_support_aggregator: Option<(usize, Box<dyn MultiThreadSafeAggregator>)>,
+
     /// The taxon list.
     taxon_list: TaxonList
 }
@@ -39,7 +44,14 @@ pub enum AggregationMethod {
     Lca,
 
     /// The LCA* aggregation method.
-    LcaStar
+    LcaStar,
+
+    /// Uses the LCA* aggregation method when more than `threshold` taxon IDs are aggregated, and the LCA aggregation method
+    /// otherwise. This prevents LCA* from picking a single species when only a few proteins support it.
+    Hybrid {
+        /// The maximum number of taxon IDs that are aggregated with the LCA aggregation method
+        threshold: usize
+    }
 }
 
 impl TaxonAggregator {
@@ -63,17 +75,27 @@ impl TaxonAggregator {
     ) -> Result<Self, Box<dyn Error>> {
         let taxons = read_taxa_file(file)?;
         let taxon_tree = TaxonTree::new(&taxons);
+
+        // the hybrid aggregation method needs its own taxon tree for the LCA aggregator
+        let low_support_aggregator: Option<(usize, Box<dyn MultiThreadSafeAggregator>)> = match method {
+            AggregationMethod::Hybrid { threshold } => {
+                Some((threshold, Box::new(MixCalculator::new(TaxonTree::new(&taxons), 1.0))))
+            }
+            _ => None
+        };
+
         let taxon_list = TaxonList::new(taxons);
         let snapping = taxon_tree.snapping(&taxon_list, true);
 
         let aggregator: Box<dyn MultiThreadSafeAggregator> = match method {
             AggregationMethod::Lca => Box::new(MixCalculator::new(taxon_tree, 1.0)),
-            AggregationMethod::LcaStar => Box::new(LCACalculator::new(taxon_tree))
+            AggregationMethod::LcaStar | AggregationMethod::Hybrid { .. } => Box::new(LCACalculator::new(taxon_tree))
         };
 
         Ok(Self {
             snapping,
             aggregator,
+            low_support_aggregator,
             taxon_list
         })
     }
@@ -108,6 +130,55 @@ impl TaxonAggregator {
         }
     }
 
+    /// Looks up the scientific name of a taxon.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The taxon ID to look up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the name of the taxon, or None if the taxon does not exist.
+    pub fn taxon_name(&self, taxon: TaxonId) -> Option<&str> {
+        self.taxon_list.get(taxon).map(|taxon| taxon.name.as_str())
+    }
+
+    /// Looks up the rank of a taxon.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The taxon ID to look up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the rank of the taxon, or None if the taxon does not exist.
+    pub fn taxon_rank(&self, taxon: TaxonId) -> Option<Rank> {
+        self.taxon_list.get(taxon).map(|taxon| taxon.rank)
+    }
+
+    /// Looks up the lineage of a taxon.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The taxon ID to look up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxon followed by all its ancestors up to the root, or an empty vector if the taxon does not exist.
+    pub fn lineage(&self, taxon: TaxonId) -> Vec<TaxonId> {
+        let mut lineage = vec![];
+        let mut current = self.taxon_list.get(taxon);
+        while let Some(taxon) = current {
+            lineage.push(taxon.id);
+            // the root is its own parent
+            if taxon.parent == taxon.id {
+                break;
+            }
+            current = self.taxon_list.get(taxon.parent);
+        }
+        lineage
+    }
+
     /// Snaps a taxon to its closest ancestor in the taxonomic tree.
     ///
     /// # Arguments
@@ -134,15 +205,87 @@ impl TaxonAggregator {
     /// Returns None if the list of taxa to aggregate is emtpy,
     /// Panics if aggregation fails.
     pub fn aggregate(&self, taxa: Vec<TaxonId>, ) -> Option<TaxonId> {
-        if taxa.is_empty() {
+        self.aggregate_slice(&taxa)
+    }
+
+    /// Aggregates a borrowed list of taxon IDs using the specified aggregation method.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxa` - A slice of taxon IDs to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// Returns the aggregated taxon ID wrapped in Some if aggregation succeeds,
+    /// Returns None if the list of taxa to aggregate is emtpy,
+    /// Panics if aggregation fails.
+    pub fn aggregate_slice(&self, taxa: &[TaxonId]) -> Option<TaxonId> {
+        self.aggregate_iter(taxa.iter().copied())
+    }
+
+    /// Aggregates the taxon IDs of an iterator using the specified aggregation method.
+    /// This avoids collecting the taxon IDs in an intermediate vector, e.g. when they are taken from a list of proteins.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxa` - An iterator over the taxon IDs to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// Returns the aggregated taxon ID wrapped in Some if aggregation succeeds,
+    /// Returns None if the iterator is emtpy,
+    /// Panics if aggregation fails.
+    pub fn aggregate_iter(&self, taxa: impl Iterator<Item = TaxonId>) -> Option<TaxonId> {
+        let mut support = 0;
+        let count = count(taxa.inspect(|_| support += 1).map(|t| (t, 1.0_f32)));
+        if count.is_empty() {
+            return None
+        }
+
+        Some(self.aggregator_for(support)
+            .aggregate(&count)
+            .unwrap_or_else(|_| panic!("Could not aggregate following taxon ids: {:?}", &count)))
+    }
+
+    /// Aggregates weighted taxon IDs using the specified aggregation method.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxa_with_weights` - A vector of taxon IDs with their weight, e.g. the spectral count or intensity of the peptide they were found for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the aggregated taxon ID wrapped in Some if aggregation succeeds,
+    /// Returns None if the list of taxa to aggregate is emtpy,
+    /// Panics if aggregation fails.
+    pub fn aggregate_weighted(&self, taxa_with_weights: Vec<(TaxonId, f32)>) -> Option<TaxonId> {
+        if taxa_with_weights.is_empty() {
             return None
         }
 
-        let count = count(taxa.into_iter().map(|t| (t, 1.0_f32)));
-        Some(self.aggregator
+        let support = taxa_with_weights.len();
+        let count = count(taxa_with_weights.into_iter());
+        Some(self.aggregator_for(support)
             .aggregate(&count)
             .unwrap_or_else(|_| panic!("Could not aggregate following taxon ids: {:?}", &count)))
     }
+
+    /// Chooses the aggregator based on the number of taxon IDs that are aggregated.
+    ///
+    /// # Arguments
+    ///
+    /// * `support` - The number of taxon IDs that are aggregated.
+    ///
+    /// # Returns
+    ///
+    /// Returns the LCA aggregator of the hybrid aggregation method if `support` does not exceed its threshold,
+    /// otherwise the aggregator of the aggregation method.
+    fn aggregator_for(&self, support: usize) -> &dyn MultiThreadSafeAggregator {
+        match &self.low_support_aggregator {
+            Some((threshold, aggregator)) if support <= *threshold => aggregator.as_ref(),
+            _ => self.aggregator.as_ref()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -221,6 +364,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_taxon_name_and_rank() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_taxon_name_and_rank").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+
+        assert_eq!(taxon_aggregator.taxon_name(7), Some("Azorhizobium caulinodans"));
+        assert_eq!(taxon_aggregator.taxon_rank(7), Some(Rank::Species));
+        assert_eq!(taxon_aggregator.taxon_rank(1).unwrap().to_string(), "no rank");
+        assert_eq!(taxon_aggregator.taxon_name(3), None);
+        assert_eq!(taxon_aggregator.taxon_rank(3), None);
+    }
+
+    #[test]
+    fn test_lineage() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_lineage").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+
+        assert_eq!(taxon_aggregator.lineage(17), vec![17, 16, 14, 10, 6, 1]);
+        assert_eq!(taxon_aggregator.lineage(1), vec![1]);
+        assert!(taxon_aggregator.lineage(3).is_empty());
+    }
+
     #[test]
     fn test_snap_taxon() {
         // Create a temporary directory for this test
@@ -276,4 +457,81 @@ mod tests {
         assert_eq!(taxon_aggregator.aggregate(vec![11, 14]), Some(10));
         assert_eq!(taxon_aggregator.aggregate(vec![17, 19]), Some(19));
     }
+
+    #[test]
+    fn test_aggregate_slice() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_aggregate_slice").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        for taxa in [vec![7, 9], vec![11, 14], vec![17, 19], vec![7, 7, 9], vec![13], vec![]] {
+            assert_eq!(taxon_aggregator.aggregate_slice(&taxa), taxon_aggregator.aggregate(taxa.clone()));
+            assert_eq!(taxon_aggregator.aggregate_iter(taxa.iter().copied()), taxon_aggregator.aggregate(taxa.clone()));
+        }
+        assert_eq!(taxon_aggregator.aggregate_slice(&[]), None);
+    }
+
+    #[test]
+    fn test_aggregate_weighted() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_aggregate_weighted").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+
+        // equal weights give the same result as the unweighted aggregation
+        assert_eq!(taxon_aggregator.aggregate_weighted(vec![(7, 1.0), (9, 1.0)]), Some(6));
+        assert_eq!(taxon_aggregator.aggregate_weighted(vec![]), None);
+
+        // the LCA and LCA* methods do not take the weights into account, so use an aggregator
+        // that picks the heaviest root-to-leaf path to check the weights are passed on
+        let taxons = read_taxa_file(taxonomy_file.to_str().unwrap()).unwrap();
+        let taxon_tree = TaxonTree::new(&taxons);
+        let taxon_list = TaxonList::new(taxons);
+        let taxon_aggregator = TaxonAggregator {
+            snapping: taxon_tree.snapping(&taxon_list, true),
+            aggregator: Box::new(MixCalculator::new(taxon_tree, 0.0)),
+            low_support_aggregator: None,
+            taxon_list
+        };
+
+        assert_eq!(taxon_aggregator.aggregate_weighted(vec![(7, 10.0), (9, 1.0)]), Some(7));
+        assert_eq!(taxon_aggregator.aggregate_weighted(vec![(7, 1.0), (9, 10.0)]), Some(9));
+    }
+
+    #[test]
+    fn test_aggregate_hybrid() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_aggregate_hybrid").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Hybrid { threshold: 2 }
+        )
+        .unwrap();
+
+        // at most 2 taxa are aggregated with LCA, so the species 19 is not picked over its ancestor 17
+        assert_eq!(taxon_aggregator.aggregate(vec![17, 19]), Some(17));
+        assert_eq!(taxon_aggregator.aggregate(vec![19]), Some(19));
+        assert_eq!(taxon_aggregator.aggregate_weighted(vec![(17, 1.0), (19, 1.0)]), Some(17));
+
+        // more taxa are aggregated with LCA*
+        assert_eq!(taxon_aggregator.aggregate(vec![17, 19, 19]), Some(19));
+        assert_eq!(taxon_aggregator.aggregate_slice(&[17, 19, 19]), Some(19));
+        assert_eq!(taxon_aggregator.aggregate(vec![7, 9, 7]), Some(6));
+    }
 }
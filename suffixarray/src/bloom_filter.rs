@@ -0,0 +1,122 @@
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::LN_2;
+use std::hash::{Hash, Hasher};
+
+/// Probabilistic set of byte strings that never gives false negatives
+/// A lookup of an item that was inserted always returns true, a lookup of an item that was not inserted returns true
+/// with a probability of (approximately) the false-positive rate the filter was created with
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u64,
+}
+
+impl BloomFilter {
+    /// Creates an empty Bloom filter sized for the expected number of items
+    /// The filter uses about `-ln(false_positive_rate) / ln(2)^2` bits per item, e.g. 9.6 bits per item for a rate of 1%
+    ///
+    /// # Arguments
+    /// * `expected_items` - The number of items that will be inserted
+    /// * `false_positive_rate` - The probability that a lookup of an item that was not inserted returns true, between 0 and 1
+    ///
+    /// # Returns
+    ///
+    /// Returns an empty Bloom filter
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let bit_count = (-items * false_positive_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0) as u64;
+        let hash_count = (bit_count as f64 / items * LN_2).round().max(1.0) as u64;
+
+        Self {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+        }
+    }
+
+    /// Inserts an item in the filter
+    ///
+    /// # Arguments
+    /// * `item` - The item that is inserted
+    pub fn insert(&mut self, item: &[u8]) {
+        for bit in Self::bit_positions(item, self.bit_count, self.hash_count) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Checks if an item may be part of the filter
+    ///
+    /// # Arguments
+    /// * `item` - The item that is looked up
+    ///
+    /// # Returns
+    ///
+    /// Returns false if the item was certainly not inserted, true if it was (probably) inserted
+    pub fn contains(&self, item: &[u8]) -> bool {
+        Self::bit_positions(item, self.bit_count, self.hash_count).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the memory used by the bits of the filter in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Calculates the bits of an item using double hashing, so the item only has to be hashed twice
+    ///
+    /// # Arguments
+    /// * `item` - The item of which the bits are calculated
+    /// * `bit_count` - The number of bits in the filter
+    /// * `hash_count` - The number of bits that are set for every item
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over the `hash_count` bits of the item
+    fn bit_positions(item: &[u8], bit_count: u64, hash_count: u64) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let first = hasher.finish();
+        // continue from the state of the first hash, so the second hash is independent of the first one
+        first.hash(&mut hasher);
+        // an odd step never cycles through only a part of the bits
+        let second = hasher.finish() | 1;
+
+        (0..hash_count).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % bit_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bloom_filter::BloomFilter;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let items: Vec<Vec<u8>> = (0..1000_u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let mut filter = BloomFilter::new(items.len(), 0.01);
+        for item in &items {
+            filter.insert(item);
+        }
+
+        assert!(items.iter().all(|item| filter.contains(item)));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000_u32 {
+            filter.insert(&i.to_le_bytes());
+        }
+
+        // the items that were not inserted are only rarely reported as present
+        let false_positives = (1000..11000_u32).filter(|i| filter.contains(&i.to_le_bytes())).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+        // about 9.6 bits per item
+        assert_eq!(filter.size_in_bytes(), 1200);
+    }
+
+    #[test]
+    fn test_empty() {
+        let filter = BloomFilter::new(0, 0.01);
+        assert!(!filter.contains(b"ACW"));
+    }
+}
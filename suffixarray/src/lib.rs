@@ -1,41 +1,73 @@
+use std::collections::HashSet;
 use std::error::Error;
+use std::fs;
+use std::io::{self, BufWriter, Write};
 use std::num::NonZeroUsize;
+use std::time::Instant;
 
 use clap::{arg, Parser, ValueEnum};
+use log::{info, LevelFilter};
 
 use sa_mappings::functionality::FunctionAggregator;
-use sa_mappings::proteins::Proteins;
+use sa_mappings::proteins::{DatabaseSchema, LoadOptions, Proteins};
 use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
+use suffixarray_builder::{build_sa_from_proteins, SAConstructionAlgorithm};
 use suffixarray_builder::binary::{load_suffix_array, write_suffix_array};
 
-use crate::peptide_search::{analyse_all_peptides, search_all_peptides};
-use crate::sa_searcher::Searcher;
+use crate::peptide_search::{analyse_all_peptides, benchmark_peptides, BenchmarkReport, CutoffPolicy, search_all_peptides, SearchOptions, SearchStats, write_all_peptide_counts, write_all_peptides_chunked, write_all_peptides_unipept_tsv};
+use crate::sa_searcher::{Searcher, DEFAULT_KMER_FILTER_FALSE_POSITIVE_RATE};
 use crate::suffix_to_protein_index::{
-    DenseSuffixToProtein, SparseSuffixToProtein, SuffixToProteinIndex, SuffixToProteinMappingStyle,
+    DenseSuffixToProtein, load_suffix_to_protein_mapping, SparseSuffixToProtein, SuffixToProteinIndex, SuffixToProteinMappingStyle,
+    write_suffix_to_protein_mapping,
 };
-use crate::util::{get_time_ms, read_lines};
+use crate::util::{elapsed_ms, read_lines};
 
+pub mod bloom_filter;
 pub mod peptide_search;
 pub mod sa_searcher;
 pub mod suffix_to_protein_index;
+pub mod translation;
 pub mod util;
 
-/// Enum that represents the 2 kinds of search that are supported
+/// Search files larger than this size (in bytes) are not loaded in memory at once, but searched in chunks
+const CHUNKED_SEARCH_THRESHOLD: u64 = 1 << 30;
+
+/// The number of peptides searched at once when a search file is searched in chunks
+const SEARCH_CHUNK_SIZE: usize = 100_000;
+
+/// The maximum number of threads used during search when the number of threads is not set explicitly
+const MAX_DEFAULT_THREADS: usize = 8;
+
+/// The number of peptides at the start of the input that are checked to be uppercase when `--assume-uppercase` is set
+const UPPERCASE_SAMPLE_SIZE: usize = 1000;
+
+/// Iterator over the peptides that are searched
+type PeptideIterator = Box<dyn Iterator<Item = String>>;
+
+/// Enum that represents the 4 kinds of search that are supported
+/// - Search the peptides and output the matching proteins
+/// - Search the peptides and output the taxonomic and functional analysis of the matching proteins
+/// - Only count the matches of every peptide and output a line `peptide\tmatch_count`
+/// - Search the peptides and output the lowest common ancestor as TSV, compatible with the `pept2lca` output of the Unipept CLI
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 pub enum SearchMode {
     Search,
     Analysis,
+    Count,
+    UnipeptTsv,
 }
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
 pub struct Arguments {
-    /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
+    /// File with the proteins used to build the suffix tree. The proteins are concatenated using the `-` separation character.
     #[arg(short, long)]
     database_file: String,
     #[arg(short, long)]
     search_file: Option<String>,
+    /// Search a single peptide instead of the peptides of a search file
+    #[arg(long, conflicts_with = "search_file")]
+    peptide: Option<String>,
     #[arg(short, long)]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
     taxonomy: String,
@@ -45,6 +77,9 @@ pub struct Arguments {
     /// Output file to store the built index.
     #[arg(short, long)]
     output: Option<String>,
+    /// Gzip compress the stored index, which makes the file smaller but loading the index slower
+    #[arg(long, requires = "output")]
+    compress: bool,
     /// The sparseness factor used on the suffix array (default value 1, which means every value in the SA is used)
     #[arg(long, default_value_t = 1)]
     sparseness_factor: u8,
@@ -53,21 +88,98 @@ pub struct Arguments {
     /// Sparse uses O(m) memory with m the number of proteins, and takes O(log m) to find the mapping
     #[arg(long, value_enum, default_value_t = SuffixToProteinMappingStyle::Sparse)]
     suffix_to_protein_mapping: SuffixToProteinMappingStyle,
+    /// Output file to store the suffix-to-protein mapping, so it does not have to be built again when the index is loaded
+    #[arg(long)]
+    mapping_output: Option<String>,
+    /// Load the suffix-to-protein mapping from this file instead of building it, the style of the loaded mapping is used
+    #[arg(long)]
+    load_mapping: Option<String>,
     #[arg(long)]
     load_index: Option<String>,
     #[arg(short, long, value_enum, default_value_t = SAConstructionAlgorithm::LibSais)]
     construction_algorithm: SAConstructionAlgorithm,
+    /// Only use the proteins of these taxa (comma separated), this should be the same list as used to build a loaded index
+    #[arg(long, value_delimiter = ',')]
+    keep_taxa: Option<Vec<usize>>,
+    /// Collapse the proteins with an identical sequence, this should be the same as used to build a loaded index
+    #[arg(long)]
+    deduplicate: bool,
+    /// Drop the proteins flagged as a fragment in this (zero-based) column, this should be the same column as used to build a loaded index
+    #[arg(long, value_name = "COLUMN")]
+    exclude_fragments: Option<usize>,
+    /// The character that separates the fields of a line in the database file
+    #[arg(long, default_value_t = '\t')]
+    separator: char,
+    /// The (zero-based) columns of the uniprot id, taxon id, sequence and functional annotations in the database file (comma separated)
+    #[arg(long, value_delimiter = ',', default_values_t = [0, 1, 2, 3], value_name = "UNIPROT,TAXON,SEQUENCE,ANNOTATIONS")]
+    columns: Vec<usize>,
     /// Assume the resulting taxon ID is root (1) whenever a peptide matches >= cutoff proteins
     #[arg(long, default_value_t = 10000)]
     cutoff: usize,
+    /// Use a length dependent cutoff `cutoff + cutoff_length_factor * (cutoff_max_length - peptide length)`, so shorter peptides can have more matches
     #[arg(long)]
-    threads: Option<NonZeroUsize>,
+    cutoff_length_factor: Option<usize>,
+    /// The peptide length from which on the length dependent cutoff is equal to `cutoff`
+    #[arg(long, default_value_t = 50)]
+    cutoff_max_length: usize,
+    /// The number of threads used during search, by default the number of cores with a maximum of 8
+    #[arg(long, default_value_t = default_thread_count())]
+    threads: NonZeroUsize,
     #[arg(long)]
     equalize_i_and_l: bool,
+    /// Reject peptides that contain a k-mer of this length that does not occur in the database without searching them.
+    /// This uses a Bloom filter with a false-positive rate of 1%, which takes about 1.2 bytes per residue of the database
+    #[arg(long)]
+    kmer_filter: Option<usize>,
     #[arg(long)]
     clean_taxa: bool,
+    /// Search case-sensitive instead of uppercasing the peptides, lowercase residues then only match lowercase (masked) residues
+    #[arg(long)]
+    preserve_case: bool,
+    /// The peptides are already uppercase, so they are not uppercased again before searching.
+    /// The first peptides of the input are checked and an error is returned if they contain lowercase residues
+    #[arg(long, conflicts_with = "preserve_case")]
+    assume_uppercase: bool,
+    /// Add the position of each peptide in the input to its result, so the results can be matched with the input peptides
+    #[arg(long)]
+    include_index: bool,
+    /// Leave out the peptides without matches in every search mode, the search, analysis and unipept-tsv modes always leave them out,
+    /// the count mode then no longer writes a line with count 0. The output then no longer aligns with the input peptides,
+    /// use `--include-index` in the search and analysis modes to match the results with the input
+    #[arg(long)]
+    matches_only: bool,
+    /// Add the sequence of every matched protein to the results of the search mode
+    #[arg(long)]
+    include_sequences: bool,
+    /// Leave out the taxon of every matched protein in the analysis results, only the number of proteins per taxon is kept
+    #[arg(long)]
+    no_taxa: bool,
+    /// Add the lca before it is snapped to a valid (ranked) taxon to the analysis results, next to the snapped lca
+    #[arg(long)]
+    include_raw_lca: bool,
+    /// Skip the functional analysis in the analysis mode, this avoids decoding the functional annotations of every matched protein
+    #[arg(long)]
+    no_functional_analysis: bool,
     #[arg(long, value_enum, default_value_t = SearchMode::Analysis)]
-    search_mode: SearchMode
+    search_mode: SearchMode,
+    /// Print a summary of the search run (number of (un)matched peptides, cutoffs used and search time) to stderr
+    #[arg(long)]
+    stats: bool,
+    /// Write the summary of the search run to this file instead of to stderr
+    #[arg(long)]
+    stats_file: Option<String>,
+    /// Only measure the search: print the throughput and the p50/p95/p99 latency of the peptides as JSON to stderr instead of the results
+    #[arg(long)]
+    benchmark: bool,
+    /// Write the benchmark report to this file instead of to stderr
+    #[arg(long, requires = "benchmark")]
+    benchmark_file: Option<String>,
+    /// Print statistics of the index (number of proteins, residues and taxa, protein lengths and suffix array size) as JSON instead of searching
+    #[arg(long)]
+    index_statistics: bool,
+    /// The verbosity of the log messages (off, error, warn, info, debug or trace)
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    pub log_level: LevelFilter,
 }
 
 
@@ -86,32 +198,34 @@ pub struct Arguments {
 pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
     let taxon_id_calculator =
         TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar)?;
+    let keep_taxa: Option<HashSet<usize>> = args.keep_taxa.as_ref().map(|taxa| taxa.iter().copied().collect());
 
-    let sa = match &args.load_index {
-        // load SA from file
-        Some(index_file_name) => {
-            let (sparseness_factor, sa) = load_suffix_array(index_file_name)?;
-            args.sparseness_factor = sparseness_factor;
-            // println!("Loading the SA took {} ms and loading the proteins + SA took {} ms", end_loading_ms - start_loading_ms, end_loading_ms - start_reading_proteins_ms);
-            // TODO: some kind of security check that the loaded database file and SA match
-            sa
-        }
-        // build the SA
-        None => {
-            let protein_sequences =
-                Proteins::try_from_database_file(&args.database_file, &taxon_id_calculator)?;
-            build_sa(
-                &mut protein_sequences.input_string.clone(),
-                &args.construction_algorithm,
-                args.sparseness_factor,
-            )?
-        }
+    let load_options = LoadOptions {
+        keep_taxa: keep_taxa.as_ref(),
+        fragment_column: args.exclude_fragments,
+        schema: DatabaseSchema::try_new(args.separator, &args.columns)?,
+        deduplicate: args.deduplicate,
+        ..Default::default()
     };
 
-    let proteins = Proteins::try_from_database_file(&args.database_file, &taxon_id_calculator)?;
+    // the database is only parsed once, the same proteins are used to build the SA and to search in
+    let (proteins, _) = Proteins::try_from_database_file(&args.database_file, &taxon_id_calculator, &load_options)?;
+
+    let (sparseness_factor, sa) = load_or_build_suffix_array(&args, &proteins)?;
+    args.sparseness_factor = sparseness_factor;
 
     if let Some(output) = &args.output {
-        write_suffix_array(args.sparseness_factor, &sa, output)?;
+        write_suffix_array(args.sparseness_factor, &sa, output, args.compress)?;
+    }
+
+    // the mapping is only built when it is stored or when we search
+    if args.build_only && args.mapping_output.is_none() {
+        return Ok(());
+    }
+
+    let suffix_index_to_protein = load_or_build_suffix_to_protein(&args, &proteins)?;
+    if let Some(mapping_output) = &args.mapping_output {
+        write_suffix_to_protein_mapping(suffix_index_to_protein.as_ref(), mapping_output)?;
     }
 
     // option that only builds the tree, but does not allow for querying (easy for benchmark purposes)
@@ -119,32 +233,86 @@ pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
-    // build the right mapping index, use box to be able to store both types in this variable
-    let suffix_index_to_protein: Box<dyn SuffixToProteinIndex> =
-        match args.suffix_to_protein_mapping {
-            SuffixToProteinMappingStyle::Dense => {
-                Box::new(DenseSuffixToProtein::new(&proteins.input_string))
-            }
-            SuffixToProteinMappingStyle::Sparse => {
-                Box::new(SparseSuffixToProtein::new(&proteins.input_string))
-            }
-        };
-
     let functional_aggregator = FunctionAggregator {};
 
-    let searcher = Searcher::new(
+    let searcher = Searcher::try_new(
         sa,
         args.sparseness_factor,
         suffix_index_to_protein,
         proteins,
         taxon_id_calculator,
         functional_aggregator,
-    );
+    )?;
+    let searcher = match args.kmer_filter {
+        Some(k) => searcher.with_kmer_filter(k, DEFAULT_KMER_FILTER_FALSE_POSITIVE_RATE),
+        None => searcher,
+    };
+
+    if args.index_statistics {
+        println!("{}", serde_json::to_string(&searcher.statistics())?);
+        return Ok(());
+    }
 
     execute_search(&searcher, &args)?;
     Ok(())
 }
 
+/// Loads the suffix array from the index file if one is provided, otherwise builds the suffix array over the proteins
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `proteins` - The proteins of the database file
+///
+/// # Returns
+///
+/// Returns the sparseness factor of the suffix array, together with the suffix array
+///
+/// # Errors
+///
+/// Returns possible errors that occurred while loading or building the suffix array
+fn load_or_build_suffix_array(args: &Arguments, proteins: &Proteins) -> Result<(u8, Vec<i64>), Box<dyn Error>> {
+    match &args.load_index {
+        // load SA from file
+        // TODO: some kind of security check that the loaded database file and SA match
+        Some(index_file_name) => load_suffix_array(index_file_name),
+        // build the SA
+        None => {
+            let sa = build_sa_from_proteins(
+                proteins,
+                &args.construction_algorithm,
+                args.sparseness_factor,
+                true,
+            )?;
+            Ok((args.sparseness_factor, sa))
+        }
+    }
+}
+
+/// Loads the suffix-to-protein mapping from the mapping file if one is provided, otherwise builds the mapping in the chosen style
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `proteins` - The proteins of the database file
+///
+/// # Returns
+///
+/// Returns the mapping from a suffix to the protein it is part of
+///
+/// # Errors
+///
+/// Returns possible errors that occurred while loading the mapping, or if the loaded mapping does not match the proteins
+fn load_or_build_suffix_to_protein(args: &Arguments, proteins: &Proteins) -> Result<Box<dyn SuffixToProteinIndex>, Box<dyn Error>> {
+    if let Some(mapping_file) = &args.load_mapping {
+        return load_suffix_to_protein_mapping(mapping_file, &proteins.input_string);
+    }
+
+    // build the right mapping index, use box to be able to store both types in this variable
+    Ok(match args.suffix_to_protein_mapping {
+        SuffixToProteinMappingStyle::Dense => Box::new(DenseSuffixToProtein::new(&proteins.input_string)),
+        SuffixToProteinMappingStyle::Sparse => Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+    })
+}
+
 /// Execute the search using the provided programs
 ///
 /// # Arguments
@@ -159,57 +327,272 @@ pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
 ///
 /// Returns possible errors that occurred during search
 fn execute_search(searcher: &Searcher, args: &Arguments) -> Result<(), Box<dyn Error>> {
-    let cutoff = args.cutoff;
-    let search_file = args
-        .search_file
-        .as_ref()
-        .ok_or("No peptide file provided to search in the database")?;
+    let start_time = Instant::now();
+    let (peptides, chunk_size) = read_peptides(args)?;
 
-    let start_time = get_time_ms()?;
-    let lines = read_lines(search_file)?;
-    let all_peptides: Vec<String> = lines.map_while(Result::ok).collect();
+    // always configure the number of threads explicitly, so the behaviour does not depend on the machine
+    thread_pool_builder(args.threads).build_global()?;
+    info!("Searching with {} threads", args.threads);
 
-    // Explicitly set the number of threads to use if the commandline argument was set
-    if let Some(threads) = args.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads.get())
-            .build_global()?;
+    if args.benchmark {
+        let report = run_benchmark(searcher, args, peptides);
+        let report = serde_json::to_string(&report)?;
+        match &args.benchmark_file {
+            Some(benchmark_file) => fs::write(benchmark_file, report)?,
+            None => eprintln!("{}", report),
+        }
+        return Ok(());
     }
 
-    match args.search_mode {
-        SearchMode::Search => {
-            let search_result = search_all_peptides(
-                searcher,
-                &all_peptides,
-                cutoff,
-                args.equalize_i_and_l,
-                args.clean_taxa,
-            );
-            println!("{}", serde_json::to_string(&search_result)?);
-        }
-        SearchMode::Analysis => {
-            let search_result = analyse_all_peptides(
-                searcher,
-                &all_peptides,
-                cutoff,
-                args.equalize_i_and_l,
-                args.clean_taxa,
-            );
-            println!("{}", serde_json::to_string(&search_result)?);
-        }
+    let mut output = BufWriter::new(io::stdout().lock());
+    let mut stats = write_search_results(searcher, args, peptides, chunk_size, &mut output)?;
+    // every count or TSV row is already written on its own line
+    if !matches!(args.search_mode, SearchMode::Count | SearchMode::UnipeptTsv) {
+        writeln!(output)?;
+    }
+    output.flush()?;
+
+    stats.set_total_time(elapsed_ms(start_time));
+    if let Some(stats_file) = &args.stats_file {
+        fs::write(stats_file, serde_json::to_string(&stats)?)?;
+    } else if args.stats {
+        eprintln!("{}", serde_json::to_string(&stats)?);
     }
-        
-    let end_time = get_time_ms()?;
 
-    // output to other channel to prevent integrating it into the actual output
-    eprintln!(
+    // the log is written to another channel to prevent integrating it into the actual output
+    info!(
         "Spend {} ms to search the whole file",
-        end_time - start_time
+        elapsed_ms(start_time)
     );
 
     Ok(())
 }
 
+/// Measures the throughput and latency of searching the peptides, with the analyses set with the commandline arguments
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `args` - The arguments used to start the program
+/// * `peptides` - The peptides that are searched
+///
+/// # Returns
+///
+/// Returns the `BenchmarkReport` of the search
+fn run_benchmark(searcher: &Searcher, args: &Arguments, peptides: impl Iterator<Item = String>) -> BenchmarkReport {
+    let peptides: Vec<String> = peptides.collect();
+    benchmark_peptides(searcher, &peptides, cutoff_policy(args), &search_options(args))
+}
+
+/// Returns the default number of threads used during search
+///
+/// # Returns
+///
+/// Returns the number of available cores, with a maximum of `MAX_DEFAULT_THREADS`
+fn default_thread_count() -> NonZeroUsize {
+    let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    NonZeroUsize::new(cores.min(MAX_DEFAULT_THREADS)).unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Creates the builder of the thread pool used during search
+///
+/// # Arguments
+/// * `threads` - The number of threads in the thread pool
+///
+/// # Returns
+///
+/// Returns the thread pool builder configured with `threads` threads
+fn thread_pool_builder(threads: NonZeroUsize) -> rayon::ThreadPoolBuilder {
+    rayon::ThreadPoolBuilder::new().num_threads(threads.get())
+}
+
+/// Reads the peptides that should be searched, either the single peptide or the peptides of the search file
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+///
+/// # Returns
+///
+/// Returns an iterator over the peptides, together with the number of peptides that should be searched at once
+///
+/// # Errors
+///
+/// Returns an error if no peptides are provided, if the search file could not be read,
+/// or if `--assume-uppercase` is set and the first peptides contain lowercase residues
+fn read_peptides(args: &Arguments) -> Result<(PeptideIterator, usize), Box<dyn Error>> {
+    let (peptides, chunk_size) = read_peptide_input(args)?;
+    if args.assume_uppercase {
+        Ok((check_uppercase_sample(peptides)?, chunk_size))
+    } else {
+        Ok((peptides, chunk_size))
+    }
+}
+
+/// Checks that the first `UPPERCASE_SAMPLE_SIZE` peptides do not contain lowercase residues
+///
+/// # Arguments
+/// * `peptides` - The peptides that are searched
+///
+/// # Returns
+///
+/// Returns an iterator over all the peptides, including the checked ones
+///
+/// # Errors
+///
+/// Returns an error if one of the checked peptides contains a lowercase residue
+fn check_uppercase_sample(mut peptides: PeptideIterator) -> Result<PeptideIterator, Box<dyn Error>> {
+    let sample: Vec<String> = peptides.by_ref().take(UPPERCASE_SAMPLE_SIZE).collect();
+    if let Some(peptide) = sample.iter().find(|peptide| peptide.bytes().any(|c| c.is_ascii_lowercase())) {
+        return Err(format!("Peptide {} contains lowercase residues, but the peptides are assumed to be uppercase", peptide).into());
+    }
+
+    Ok(Box::new(sample.into_iter().chain(peptides)))
+}
+
+/// Reads the peptides of the single peptide argument or of the search file
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+///
+/// # Returns
+///
+/// Returns an iterator over the peptides, together with the number of peptides that should be searched at once
+///
+/// # Errors
+///
+/// Returns an error if no peptides are provided, or if the search file could not be read
+fn read_peptide_input(args: &Arguments) -> Result<(PeptideIterator, usize), Box<dyn Error>> {
+    if let Some(peptide) = &args.peptide {
+        return Ok((Box::new(std::iter::once(peptide.clone())), 1));
+    }
+
+    let search_file = args
+        .search_file
+        .as_ref()
+        .ok_or("No peptide or peptide file provided to search in the database")?;
+    let lines = read_lines(search_file)?;
+
+    // large files are searched in chunks to keep the memory usage bounded, smaller files are searched at once
+    let chunk_size = if fs::metadata(search_file)?.len() > CHUNKED_SEARCH_THRESHOLD {
+        SEARCH_CHUNK_SIZE
+    } else {
+        usize::MAX
+    };
+
+    Ok((Box::new(lines.map_while(Result::ok)), chunk_size))
+}
+
+/// Creates the cutoff policy set with the commandline arguments
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+///
+/// # Returns
+///
+/// Returns a length dependent cutoff policy if a cutoff length factor is set, otherwise a constant cutoff policy
+fn cutoff_policy(args: &Arguments) -> CutoffPolicy {
+    match args.cutoff_length_factor {
+        Some(factor) => CutoffPolicy::LengthDependent {
+            base: args.cutoff,
+            factor,
+            max_length: args.cutoff_max_length,
+        },
+        None => CutoffPolicy::Constant(args.cutoff),
+    }
+}
+
+/// Creates the search options set with the commandline arguments
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+///
+/// # Returns
+///
+/// Returns the options that determine how the peptides are searched and which analyses are added to the results
+fn search_options(args: &Arguments) -> SearchOptions {
+    SearchOptions {
+        equalize_i_and_l: args.equalize_i_and_l,
+        clean_taxa: args.clean_taxa,
+        // peptides that are already uppercase are searched without uppercasing them again
+        preserve_case: args.preserve_case || args.assume_uppercase,
+        include_index: args.include_index,
+        include_sequences: args.include_sequences,
+        include_taxa: !args.no_taxa,
+        include_raw_lca: args.include_raw_lca,
+        functional_analysis: !args.no_functional_analysis,
+    }
+}
+
+/// Searches the peptides in the way set with the commandline arguments, and writes the results to `output`
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `args` - The arguments used to start the program
+/// * `peptides` - The peptides that are searched
+/// * `chunk_size` - The number of peptides that are searched at once
+/// * `output` - The writer to which the results are written
+///
+/// # Returns
+///
+/// Returns the statistics of the search (without the timing)
+///
+/// # Errors
+///
+/// Returns possible errors that occurred while writing the results
+fn write_search_results(
+    searcher: &Searcher,
+    args: &Arguments,
+    peptides: impl Iterator<Item = String>,
+    chunk_size: usize,
+    output: &mut impl Write,
+) -> Result<SearchStats, Box<dyn Error>> {
+    let cutoff = cutoff_policy(args);
+    let options = search_options(args);
+
+    let stats = match args.search_mode {
+        SearchMode::Search => write_all_peptides_chunked(
+            peptides,
+            chunk_size,
+            |chunk| search_all_peptides(searcher, chunk, cutoff, &options),
+            output,
+        )?,
+        SearchMode::Analysis => write_all_peptides_chunked(
+            peptides,
+            chunk_size,
+            |chunk| analyse_all_peptides(searcher, chunk, cutoff, &options),
+            output,
+        )?,
+        SearchMode::UnipeptTsv => write_all_peptides_unipept_tsv(
+            searcher,
+            peptides,
+            chunk_size,
+            // only the lca is part of the output, so the functional analysis is skipped
+            |chunk| analyse_all_peptides(
+                searcher,
+                chunk,
+                cutoff,
+                &SearchOptions {
+                    include_index: false,
+                    include_taxa: false,
+                    include_raw_lca: false,
+                    functional_analysis: false,
+                    ..options
+                },
+            ),
+            output,
+        )?,
+        SearchMode::Count => write_all_peptide_counts(
+            searcher,
+            peptides,
+            chunk_size,
+            &options,
+            args.matches_only,
+            output,
+        )?,
+    };
+
+    Ok(stats)
+}
+
 /// Custom trait implemented by types that have a value that represents NULL
 pub trait Nullable<T> {
     const NULL: T;
@@ -224,3 +607,204 @@ impl Nullable<u32> for u32 {
         *self == Self::NULL
     }
 }
+
+/// Custom trait implemented by the types that can be used as the elements of a suffix array
+/// This allows the `Searcher` to search in both 32-bit and 64-bit suffix arrays
+pub trait SuffixValue: Copy {
+    fn to_usize(self) -> usize;
+
+    fn to_i64(self) -> i64;
+
+    fn try_from_i64(value: i64) -> Option<Self>;
+}
+
+impl SuffixValue for i32 {
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+
+    #[inline]
+    fn try_from_i64(value: i64) -> Option<Self> {
+        i32::try_from(value).ok()
+    }
+}
+
+impl SuffixValue for i64 {
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn to_i64(self) -> i64 {
+        self
+    }
+
+    #[inline]
+    fn try_from_i64(value: i64) -> Option<Self> {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use clap::Parser;
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{LoadOptions, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
+
+    use crate::{Arguments, default_thread_count, load_or_build_suffix_array, MAX_DEFAULT_THREADS, read_peptides, run_benchmark, SuffixValue, thread_pool_builder, write_search_results};
+    use crate::sa_searcher::Searcher;
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+
+    #[test]
+    fn test_build_suffix_array_from_parsed_proteins() {
+        let args = Arguments::parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+            "--sparseness-factor", "2",
+        ]);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar).unwrap();
+        let (proteins, _) = Proteins::try_from_database_file(&args.database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+
+        // the SA built from the already parsed proteins equals the SA built from a separate parse of the database file
+        let (mut text, _) = Proteins::try_from_database_file_without_annotations(&args.database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+        let expected = build_sa(&mut text, &SAConstructionAlgorithm::LibSais, 2).unwrap();
+
+        assert_eq!(load_or_build_suffix_array(&args, &proteins).unwrap(), (2, expected));
+    }
+
+    #[test]
+    fn test_search_single_peptide() {
+        let args = Arguments::parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+            "--peptide", "DSDPSG",
+            "--search-mode", "search",
+        ]);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar).unwrap();
+        let (proteins, _) = Proteins::try_from_database_file(&args.database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+        let (sparseness_factor, sa) = load_or_build_suffix_array(&args, &proteins).unwrap();
+        let searcher = Searcher::new(
+            sa,
+            sparseness_factor,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            taxon_aggregator,
+            FunctionAggregator {}
+        );
+
+        let (peptides, chunk_size) = read_peptides(&args).unwrap();
+        let mut output = vec![];
+        let stats = write_search_results(&searcher, &args, peptides, chunk_size, &mut output).unwrap();
+
+        assert_eq!((stats.total_peptides, stats.matched), (1, 1));
+        let output: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(output["result"][0]["sequence"], "DSDPSG");
+        assert_eq!(output["result"][0]["proteins"][0]["uniprot_accession"], "P67890");
+
+        // a single peptide can not be combined with a search file
+        assert!(Arguments::try_parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+            "--peptide", "DSDPSG",
+            "--search-file", "peptides.txt",
+        ]).is_err());
+    }
+
+    #[test]
+    fn test_assume_uppercase() {
+        let search = |extra_args: &[&str]| {
+            let args = Arguments::parse_from([
+                ["suffixarray", "--database-file", "../testfiles/small_database.tsv", "--taxonomy", "../testfiles/small_taxonomy.tsv"].as_slice(),
+                extra_args,
+            ].concat());
+            let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar).unwrap();
+            let (proteins, _) = Proteins::try_from_database_file(&args.database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+            let (sparseness_factor, sa) = load_or_build_suffix_array(&args, &proteins).unwrap();
+            let searcher = Searcher::new(
+                sa,
+                sparseness_factor,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                taxon_aggregator,
+                FunctionAggregator {}
+            );
+
+            let (peptides, chunk_size) = read_peptides(&args)?;
+            let mut output = vec![];
+            write_search_results(&searcher, &args, peptides, chunk_size, &mut output)?;
+            Ok::<serde_json::Value, Box<dyn std::error::Error>>(serde_json::from_slice(&output).unwrap())
+        };
+
+        let expected = search(&["--peptide", "DSDPSG"]).unwrap();
+        assert_eq!(expected["result"][0]["uniprot_accession_numbers"][0], "P67890");
+        assert_eq!(search(&["--peptide", "DSDPSG", "--assume-uppercase"]).unwrap(), expected);
+
+        // lowercase peptides are rejected instead of silently not matching
+        assert!(search(&["--peptide", "dsdpsg", "--assume-uppercase"]).is_err());
+    }
+
+    #[test]
+    fn test_thread_count() {
+        let args = Arguments::parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+            "--threads", "3",
+        ]);
+        assert_eq!(thread_pool_builder(args.threads).build().unwrap().current_num_threads(), 3);
+
+        let args = Arguments::parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+        ]);
+        assert_eq!(args.threads, default_thread_count());
+        assert!(args.threads <= NonZeroUsize::new(MAX_DEFAULT_THREADS).unwrap());
+    }
+
+    #[test]
+    fn test_benchmark() {
+        let args = Arguments::parse_from([
+            "suffixarray",
+            "--database-file", "../testfiles/small_database.tsv",
+            "--taxonomy", "../testfiles/small_taxonomy.tsv",
+            "--peptide", "DSDPSG",
+            "--benchmark",
+        ]);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar).unwrap();
+        let (proteins, _) = Proteins::try_from_database_file(&args.database_file, &taxon_aggregator, &LoadOptions::default()).unwrap();
+        let (sparseness_factor, sa) = load_or_build_suffix_array(&args, &proteins).unwrap();
+        let searcher = Searcher::new(
+            sa,
+            sparseness_factor,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            taxon_aggregator,
+            FunctionAggregator {}
+        );
+
+        let peptides = ["DSDPSG", "GNAGLL", "WWWWWW"].iter().map(|peptide| peptide.to_string());
+        assert_eq!(run_benchmark(&searcher, &args, peptides).peptides, 3);
+    }
+
+    #[test]
+    fn test_suffix_value_conversion() {
+        assert_eq!(i32::try_from_i64(i32::MAX as i64), Some(i32::MAX));
+        assert_eq!(i32::try_from_i64(i32::MAX as i64 + 1), None);
+        assert_eq!(i64::try_from_i64(i64::MAX), Some(i64::MAX));
+    }
+}
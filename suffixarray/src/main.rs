@@ -3,6 +3,7 @@ use suffixarray::{Arguments, run};
 
 fn main() {
     let args = Arguments::parse();
+    env_logger::Builder::new().filter_level(args.log_level).init();
     if let Err(error) = run(args) {
         eprintln!("{}", error);
         std::process::exit(1);
@@ -1,40 +1,296 @@
-use crate::sa_searcher::{SearchAllSuffixesResult, Searcher};
+use crate::sa_searcher::{contains_sentinel, normalize_il, IndexedProtein, SearchAllSuffixesResult, Searcher};
+use crate::util::elapsed_ms;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
 use rayon::prelude::*;
-use sa_mappings::functionality::FunctionalAggregation;
+use sa_mappings::functionality::{AnnotationCache, FunctionalAggregation};
 use sa_mappings::proteins::Protein;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use umgap::rank::Rank;
+
+/// The maximum number of proteins of which the decoded functional annotations are cached during the analysis of a batch of peptides
+/// This bounds the memory used by the cache for large batches, only the least recently used proteins are decoded again
+pub const ANNOTATION_CACHE_CAPACITY: usize = 100_000;
+
+/// Enum representing the maximum amount of matches we want to process from the index for a peptide
+/// A `Constant` cutoff is used for every peptide, while a `LengthDependent` cutoff allows more matches for shorter peptides:
+/// `cutoff = base + factor * (max_length - peptide length)`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum CutoffPolicy {
+    Constant(usize),
+    LengthDependent {
+        base: usize,
+        factor: usize,
+        max_length: usize,
+    },
+}
+
+impl CutoffPolicy {
+    /// Calculates the cutoff for a peptide
+    ///
+    /// # Arguments
+    /// * `peptide_length` - The length of the peptide we want to search
+    ///
+    /// # Returns
+    ///
+    /// Returns the maximum amount of matches we want to process for a peptide with length `peptide_length`
+    pub fn cutoff(&self, peptide_length: usize) -> usize {
+        match *self {
+            CutoffPolicy::Constant(cutoff) => cutoff,
+            CutoffPolicy::LengthDependent { base, factor, max_length } => {
+                base.saturating_add(factor.saturating_mul(max_length.saturating_sub(peptide_length)))
+            }
+        }
+    }
+}
+
+/// The options that determine how peptides are searched and which analyses are added to the results
+///
+/// # Arguments
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `preserve_case` - Boolean indicating if we want to search case-sensitive, otherwise the peptide is uppercased before searching
+/// * `include_index` - Boolean indicating if we want to add the position of the peptide in the input to each result
+/// * `include_sequences` - Boolean indicating if we want to add the sequence of every matched protein to each search result
+/// * `include_taxa` - Boolean indicating if we want to add the taxon of every matched protein to each result, next to the number of proteins per taxon
+/// * `include_raw_lca` - Boolean indicating if we want to add the lca before it is snapped to a valid taxon to each result, next to the snapped `lca`
+/// * `functional_analysis` - Boolean indicating if we want to perform the functional analysis, otherwise `fa` is None for every result
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub equalize_i_and_l: bool,
+    pub clean_taxa: bool,
+    pub preserve_case: bool,
+    pub include_index: bool,
+    pub include_sequences: bool,
+    pub include_taxa: bool,
+    pub include_raw_lca: bool,
+    pub functional_analysis: bool,
+}
+
+/// The reasons why a peptide could not be searched in the index
+/// A peptide that could be searched but has no matches is not an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeptideSearchError {
+    /// The peptide is shorter than the sparseness factor of the index
+    TooShort,
+    /// The peptide contains the separation or termination character, so it could only match across 2 proteins
+    ContainsSeparator,
+    /// The deadline of the search passed before the matching proteins were retrieved
+    OutOfTime,
+}
+
+impl Display for PeptideSearchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeptideSearchError::TooShort => write!(f, "the peptide is shorter than the sparseness factor of the index"),
+            PeptideSearchError::ContainsSeparator => write!(f, "the peptide contains a separation or termination character"),
+            PeptideSearchError::OutOfTime => write!(f, "the search of the peptide did not finish before the deadline"),
+        }
+    }
+}
+
+impl Error for PeptideSearchError {}
 
 /// Struct representing a collection of `SearchResultWithAnalysis` or `SearchOnlyResult` results
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct OutputData<T: Serialize> {
     result: Vec<T>,
 }
 
+/// Struct representing an `OutputData` together with statistics about the search
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutputDataWithStats<T: Serialize> {
+    #[serde(flatten)]
+    output: OutputData<T>,
+    match_count_histogram: MatchCountHistogram,
+}
+
+/// Histogram of the number of matched proteins per peptide, bucketed per order of magnitude
+/// Bucket 0 counts the peptides with exactly 1 match, bucket `i` counts the peptides with `10^(i-1) + 1` up to `10^i` matches
+#[derive(Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct MatchCountHistogram {
+    buckets: Vec<usize>,
+}
+
+impl MatchCountHistogram {
+    /// Adds the match count of a single peptide to the histogram
+    ///
+    /// # Arguments
+    /// * `match_count` - The number of proteins matched by the peptide, peptides without matches are not counted
+    pub fn add(&mut self, match_count: usize) {
+        if match_count == 0 {
+            return;
+        }
+
+        let mut bucket = 0;
+        let mut upper_bound: usize = 1;
+        while match_count > upper_bound {
+            upper_bound = upper_bound.saturating_mul(10);
+            bucket += 1;
+        }
+
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    /// Returns the number of peptides per bucket
+    pub fn buckets(&self) -> &[usize] {
+        &self.buckets
+    }
+
+    /// Returns the total number of peptides counted in the histogram
+    pub fn total(&self) -> usize {
+        self.buckets.iter().sum()
+    }
+}
+
 /// Struct representing the search result of the `sequence` in the index, including the analyses
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct SearchResultWithAnalysis {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_index: Option<usize>,
     sequence: String,
     lca: Option<usize>,
-    taxa: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_lca: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taxa: Option<Vec<usize>>,
+    taxon_protein_counts: HashMap<usize, usize>,
     uniprot_accession_numbers: Vec<String>,
     fa: Option<FunctionalAggregation>,
     cutoff_used: bool,
 }
 
 /// Struct representing the search result of the `sequence` in the index (without the analyses)
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct SearchOnlyResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_index: Option<usize>,
     sequence: String,
     proteins: Vec<ProteinInfo>,
     cutoff_used: bool,
+    truncated: bool,
+}
+
+impl SearchOnlyResult {
+    /// Limits the number of proteins in the result, and marks the result as truncated if proteins were removed
+    ///
+    /// # Arguments
+    /// * `max_proteins` - The maximum number of proteins that are kept
+    pub fn truncate_proteins(&mut self, max_proteins: usize) {
+        if self.proteins.len() > max_proteins {
+            self.proteins.truncate(max_proteins);
+            self.truncated = true;
+        }
+    }
+}
+
+impl OutputData<SearchOnlyResult> {
+    /// Limits the number of proteins returned for every peptide, this only limits the output and not the number of matches that is processed
+    ///
+    /// # Arguments
+    /// * `max_proteins` - The maximum number of proteins that are kept per peptide
+    pub fn truncate_proteins(&mut self, max_proteins: usize) {
+        self.result.iter_mut().for_each(|result| result.truncate_proteins(max_proteins));
+    }
+}
+
+/// Struct representing a summary of a search run over a list of peptides
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SearchStats {
+    pub total_peptides: usize,
+    pub matched: usize,
+    pub unmatched: usize,
+    pub cutoff_used: usize,
+    pub total_time_ms: f64,
+    pub average_time_ms: f64,
+}
+
+impl SearchStats {
+    /// Sets the total time of the search run, and the average search time per peptide
+    ///
+    /// # Arguments
+    /// * `total_time_ms` - The time in ms it took to search all peptides
+    pub fn set_total_time(&mut self, total_time_ms: f64) {
+        self.total_time_ms = total_time_ms;
+        self.average_time_ms = if self.total_peptides == 0 {
+            0.0
+        } else {
+            total_time_ms / self.total_peptides as f64
+        };
+    }
+}
+
+/// Trait implemented by the search results of a single peptide
+pub trait PeptideResult {
+    /// Shifts the position of the peptide in the input by `offset`, if the position is part of the result
+    fn offset_input_index(&mut self, offset: usize);
+
+    /// Returns true if the cutoff was used for the peptide
+    fn cutoff_used(&self) -> bool;
+}
+
+impl PeptideResult for SearchResultWithAnalysis {
+    fn offset_input_index(&mut self, offset: usize) {
+        self.input_index = self.input_index.map(|index| index + offset);
+    }
+
+    fn cutoff_used(&self) -> bool {
+        self.cutoff_used
+    }
+}
+
+impl PeptideResult for SearchOnlyResult {
+    fn offset_input_index(&mut self, offset: usize) {
+        self.input_index = self.input_index.map(|index| index + offset);
+    }
+
+    fn cutoff_used(&self) -> bool {
+        self.cutoff_used
+    }
 }
 
 /// Struct that represents all information known about a certain protein in our database
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ProteinInfo {
     taxon: usize,
     uniprot_accession: String,
     functional_annotations: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<String>,
+}
+
+/// Prepares a peptide of the input to be searched in the index
+///
+/// # Arguments
+/// * `peptide` - The peptide as it is read from the input
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `preserve_case` - Boolean indicating if we want to search case-sensitive, otherwise the peptide is uppercased
+///
+/// # Returns
+///
+/// Returns the peptide without trailing newline, it is only copied when it has to be uppercased or normalized
+fn prepare_peptide(peptide: &str, equalize_i_and_l: bool, preserve_case: bool) -> Cow<'_, [u8]> {
+    let peptide = peptide.strip_suffix('\n').unwrap_or(peptide);
+    let mut peptide: Cow<[u8]> = if preserve_case {
+        Cow::Borrowed(peptide.as_bytes())
+    } else {
+        Cow::Owned(peptide.to_uppercase().into_bytes())
+    };
+    if equalize_i_and_l {
+        normalize_il(peptide.to_mut());
+    }
+    peptide
 }
 
 /// Searches the `peptide` in the index multithreaded and retrieves the matching proteins
@@ -42,32 +298,94 @@ pub struct ProteinInfo {
 /// # Arguments
 /// * `searcher` - The Searcher which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
-/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
-/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptide is searched, only `equalize_i_and_l`, `clean_taxa` and `preserve_case` are used
 ///
 /// # Returns
 ///
-/// Returns Some if matches are found.
+/// Returns Ok if the peptide could be searched, the list of proteins is empty if the peptide does not have any matches.
 /// The first argument is true if the cutoff is used, otherwise false
-/// The second argument is a list of all matching proteins for the peptide
-/// Returns None if the peptides does not have any matches, or if the peptide is shorter than the sparseness factor k used in the index
+/// The second argument is a list of all matching proteins for the peptide, together with the index of each protein
+///
+/// By default the peptide is uppercased, since the proteins in the index are uppercased as well.
+/// When `preserve_case` is set, lowercase residues (e.g. soft-masked regions) are kept and only match lowercase residues in the index.
+///
+/// # Errors
+///
+/// Returns `TooShort` if the peptide is shorter than the sparseness factor k used in the index,
+/// and `ContainsSeparator` if the peptide contains the separation or termination character
 pub fn search_proteins_for_peptide<'a>(
     searcher: &'a Searcher,
     peptide: &str,
-    cutoff: usize,
-    equalize_i_and_l: bool,
-    clean_taxa: bool,
-) -> Option<(bool, Vec<&'a Protein>)> {
-    let peptide = peptide.strip_suffix('\n').unwrap_or(peptide).to_uppercase();
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+) -> Result<(bool, Vec<IndexedProtein<'a>>), PeptideSearchError> {
+    search_proteins_for_peptide_until(searcher, peptide, cutoff, options, None)
+}
+
+/// Searches the `peptide` in the index and retrieves the matching proteins, unless the search takes longer than a deadline
+/// The deadline is checked before the peptide is searched and before the matching proteins are retrieved,
+/// so a search that already started is never interrupted
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptide is searched, only `equalize_i_and_l`, `clean_taxa` and `preserve_case` are used
+/// * `deadline` - The moment after which the search is given up
+///
+/// # Returns
+///
+/// Returns the same result as `search_proteins_for_peptide`
+///
+/// # Errors
+///
+/// Returns `OutOfTime` if the deadline passed, next to the errors of `search_proteins_for_peptide`
+pub fn search_proteins_for_peptide_with_deadline<'a>(
+    searcher: &'a Searcher,
+    peptide: &str,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+    deadline: Instant,
+) -> Result<(bool, Vec<IndexedProtein<'a>>), PeptideSearchError> {
+    search_proteins_for_peptide_until(searcher, peptide, cutoff, options, Some(deadline))
+}
+
+/// Searches the `peptide` in the index and retrieves the matching proteins, with an optional deadline
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptide is searched, only `equalize_i_and_l`, `clean_taxa` and `preserve_case` are used
+/// * `deadline` - The moment after which the search is given up, None if the search can take as long as needed
+///
+/// # Returns
+///
+/// Returns the same result as `search_proteins_for_peptide_with_deadline`
+fn search_proteins_for_peptide_until<'a>(
+    searcher: &'a Searcher,
+    peptide: &str,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Result<(bool, Vec<IndexedProtein<'a>>), PeptideSearchError> {
+    let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    let peptide = prepare_peptide(peptide, options.equalize_i_and_l, options.preserve_case);
 
     // words that are shorter than the sample rate are not searchable
     if peptide.len() < searcher.sparseness_factor as usize {
-        return None;
+        return Err(PeptideSearchError::TooShort);
+    }
+    if contains_sentinel(&peptide) {
+        return Err(PeptideSearchError::ContainsSeparator);
+    }
+    if out_of_time() {
+        return Err(PeptideSearchError::OutOfTime);
     }
 
     let suffix_search =
-        searcher.search_matching_suffixes(peptide.as_bytes(), cutoff, equalize_i_and_l);
+        searcher.search_matching_suffixes(&peptide, cutoff.cutoff(peptide.len()), options.equalize_i_and_l);
     let mut cutoff_used = false;
     let suffixes = match suffix_search {
         SearchAllSuffixesResult::MaxMatches(matched_suffixes) => {
@@ -76,16 +394,21 @@ pub fn search_proteins_for_peptide<'a>(
         }
         SearchAllSuffixesResult::SearchResult(matched_suffixes) => matched_suffixes,
         SearchAllSuffixesResult::NoMatches => {
-            return None;
+            return Ok((false, vec![]));
         }
     };
 
+    // retrieving the proteins is the expensive part when the peptide has a lot of matches
+    if out_of_time() {
+        return Err(PeptideSearchError::OutOfTime);
+    }
+
     let mut proteins = searcher.retrieve_proteins(&suffixes);
-    if clean_taxa {
-        proteins.retain(|protein| searcher.taxon_valid(protein))
+    if options.clean_taxa {
+        proteins.retain(|(_, protein)| searcher.taxon_valid(protein))
     }
 
-    Some((cutoff_used, proteins))
+    Ok((cutoff_used, proteins))
 }
 
 
@@ -95,9 +418,8 @@ pub fn search_proteins_for_peptide<'a>(
 /// # Arguments
 /// * `searcher` - The Searcher which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
-/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
-/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptide is searched, `include_sequences` determines if the sequence of every matched protein is added to the result
 ///
 /// # Returns
 ///
@@ -106,28 +428,35 @@ pub fn search_proteins_for_peptide<'a>(
 pub fn search_peptide_retrieve_annotations(
     searcher: &Searcher,
     peptide: &str,
-    cutoff: usize,
-    equalize_i_and_l: bool,
-    clean_taxa: bool,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
 ) -> Option<SearchOnlyResult> {
-    let (cutoff_used, proteins) =
-        search_proteins_for_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa)?;
+    let (cutoff_used, indexed_proteins) = search_proteins_for_peptide(searcher, peptide, cutoff, options).ok()?;
+    if indexed_proteins.is_empty() {
+        return None;
+    }
 
+    let proteins: Vec<&Protein> = indexed_proteins.iter().map(|&(_, protein)| protein).collect();
     let annotations = searcher.get_all_functional_annotations(&proteins);
 
     let mut protein_info: Vec<ProteinInfo> = vec![];
-    for (&protein, annotations) in proteins.iter().zip(annotations) {
+    for (&(protein_index, protein), annotations) in indexed_proteins.iter().zip(annotations) {
         protein_info.push(ProteinInfo {
             taxon: protein.taxon_id,
             uniprot_accession: protein.uniprot_id.clone(),
             functional_annotations: annotations,
+            sequence: options
+                .include_sequences
+                .then(|| String::from_utf8_lossy(searcher.protein_sequence(protein_index)).to_string()),
         })
     }
 
     Some(SearchOnlyResult {
+        input_index: None,
         sequence: peptide.to_string(),
         proteins: protein_info,
         cutoff_used,
+        truncated: false,
     })
 }
 
@@ -137,9 +466,10 @@ pub fn search_peptide_retrieve_annotations(
 /// # Arguments
 /// * `searcher` - The Searcher which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
-/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
-/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptide is searched, `functional_analysis` determines if the functional analysis is performed, otherwise `fa` is None.
+///   The other options that determine what is added to the result are ignored, the result always contains the taxa and the raw lca
+/// * `annotation_cache` - Optional cache shared with other peptides, so the functional annotations of a protein are only decoded once
 ///
 /// # Returns
 ///
@@ -148,22 +478,23 @@ pub fn search_peptide_retrieve_annotations(
 pub fn analyse_peptide(
     searcher: &Searcher,
     peptide: &str,
-    cutoff: usize,
-    equalize_i_and_l: bool,
-    clean_taxa: bool,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+    annotation_cache: Option<&AnnotationCache>,
 ) -> Option<SearchResultWithAnalysis> {
-    let (cutoff_used, mut proteins) =
-        search_proteins_for_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa)?;
+    let (cutoff_used, mut indexed_proteins) = search_proteins_for_peptide(searcher, peptide, cutoff, options).ok()?;
 
-    if clean_taxa {
-        proteins.retain(|protein| searcher.taxon_valid(protein))
+    if options.clean_taxa {
+        indexed_proteins.retain(|(_, protein)| searcher.taxon_valid(protein))
     }
+    let proteins: Vec<&Protein> = indexed_proteins.iter().map(|&(_, protein)| protein).collect();
 
-    // calculate the lca
-    let lca = if cutoff_used {
-        Some(1)
+    // calculate the lca, both as it is aggregated and snapped to a valid taxon
+    let (raw_lca, lca) = if cutoff_used {
+        (Some(1), Some(1))
     } else {
-        searcher.retrieve_lca(&proteins)
+        let raw_lca = searcher.retrieve_raw_lca(&proteins);
+        (raw_lca, raw_lca.map(|taxon| searcher.taxonomy().snap_taxon(taxon)))
     };
 
     // return None if the LCA is none
@@ -171,20 +502,30 @@ pub fn analyse_peptide(
 
     let mut uniprot_accession_numbers = vec![];
     let mut taxa = vec![];
+    let mut taxon_protein_counts: HashMap<usize, usize> = HashMap::new();
 
-    for protein in &proteins {
-        taxa.push(protein.taxon_id);
-        uniprot_accession_numbers.push(protein.uniprot_id.clone());
+    for (uniprot_id, taxon_id) in proteins.iter().flat_map(|protein| protein.accessions()) {
+        taxa.push(taxon_id);
+        uniprot_accession_numbers.push(uniprot_id.to_string());
+        *taxon_protein_counts.entry(taxon_id).or_insert(0) += 1;
     }
 
-    let fa = searcher.retrieve_function(&proteins);
+    // decoding the functional annotations is expensive, so only do this when the functional analysis is needed
+    let fa = match (options.functional_analysis, annotation_cache) {
+        (true, Some(cache)) => searcher.retrieve_function_cached(&indexed_proteins, cache),
+        (true, None) => searcher.retrieve_function(&proteins),
+        (false, _) => None,
+    };
     // output the result
     Some(SearchResultWithAnalysis {
+        input_index: None,
         sequence: peptide.to_string(),
         lca,
+        raw_lca,
         cutoff_used,
         uniprot_accession_numbers,
-        taxa,
+        taxa: Some(taxa),
+        taxon_protein_counts,
         fa,
     })
 }
@@ -195,9 +536,8 @@ pub fn analyse_peptide(
 /// # Arguments
 /// * `searcher` - The Searcher which contains the protein database
 /// * `peptides` - List of peptides we want to search in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
-/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
-/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptides are searched and which analyses are added to the results, `include_sequences` is ignored
 ///
 /// # Returns
 ///
@@ -205,14 +545,25 @@ pub fn analyse_peptide(
 pub fn analyse_all_peptides(
     searcher: &Searcher,
     peptides: &Vec<String>,
-    cutoff: usize,
-    equalize_i_and_l: bool,
-    clean_taxa: bool,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
 ) -> OutputData<SearchResultWithAnalysis> {
+    // peptides of the same batch often match the same proteins, share the decoded annotations between them
+    let annotation_cache = options.functional_analysis.then(|| AnnotationCache::with_capacity(ANNOTATION_CACHE_CAPACITY));
+
     let res: Vec<SearchResultWithAnalysis> = peptides
         .par_iter()
+        .enumerate()
         // calculate the results
-        .map(|peptide| analyse_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa))
+        .map(|(index, peptide)| {
+            analyse_peptide(searcher, peptide, cutoff, options, annotation_cache.as_ref())
+                .map(|result| SearchResultWithAnalysis {
+                    input_index: options.include_index.then_some(index),
+                    taxa: if options.include_taxa { result.taxa } else { None },
+                    raw_lca: if options.include_raw_lca { result.raw_lca } else { None },
+                    ..result
+                })
+        })
         // remove the None's
         .filter_map(|search_result| search_result)
         .collect();
@@ -220,15 +571,43 @@ pub fn analyse_all_peptides(
     OutputData { result: res }
 }
 
+/// Searches the list of `peptides` in the index multithreaded, performs the functional and taxonomic analyses
+/// and keeps track of how many proteins each peptide matched, which can be used to choose a suitable `cutoff`
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptides` - List of peptides we want to search in the index
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptides are searched and which analyses are added to the results, `include_sequences` is ignored
+///
+/// # Returns
+///
+/// Returns an `OutputDataWithStats<SearchResultWithAnalysis>` object with the search and analyses results for the peptides
+/// and a histogram of the number of matched proteins of every peptide in the results
+pub fn analyse_all_peptides_with_stats(
+    searcher: &Searcher,
+    peptides: &Vec<String>,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+) -> OutputDataWithStats<SearchResultWithAnalysis> {
+    let output = analyse_all_peptides(searcher, peptides, cutoff, options);
+
+    let mut match_count_histogram = MatchCountHistogram::default();
+    for result in output.result.iter() {
+        match_count_histogram.add(result.uniprot_accession_numbers.len());
+    }
+
+    OutputDataWithStats { output, match_count_histogram }
+}
+
 /// Searches the list of `peptides` in the index and retrieves all related information about the found proteins
 /// This does NOT perform any of the analyses
 /// 
 /// # Arguments
 /// * `searcher` - The Searcher which contains the protein database
 /// * `peptides` - List of peptides we want to search in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
-/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
-/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptides are searched and if the index and protein sequences are added to the results, the analysis options are ignored
 ///
 /// # Returns
 ///
@@ -236,17 +615,819 @@ pub fn analyse_all_peptides(
 pub fn search_all_peptides(
     searcher: &Searcher,
     peptides: &Vec<String>,
-    cutoff: usize,
-    equalize_i_and_l: bool,
-    clean_taxa: bool,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
 ) -> OutputData<SearchOnlyResult> {
     let res: Vec<SearchOnlyResult> = peptides
         .par_iter()
+        .enumerate()
         // calculate the results
-        .map(|peptide| search_peptide_retrieve_annotations(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa))
+        .map(|(index, peptide)| {
+            search_peptide_retrieve_annotations(searcher, peptide, cutoff, options)
+                .map(|result| SearchOnlyResult {
+                    input_index: options.include_index.then_some(index),
+                    ..result
+                })
+        })
         // remove None's
         .filter_map(|search_result| search_result)
         .collect();
 
     OutputData { result: res }
 }
+
+/// Searches the `peptides` in chunks of `chunk_size` peptides and writes the results as a single `OutputData` JSON object
+/// Only a single chunk of peptides and its results are kept in memory at a time, so arbitrarily large inputs can be searched
+///
+/// # Arguments
+/// * `peptides` - Iterator over the peptides we want to search in the index
+/// * `chunk_size` - The maximum number of peptides that are searched (multithreaded) at once
+/// * `search_chunk` - Function that searches a chunk of peptides, e.g. `search_all_peptides` or `analyse_all_peptides`
+/// * `writer` - The writer to which the results are written
+///
+/// # Returns
+///
+/// Returns the counters of the `SearchStats` (without the timing) if all the results were written,
+/// the written JSON is identical to the serialization of `search_chunk` on all peptides at once
+///
+/// # Errors
+///
+/// Returns the errors that occurred while serializing or writing the results
+pub fn write_all_peptides_chunked<T: Serialize + PeptideResult>(
+    peptides: impl Iterator<Item = String>,
+    chunk_size: usize,
+    mut search_chunk: impl FnMut(&Vec<String>) -> OutputData<T>,
+    writer: &mut impl Write,
+) -> Result<SearchStats, Box<dyn Error>> {
+    let mut peptides = peptides;
+    let mut offset = 0;
+    let mut first_result = true;
+    let mut stats = SearchStats::default();
+
+    writer.write_all(b"{\"result\":[")?;
+    loop {
+        let chunk: Vec<String> = peptides.by_ref().take(chunk_size.max(1)).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        for mut result in search_chunk(&chunk).result {
+            // the input index of a result is relative to the start of its chunk
+            result.offset_input_index(offset);
+            stats.matched += 1;
+            stats.cutoff_used += result.cutoff_used() as usize;
+            if !first_result {
+                writer.write_all(b",")?;
+            }
+            first_result = false;
+            serde_json::to_writer(&mut *writer, &result)?;
+        }
+
+        offset += chunk.len();
+        writer.flush()?;
+    }
+    writer.write_all(b"]}")?;
+
+    stats.total_peptides = offset;
+    stats.unmatched = stats.total_peptides - stats.matched;
+    Ok(stats)
+}
+
+/// Counts the matches of the `peptides` in chunks of `chunk_size` peptides and writes a line `peptide\tmatch_count` for every peptide
+/// The matching proteins are not retrieved and no analysis is done, so this is the fastest way to search the peptides
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptides` - Iterator over the peptides we want to search in the index
+/// * `chunk_size` - The maximum number of peptides that are searched (multithreaded) at once
+/// * `options` - How the peptides are searched, only `equalize_i_and_l` and `preserve_case` are used
+/// * `matches_only` - Boolean indicating if we want to leave out the lines of the peptides without matches,
+///   the lines then no longer align with the input peptides
+/// * `writer` - The writer to which the counts are written
+///
+/// # Returns
+///
+/// Returns the counters of the `SearchStats` (without the timing), the cutoff is never used when counting
+/// Peptides that are shorter than the sparseness factor k used in the index have 0 matches
+///
+/// # Errors
+///
+/// Returns the errors that occurred while writing the counts
+pub fn write_all_peptide_counts(
+    searcher: &Searcher,
+    peptides: impl Iterator<Item = String>,
+    chunk_size: usize,
+    options: &SearchOptions,
+    matches_only: bool,
+    writer: &mut impl Write,
+) -> Result<SearchStats, Box<dyn Error>> {
+    let mut peptides = peptides;
+    let mut stats = SearchStats::default();
+
+    loop {
+        let chunk: Vec<String> = peptides.by_ref().take(chunk_size.max(1)).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        let counts: Vec<usize> = chunk
+            .par_iter()
+            .map(|peptide| {
+                let search_string = prepare_peptide(peptide, options.equalize_i_and_l, options.preserve_case);
+                if search_string.len() < searcher.sparseness_factor as usize {
+                    0
+                } else {
+                    searcher.count_matching_suffixes(&search_string, options.equalize_i_and_l)
+                }
+            })
+            .collect();
+
+        for (peptide, count) in chunk.iter().zip(counts) {
+            stats.matched += (count > 0) as usize;
+            if matches_only && count == 0 {
+                continue;
+            }
+            writeln!(writer, "{}\t{}", peptide.strip_suffix('\n').unwrap_or(peptide), count)?;
+        }
+
+        stats.total_peptides += chunk.len();
+        writer.flush()?;
+    }
+
+    stats.unmatched = stats.total_peptides - stats.matched;
+    Ok(stats)
+}
+
+/// The header of the TSV output that is compatible with the `pept2lca` output of the Unipept CLI
+pub const UNIPEPT_TSV_HEADER: &str = "peptide\ttaxon_id\ttaxon_name\ttaxon_rank";
+
+/// Searches the `peptides` in chunks of `chunk_size` peptides and writes the lowest common ancestor of every matched peptide
+/// as TSV with the same columns as the `pept2lca` output of the Unipept CLI: peptide, taxon id, taxon name and taxon rank
+/// Only a single chunk of peptides and its results are kept in memory at a time, peptides without matches are left out like Unipept does
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database and the taxonomy used to look up the names and ranks
+/// * `peptides` - Iterator over the peptides we want to search in the index
+/// * `chunk_size` - The maximum number of peptides that are searched (multithreaded) at once
+/// * `search_chunk` - Function that analyses a chunk of peptides, e.g. `analyse_all_peptides` without the functional analysis
+/// * `writer` - The writer to which the TSV is written
+///
+/// # Returns
+///
+/// Returns the counters of the `SearchStats` (without the timing) if all the results were written
+///
+/// # Errors
+///
+/// Returns the errors that occurred while writing the results
+pub fn write_all_peptides_unipept_tsv(
+    searcher: &Searcher,
+    peptides: impl Iterator<Item = String>,
+    chunk_size: usize,
+    mut search_chunk: impl FnMut(&Vec<String>) -> OutputData<SearchResultWithAnalysis>,
+    writer: &mut impl Write,
+) -> Result<SearchStats, Box<dyn Error>> {
+    let mut peptides = peptides;
+    let mut stats = SearchStats::default();
+    let taxonomy = searcher.taxonomy();
+
+    writeln!(writer, "{}", UNIPEPT_TSV_HEADER)?;
+    loop {
+        let chunk: Vec<String> = peptides.by_ref().take(chunk_size.max(1)).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        for result in search_chunk(&chunk).result {
+            stats.matched += 1;
+            stats.cutoff_used += result.cutoff_used as usize;
+
+            // a result always has an lca, peptides without lca are not part of the results
+            let Some(lca) = result.lca else {
+                continue;
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                result.sequence,
+                lca,
+                taxonomy.taxon_name(lca).unwrap_or_default(),
+                taxonomy.taxon_rank(lca).map(|rank| rank.to_string()).unwrap_or_default()
+            )?;
+        }
+
+        stats.total_peptides += chunk.len();
+        writer.flush()?;
+    }
+
+    stats.unmatched = stats.total_peptides - stats.matched;
+    Ok(stats)
+}
+
+/// Struct representing the taxonomic profile of a list of peptides: for every rank, the number of peptides per taxon of that rank
+/// A peptide is counted for the lca of its matched proteins and for every ancestor of that lca that has a rank
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct TaxonomicProfile {
+    /// The number of peptides that have an lca
+    pub peptides_with_lca: usize,
+    /// For every rank (e.g. species or genus), the number of peptides per taxon of that rank
+    pub ranks: HashMap<String, HashMap<usize, usize>>,
+}
+
+/// Calculates the taxonomic profile of the analysed peptides by walking the lineage of the lca of every peptide
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the taxonomy used to look up the lineages and ranks
+/// * `output` - The analysis results of the peptides, e.g. of `analyse_all_peptides` without the functional analysis
+///
+/// # Returns
+///
+/// Returns the number of peptides per taxon for every rank
+pub fn taxonomic_profile(searcher: &Searcher, output: &OutputData<SearchResultWithAnalysis>) -> TaxonomicProfile {
+    let taxonomy = searcher.taxonomy();
+    let mut profile = TaxonomicProfile::default();
+
+    for lca in output.result.iter().filter_map(|result| result.lca) {
+        profile.peptides_with_lca += 1;
+        for taxon in taxonomy.lineage(lca) {
+            match taxonomy.taxon_rank(taxon) {
+                None | Some(Rank::NoRank) => {}
+                Some(rank) => *profile.ranks.entry(rank.to_string()).or_default().entry(taxon).or_insert(0) += 1,
+            }
+        }
+    }
+
+    profile
+}
+
+/// Searches the list of `peptides` in the index multithreaded and returns the taxa that are the lca of the most peptides
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptides` - List of peptides we want to search in the index
+/// * `k` - The maximum number of taxa that are returned
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptides are searched, the analysis options are ignored since only the lca of every peptide is needed
+///
+/// # Returns
+///
+/// Returns at most `k` taxa together with the number of peptides that have the taxon as lca,
+/// sorted descending by the number of peptides (and ascending by taxon id for taxa with the same number of peptides)
+pub fn top_taxa(
+    searcher: &Searcher,
+    peptides: &Vec<String>,
+    k: usize,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+) -> Vec<(usize, usize)> {
+    // only the lca of every peptide is needed
+    let options = SearchOptions {
+        equalize_i_and_l: options.equalize_i_and_l,
+        clean_taxa: options.clean_taxa,
+        preserve_case: options.preserve_case,
+        ..Default::default()
+    };
+    let output = analyse_all_peptides(searcher, peptides, cutoff, &options);
+
+    let mut peptide_counts: HashMap<usize, usize> = HashMap::new();
+    for lca in output.result.iter().filter_map(|result| result.lca) {
+        *peptide_counts.entry(lca).or_insert(0) += 1;
+    }
+
+    let mut taxa: Vec<(usize, usize)> = peptide_counts.into_iter().collect();
+    taxa.sort_unstable_by(|(taxon1, count1), (taxon2, count2)| count2.cmp(count1).then(taxon1.cmp(taxon2)));
+    taxa.truncate(k);
+    taxa
+}
+
+/// Finds the proteins that are not matched by any of the `peptides`, e.g. to calculate which fraction of the database is covered by a sample
+/// Every matched protein is marked in a bitset that is shared between the threads searching the peptides
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptides` - List of peptides we want to search in the index
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide,
+///   only the proteins of the processed matches are marked as covered
+/// * `options` - How the peptides are searched, only `equalize_i_and_l`, `clean_taxa` and `preserve_case` are used
+///
+/// # Returns
+///
+/// Returns the proteins that are not matched by any peptide, in the order of the database
+pub fn uncovered_proteins<'a>(
+    searcher: &'a Searcher,
+    peptides: &Vec<String>,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+) -> Vec<&'a Protein> {
+    let proteins = searcher.proteins();
+    let covered: Vec<AtomicU64> = (0..proteins.len().div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+
+    peptides.par_iter().for_each(|peptide| {
+        let Ok((_, matched_proteins)) = search_proteins_for_peptide(searcher, peptide, cutoff, options) else {
+            return;
+        };
+        for (index, _) in matched_proteins {
+            covered[index / 64].fetch_or(1 << (index % 64), Ordering::Relaxed);
+        }
+    });
+
+    proteins
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| covered[index / 64].load(Ordering::Relaxed) & (1 << (index % 64)) == 0)
+        .map(|(_, protein)| protein)
+        .collect()
+}
+
+/// Struct representing the throughput and latency percentiles of a benchmark run over a list of peptides
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BenchmarkReport {
+    pub peptides: usize,
+    pub total_time_ms: f64,
+    pub peptides_per_second: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl BenchmarkReport {
+    /// Creates the report of a benchmark run from the latencies of the searched peptides
+    ///
+    /// # Arguments
+    /// * `latencies_ms` - The time in ms it took to search every peptide
+    /// * `total_time_ms` - The time in ms it took to search all peptides
+    ///
+    /// # Returns
+    ///
+    /// Returns the report with the number of peptides, the throughput and the latency percentiles (nearest-rank)
+    pub fn from_latencies(mut latencies_ms: Vec<f64>, total_time_ms: f64) -> Self {
+        latencies_ms.sort_unstable_by(f64::total_cmp);
+
+        let percentile = |percentile: usize| {
+            // nearest-rank method: the smallest latency such that `percentile`% of the latencies is at most this latency
+            let rank = (percentile * latencies_ms.len()).div_ceil(100);
+            latencies_ms.get(rank.saturating_sub(1)).copied().unwrap_or(0.0)
+        };
+
+        BenchmarkReport {
+            peptides: latencies_ms.len(),
+            total_time_ms,
+            peptides_per_second: if total_time_ms > 0.0 {
+                latencies_ms.len() as f64 / total_time_ms * 1e3
+            } else {
+                0.0
+            },
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            max_ms: latencies_ms.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+/// Searches and analyses the list of `peptides` multithreaded, and measures the latency of every peptide
+/// The results of the analyses are not kept, only the timing is reported
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `peptides` - List of peptides we want to search in the index
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `options` - How the peptides are searched and which analyses are included in the measured time
+///
+/// # Returns
+///
+/// Returns a `BenchmarkReport` with the throughput and latency percentiles of the search
+pub fn benchmark_peptides(
+    searcher: &Searcher,
+    peptides: &Vec<String>,
+    cutoff: CutoffPolicy,
+    options: &SearchOptions,
+) -> BenchmarkReport {
+    let start_time = Instant::now();
+    let latencies_ms: Vec<f64> = peptides
+        .par_iter()
+        .map(|peptide| {
+            let peptide_start_time = Instant::now();
+            analyse_peptide(searcher, peptide, cutoff, options, None);
+            elapsed_ms(peptide_start_time)
+        })
+        .collect();
+
+    BenchmarkReport::from_latencies(latencies_ms, elapsed_ms(start_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::functionality::{AnnotationCache, FunctionAggregator};
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
+
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use crate::peptide_search::{CutoffPolicy, MatchCountHistogram, SearchOptions, analyse_all_peptides, analyse_all_peptides_with_stats, analyse_peptide, benchmark_peptides, BenchmarkReport, PeptideSearchError, search_all_peptides, search_proteins_for_peptide, search_proteins_for_peptide_with_deadline, SearchStats, top_taxa, uncovered_proteins, UNIPEPT_TSV_HEADER, write_all_peptide_counts, write_all_peptides_chunked, write_all_peptides_unipept_tsv};
+    use crate::sa_searcher::Searcher;
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+
+    fn get_example_searcher() -> Searcher {
+        get_searcher("MACWY-MacwY$", &[6, 7])
+    }
+
+    fn get_searcher(text: &str, taxa: &[usize]) -> Searcher {
+        let text = text.to_string().into_bytes();
+        let sa = build_sa(&mut text.clone(), &SAConstructionAlgorithm::LibSais, 1).unwrap();
+        let proteins = Proteins {
+            input_string: text.into(),
+            proteins: taxa
+                .iter()
+                .enumerate()
+                .map(|(i, &taxon_id)| Protein {
+                    uniprot_id: format!("P{}", i + 1),
+                    taxon_id,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![],
+                })
+                .collect(),
+        };
+
+        Searcher::new(
+            sa,
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        )
+    }
+
+    #[test]
+    fn test_search_preserve_case() {
+        let searcher = get_example_searcher();
+
+        // the lowercase peptide only matches the lowercase (masked) region when the case is preserved
+        let (_, proteins) = search_proteins_for_peptide(&searcher, "acw", CutoffPolicy::Constant(10000), &SearchOptions { preserve_case: true, ..Default::default() }).unwrap();
+        assert_eq!(proteins.len(), 1);
+        assert_eq!(proteins[0].1.uniprot_id, "P2");
+
+        // by default the peptide is uppercased
+        let (_, proteins) = search_proteins_for_peptide(&searcher, "acw", CutoffPolicy::Constant(10000), &SearchOptions::default()).unwrap();
+        assert_eq!(proteins.len(), 1);
+        assert_eq!(proteins[0].1.uniprot_id, "P1");
+    }
+
+    #[test]
+    fn test_include_input_index() {
+        let searcher = get_example_searcher();
+        let peptides = vec!["QQQ".to_string(), "ACW".to_string(), "GGG".to_string(), "WY".to_string()];
+
+        let output = search_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, ..Default::default() });
+        let indices: Vec<Option<usize>> = output.result.iter().map(|result| result.input_index).collect();
+        assert_eq!(indices, vec![Some(1), Some(3)]);
+
+        let output = analyse_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, include_taxa: true, functional_analysis: true, ..Default::default() });
+        let indices: Vec<Option<usize>> = output.result.iter().map(|result| result.input_index).collect();
+        assert_eq!(indices, vec![Some(1), Some(3)]);
+
+        // the index is omitted when it is not requested
+        let output = search_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        assert!(output.result.iter().all(|result| result.input_index.is_none()));
+        assert!(!serde_json::to_string(&output.result[0]).unwrap().contains("input_index"));
+    }
+
+    #[test]
+    fn test_taxon_protein_counts() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+
+        let result = analyse_peptide(&searcher, "AC", CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() }, None).unwrap();
+        assert_eq!(result.taxon_protein_counts, HashMap::from([(6, 2), (7, 1)]));
+        assert_eq!(result.taxa.map(|taxa| taxa.len()), Some(3));
+
+        // the list of taxa is only kept when requested
+        let output = analyse_all_peptides(&searcher, &vec!["AC".to_string()], CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() });
+        assert_eq!(output.result[0].taxa, None);
+        assert_eq!(output.result[0].taxon_protein_counts, HashMap::from([(6, 2), (7, 1)]));
+    }
+
+    #[test]
+    fn test_no_functional_analysis() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+
+        let with_fa = analyse_peptide(&searcher, "AC", CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() }, None).unwrap();
+        let without_fa = analyse_peptide(&searcher, "AC", CutoffPolicy::Constant(10000), &SearchOptions::default(), None).unwrap();
+
+        assert!(with_fa.fa.is_some());
+        assert!(without_fa.fa.is_none());
+        assert_eq!(with_fa.lca, without_fa.lca);
+        assert_eq!(with_fa.taxa, without_fa.taxa);
+    }
+
+    #[test]
+    fn test_length_dependent_cutoff() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+        // a cutoff of 4 for peptides of length 1, and 1 for peptides of length 2 or longer
+        let cutoff = CutoffPolicy::LengthDependent { base: 1, factor: 3, max_length: 2 };
+        assert_eq!(cutoff.cutoff(1), 4);
+        assert_eq!(cutoff.cutoff(2), 1);
+        assert_eq!(cutoff.cutoff(10), 1);
+
+        // the short peptide has 3 matches, which does not reach its higher cutoff
+        let (cutoff_used, _) = search_proteins_for_peptide(&searcher, "A", cutoff, &SearchOptions::default()).unwrap();
+        assert!(!cutoff_used);
+
+        // the longer peptide also has 3 matches, but reaches its lower cutoff
+        let (cutoff_used, _) = search_proteins_for_peptide(&searcher, "AC", cutoff, &SearchOptions::default()).unwrap();
+        assert!(cutoff_used);
+    }
+
+    #[test]
+    fn test_deserialize_cutoff_policy() {
+        let cutoff: CutoffPolicy = serde_json::from_str("10000").unwrap();
+        assert_eq!(cutoff, CutoffPolicy::Constant(10000));
+
+        let cutoff: CutoffPolicy = serde_json::from_str(r#"{"base": 100, "factor": 10, "max_length": 50}"#).unwrap();
+        assert_eq!(cutoff, CutoffPolicy::LengthDependent { base: 100, factor: 10, max_length: 50 });
+    }
+
+    #[test]
+    fn test_match_count_histogram() {
+        let mut histogram = MatchCountHistogram::default();
+        for match_count in [0, 1, 1, 2, 10, 11, 100, 101, 5000] {
+            histogram.add(match_count);
+        }
+
+        assert_eq!(histogram.buckets(), &[2, 2, 2, 1, 1]);
+        assert_eq!(histogram.total(), 8);
+    }
+
+    #[test]
+    fn test_analyse_all_peptides_with_stats() {
+        let searcher = get_searcher("MACWY-MACWY-MACKY$", &[6, 7, 7]);
+        let peptides = vec!["ACW".to_string(), "ACKY".to_string(), "QQQ".to_string(), "MAC".to_string()];
+
+        let output = analyse_all_peptides_with_stats(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_taxa: true, ..Default::default() });
+
+        // QQQ has no matches, so only 3 peptides are counted
+        assert_eq!(output.output.result.len(), 3);
+        assert_eq!(output.match_count_histogram.total(), output.output.result.len());
+        assert_eq!(output.match_count_histogram.buckets(), &[1, 2]);
+    }
+
+    #[test]
+    fn test_include_sequences() {
+        let searcher = get_searcher("MACWY-GACWYK-MACKY$", &[6, 7, 7]);
+        let peptides = vec!["ACWY".to_string()];
+
+        let output = search_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_sequences: true, ..Default::default() });
+        let mut sequences: Vec<(String, Option<String>)> = output.result[0]
+            .proteins
+            .iter()
+            .map(|protein| (protein.uniprot_accession.clone(), protein.sequence.clone()))
+            .collect();
+        sequences.sort();
+        assert_eq!(sequences, vec![
+            ("P1".to_string(), Some("MACWY".to_string())),
+            ("P2".to_string(), Some("GACWYK".to_string())),
+        ]);
+
+        // the sequences are omitted by default
+        let output = search_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        assert!(output.result[0].proteins.iter().all(|protein| protein.sequence.is_none()));
+        assert!(!serde_json::to_string(&output.result[0].proteins[0]).unwrap().contains("sequence"));
+    }
+
+    #[test]
+    fn test_shared_annotation_cache() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+        let cache = AnnotationCache::default();
+
+        // all peptides match the same 3 proteins
+        for peptide in ["A", "AC", "C", "W"] {
+            let cached = analyse_peptide(&searcher, peptide, CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() }, Some(&cache)).unwrap();
+            let uncached = analyse_peptide(&searcher, peptide, CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() }, None).unwrap();
+            let (cached_fa, uncached_fa) = (cached.fa.unwrap(), uncached.fa.unwrap());
+            assert_eq!(cached_fa.counts, uncached_fa.counts);
+            assert_eq!(cached_fa.data, uncached_fa.data);
+        }
+
+        // the annotations of every protein are decoded only once
+        assert_eq!(cache.decode_count(), 3);
+    }
+
+    #[test]
+    fn test_write_all_peptides_chunked() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+        let peptides: Vec<String> = ["AC", "GGG", "WY", "QA", "MMM", "CW", "A"].iter().map(|peptide| peptide.to_string()).collect();
+
+        // compare the parsed JSON, since the order of the keys of the hashmaps in the results is not deterministic
+        let expected = serde_json::to_value(
+            analyse_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, include_taxa: true, functional_analysis: true, ..Default::default() })
+        ).unwrap();
+
+        // the chunks don't have to divide the number of peptides, and a chunk can contain no matches at all
+        for chunk_size in [1, 2, 3, 100] {
+            let mut written = vec![];
+            write_all_peptides_chunked(
+                peptides.iter().cloned(),
+                chunk_size,
+                |chunk| analyse_all_peptides(&searcher, chunk, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, include_taxa: true, functional_analysis: true, ..Default::default() }),
+                &mut written,
+            ).unwrap();
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(&written).unwrap(), expected);
+        }
+
+        let expected = serde_json::to_value(
+            search_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, ..Default::default() })
+        ).unwrap();
+        let mut written = vec![];
+        write_all_peptides_chunked(
+            peptides.iter().cloned(),
+            2,
+            |chunk| search_all_peptides(&searcher, chunk, CutoffPolicy::Constant(10000), &SearchOptions { include_index: true, ..Default::default() }),
+            &mut written,
+        ).unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&written).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_search_stats() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[6, 7, 6]);
+        // AC, CW and A match at least 2 proteins and hit the cutoff, GGG and MMM don't match
+        let peptides: Vec<String> = ["AC", "GGG", "WY", "MMM", "CW", "A"].iter().map(|peptide| peptide.to_string()).collect();
+
+        let mut stats = write_all_peptides_chunked(
+            peptides.iter().cloned(),
+            4,
+            |chunk| analyse_all_peptides(&searcher, chunk, CutoffPolicy::Constant(2), &SearchOptions { include_taxa: true, ..Default::default() }),
+            &mut vec![],
+        ).unwrap();
+        stats.set_total_time(3.0);
+
+        assert_eq!(stats, SearchStats {
+            total_peptides: 6,
+            matched: 4,
+            unmatched: 2,
+            cutoff_used: 3,
+            total_time_ms: 3.0,
+            average_time_ms: 0.5,
+        });
+    }
+
+    #[test]
+    fn test_write_all_peptide_counts() {
+        let searcher = get_searcher("ACWY-ACYW-QACWAC$", &[6, 7, 6]);
+        let peptides: Vec<String> = ["AC", "GGG", "WY", "ACW", "Q"].iter().map(|peptide| peptide.to_string()).collect();
+
+        let mut output = vec![];
+        let stats = write_all_peptide_counts(&searcher, peptides.iter().cloned(), 2, &SearchOptions::default(), false, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "AC\t4\nGGG\t0\nWY\t1\nACW\t2\nQ\t1\n");
+        assert_eq!((stats.total_peptides, stats.matched, stats.unmatched, stats.cutoff_used), (5, 4, 1, 0));
+
+        // only the matched peptides are written, the statistics still count every peptide
+        let mut output = vec![];
+        let stats = write_all_peptide_counts(&searcher, peptides.iter().cloned(), 2, &SearchOptions::default(), true, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "AC\t4\nWY\t1\nACW\t2\nQ\t1\n");
+        assert_eq!((stats.total_peptides, stats.matched, stats.unmatched), (5, 4, 1));
+
+        // every match is one occurrence in a protein, so the count equals the number of proteins found for every occurrence
+        for peptide in &peptides {
+            let proteins = search_proteins_for_peptide(&searcher, peptide, CutoffPolicy::Constant(usize::MAX), &SearchOptions::default())
+                .map_or(vec![], |(_, proteins)| proteins);
+            assert_eq!(searcher.count_matching_suffixes(peptide.as_bytes(), false), proteins.len());
+        }
+
+        // the third protein contains AC twice, but only matches once after deduplication of the proteins
+        let proteins = search_proteins_for_peptide(&searcher, "AC", CutoffPolicy::Constant(usize::MAX), &SearchOptions::default()).unwrap().1;
+        let mut distinct_proteins: Vec<usize> = proteins.iter().map(|&(index, _)| index).collect();
+        distinct_proteins.sort_unstable();
+        distinct_proteins.dedup();
+        assert_eq!(distinct_proteins.len(), 3);
+    }
+
+    #[test]
+    fn test_write_all_peptides_unipept_tsv() {
+        let searcher = get_searcher("ACWY-ACYW-QACW$", &[7, 9, 7]);
+        let peptides: Vec<String> = ["CWY", "GGG", "AC", "YW"].iter().map(|peptide| peptide.to_string()).collect();
+
+        let mut output = vec![];
+        let stats = write_all_peptides_unipept_tsv(
+            &searcher,
+            peptides.iter().cloned(),
+            3,
+            |chunk| analyse_all_peptides(&searcher, chunk, CutoffPolicy::Constant(10000), &SearchOptions::default()),
+            &mut output,
+        ).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], UNIPEPT_TSV_HEADER);
+        assert_eq!(lines[0].split('\t').collect::<Vec<&str>>(), vec!["peptide", "taxon_id", "taxon_name", "taxon_rank"]);
+        // the names and ranks are resolved in the taxonomy, GGG has no matches and is left out
+        assert_eq!(&lines[1..], &[
+            "CWY\t7\tAzorhizobium caulinodans\tspecies",
+            "AC\t6\tAzorhizobium\tgenus",
+            "YW\t9\tBuchnera aphidicola\tspecies",
+        ]);
+        assert_eq!((stats.total_peptides, stats.matched, stats.unmatched), (4, 3, 1));
+    }
+
+    #[test]
+    fn test_search_proteins_for_peptide_errors() {
+        let searcher = get_example_searcher();
+        let cutoff = CutoffPolicy::Constant(10000);
+
+        // an empty peptide is shorter than the sparseness factor of 1
+        assert_eq!(search_proteins_for_peptide(&searcher, "\n", cutoff, &SearchOptions::default()).err(), Some(PeptideSearchError::TooShort));
+        // "WY-M" occurs in the text, but spans the boundary of the 2 proteins
+        assert_eq!(search_proteins_for_peptide(&searcher, "WY-M", cutoff, &SearchOptions::default()).err(), Some(PeptideSearchError::ContainsSeparator));
+        assert_eq!(search_proteins_for_peptide(&searcher, "WY$", cutoff, &SearchOptions { preserve_case: true, ..Default::default() }).err(), Some(PeptideSearchError::ContainsSeparator));
+        // a peptide without matches is not an error
+        assert_eq!(search_proteins_for_peptide(&searcher, "GGG", cutoff, &SearchOptions::default()).map(|(cutoff_used, proteins)| (cutoff_used, proteins.len())), Ok((false, 0)));
+
+        let passed_deadline = Instant::now();
+        assert_eq!(
+            search_proteins_for_peptide_with_deadline(&searcher, "ACW", cutoff, &SearchOptions::default(), passed_deadline).err(),
+            Some(PeptideSearchError::OutOfTime)
+        );
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        let (_, proteins) = search_proteins_for_peptide_with_deadline(&searcher, "ACW", cutoff, &SearchOptions::default(), deadline).unwrap();
+        assert_eq!(proteins.len(), 1);
+    }
+
+    #[test]
+    fn test_include_raw_lca() {
+        // the taxa 22 and 23 are children of the unranked taxon 21, which is snapped to the genus 6
+        let searcher = get_searcher("ACWY-ACYW-QQQ$", &[22, 23, 7]);
+        let peptides = vec!["AC".to_string(), "QQ".to_string()];
+
+        let output = analyse_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { include_raw_lca: true, ..Default::default() });
+        assert_eq!((output.result[0].raw_lca, output.result[0].lca), (Some(21), Some(6)));
+        // the snapping does not change a ranked taxon
+        assert_eq!((output.result[1].raw_lca, output.result[1].lca), (Some(7), Some(7)));
+
+        // the raw lca is only part of the output when it is requested
+        let output = analyse_all_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        assert_eq!((output.result[0].raw_lca, output.result[0].lca), (None, Some(6)));
+        assert!(!serde_json::to_string(&output.result[0]).unwrap().contains("raw_lca"));
+    }
+
+    #[test]
+    fn test_top_taxa() {
+        let searcher = get_searcher("ACWY-ACYW-QQQ-KKK$", &[7, 9, 7, 9]);
+        // 3 peptides only match the species 7, 1 only matches the species 9 and AC matches both, so its lca is the genus 6
+        let peptides: Vec<String> = ["QQ", "QQQ", "ACWY", "ACYW", "AC", "GGG"].iter().map(|peptide| peptide.to_string()).collect();
+
+        let taxa = top_taxa(&searcher, &peptides, 2, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        assert_eq!(taxa, vec![(7, 3), (6, 1)]);
+
+        let taxa = top_taxa(&searcher, &peptides, 10, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        assert_eq!(taxa, vec![(7, 3), (6, 1), (9, 1)]);
+        assert!(top_taxa(&searcher, &peptides, 0, CutoffPolicy::Constant(10000), &SearchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_benchmark_peptides() {
+        let searcher = get_searcher("ACWY-ACYW-QQQ-KKK$", &[7, 9, 7, 9]);
+        let peptides: Vec<String> = ["QQ", "QQQ", "ACWY", "AC", "GGG"].iter().map(|peptide| peptide.to_string()).collect();
+
+        // every searched peptide is counted, also the peptides without matches
+        let report = benchmark_peptides(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions { functional_analysis: true, ..Default::default() });
+        assert_eq!(report.peptides, 5);
+        assert!(report.p50_ms <= report.p95_ms && report.p95_ms <= report.p99_ms && report.p99_ms <= report.max_ms);
+    }
+
+    #[test]
+    fn test_benchmark_report_percentiles() {
+        let latencies: Vec<f64> = (1 ..= 100).rev().map(|latency| latency as f64).collect();
+        let report = BenchmarkReport::from_latencies(latencies, 50.0);
+        assert_eq!(
+            report,
+            BenchmarkReport {
+                peptides: 100,
+                total_time_ms: 50.0,
+                peptides_per_second: 2000.0,
+                p50_ms: 50.0,
+                p95_ms: 95.0,
+                p99_ms: 99.0,
+                max_ms: 100.0,
+            }
+        );
+
+        assert_eq!(BenchmarkReport::from_latencies(vec![], 0.0).peptides, 0);
+    }
+
+    #[test]
+    fn test_uncovered_proteins() {
+        let searcher = get_searcher("ACWY-ACYW-QQQ-KKK$", &[7, 9, 7, 9]);
+        let peptides: Vec<String> = ["QQ", "ACW", "GGG"].iter().map(|peptide| peptide.to_string()).collect();
+
+        // QQ only matches P3 and ACW only matches P1
+        let uncovered = uncovered_proteins(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions::default());
+        let uncovered: Vec<&str> = uncovered.iter().map(|protein| protein.uniprot_id.as_str()).collect();
+        assert_eq!(uncovered, vec!["P2", "P4"]);
+
+        let peptides: Vec<String> = ["AC", "Q", "K"].iter().map(|peptide| peptide.to_string()).collect();
+        assert!(uncovered_proteins(&searcher, &peptides, CutoffPolicy::Constant(10000), &SearchOptions::default()).is_empty());
+        assert_eq!(uncovered_proteins(&searcher, &vec![], CutoffPolicy::Constant(10000), &SearchOptions::default()).len(), 4);
+    }
+}
@@ -1,14 +1,46 @@
-use std::cmp::min;
-
-
-use sa_mappings::functionality::{FunctionAggregator, FunctionalAggregation};
-use sa_mappings::proteins::{Protein, Proteins};
+use std::cmp::{min, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use sa_mappings::alphabet::AminoAcidAlphabet;
+use sa_mappings::functionality::{AnnotationCache, FunctionAggregator, FunctionalAggregation};
+use sa_mappings::proteins::{Protein, Proteins, TERMINATION_CHARACTER};
 use sa_mappings::taxonomy::TaxonAggregator;
+use suffixarray_builder::{build_sa_with_equivalence_classes, SAConstructionAlgorithm};
+use suffixarray_builder::equivalence_classes::EquivalenceClasses;
+use serde::Serialize;
 use umgap::taxon::TaxonId;
 
+use crate::bloom_filter::BloomFilter;
 use crate::sa_searcher::BoundSearch::{Maximum, Minimum};
-use crate::suffix_to_protein_index::SuffixToProteinIndex;
-use crate::Nullable;
+use crate::suffix_to_protein_index::{SparseSuffixToProtein, SuffixToProteinIndex};
+use crate::{Nullable, SuffixValue};
+
+/// Normalizes a peptide for a search where I and L are equated by replacing every L by an I
+/// The search functions of the `Searcher` already equate I and L themselves, so this is never required before searching,
+/// but it gives a single canonical representation of a peptide (e.g. to deduplicate peptides or to use them as a key)
+///
+/// # Arguments
+/// * `peptide` - The peptide that is normalized in place
+pub fn normalize_il(peptide: &mut [u8]) {
+    AminoAcidAlphabet::canonicalize(peptide);
+}
+
+/// Checks if a peptide contains the `SEPARATION_CHARACTER` or the `TERMINATION_CHARACTER`
+/// Such a peptide would match across the boundary of 2 proteins in the concatenated text, so it never matches a protein
+///
+/// # Arguments
+/// * `peptide` - The peptide that is checked
+///
+/// # Returns
+///
+/// Returns true if the peptide contains a separation or termination character
+pub fn contains_sentinel(peptide: &[u8]) -> bool {
+    peptide
+        .iter()
+        .any(|&character| AminoAcidAlphabet::is_sentinel(character))
+}
 
 /// Enum indicating if we are searching for the minimum, or maximum bound in the suffix array
 #[derive(Clone, Copy, PartialEq)]
@@ -33,6 +65,109 @@ pub enum SearchAllSuffixesResult {
     SearchResult(Vec<i64>),
 }
 
+/// Struct representing the matching suffixes of a reversed peptide, used as a decoy in target-decoy FDR estimation
+#[derive(Debug, PartialEq)]
+pub struct DecoySearchResult {
+    /// The reversed peptide that was searched
+    pub reversed_peptide: Vec<u8>,
+    /// The matching suffixes of the reversed peptide
+    pub result: SearchAllSuffixesResult,
+}
+
+/// A motif (or position-specific pattern) to search for, with the residues that are allowed at every position of the motif
+/// A position with a single allowed residue is a fixed position, a position with multiple residues is an ambiguous position
+pub type Motif = [Vec<u8>];
+
+/// A protein of the searcher together with its index in the proteins of the searcher
+pub type IndexedProtein<'a> = (usize, &'a Protein);
+
+/// The maximum number of partially expanded motifs that are searched for a single motif
+/// Every ambiguous position multiplies the number of expansions, so this bounds the search time of highly degenerate motifs
+pub const MAX_MOTIF_EXPANSIONS: usize = 10_000;
+
+/// Struct representing the positions that match a motif
+#[derive(Debug, PartialEq)]
+pub struct MotifSearchResult {
+    /// The index of every matching protein together with the offset of the match in that protein, sorted by their position in the text
+    pub matches: Vec<(usize, usize)>,
+    /// True if `MAX_MOTIF_EXPANSIONS` was reached, so the matches of the branches that were not searched are missing
+    pub truncated: bool,
+}
+
+/// The number of residues shown before and after a match in a `MatchedSuffix`
+const MATCH_CONTEXT_LENGTH: usize = 10;
+
+/// Struct representing a single suffix that matches a search string, together with the information needed to explain the match
+#[derive(Debug, PartialEq)]
+pub struct MatchedSuffix {
+    /// The index in the text where the match starts
+    pub suffix: i64,
+    /// The uniprot id of the protein the match is part of
+    pub uniprot_id: String,
+    /// The offset of the match in the protein
+    pub offset: usize,
+    /// The residues of the protein in front of the match, at most `MATCH_CONTEXT_LENGTH` residues
+    pub before: String,
+    /// The matched residues as they are stored in the text
+    pub matched: String,
+    /// The residues of the protein after the match, at most `MATCH_CONTEXT_LENGTH` residues
+    pub after: String,
+    /// The positions in the search string where the residue in the text differs from the searched residue, e.g. where I was matched to L
+    pub substitutions: Vec<usize>,
+}
+
+/// Struct representing every suffix matched by a search string, used to debug why a peptide matches
+#[derive(Debug, PartialEq)]
+pub struct MatchExplanation {
+    /// The string/peptide that was searched
+    pub search_string: String,
+    /// The matched suffixes, sorted by their position in the text
+    pub matches: Vec<MatchedSuffix>,
+}
+
+impl Display for MatchExplanation {
+    /// Writes a line for every match with the uniprot id, the offset and the match in its context,
+    /// the substituted residues of the match are written in lowercase
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {} matches", self.search_string, self.matches.len())?;
+        for matched_suffix in &self.matches {
+            let matched: String = matched_suffix
+                .matched
+                .chars()
+                .enumerate()
+                .map(|(i, c)| if matched_suffix.substitutions.contains(&i) { c.to_ascii_lowercase() } else { c })
+                .collect();
+            writeln!(
+                f,
+                "{}\t{}\t{}[{}]{}",
+                matched_suffix.uniprot_id, matched_suffix.offset, matched_suffix.before, matched, matched_suffix.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct representing a summary of the index in which a `Searcher` searches
+#[derive(Debug, PartialEq, Serialize)]
+pub struct IndexStatistics {
+    /// The number of proteins in the index
+    pub protein_count: usize,
+    /// The total number of residues of all proteins
+    pub residue_count: usize,
+    /// The number of distinct taxa of the proteins, including the taxa of collapsed proteins
+    pub distinct_taxa: usize,
+    /// The length of the shortest protein, 0 if there are no proteins
+    pub min_protein_length: usize,
+    /// The median protein length (the upper median for an even number of proteins), 0 if there are no proteins
+    pub median_protein_length: usize,
+    /// The length of the longest protein, 0 if there are no proteins
+    pub max_protein_length: usize,
+    /// The number of entries in the (sparse) suffix array, including the suffixes of appended proteins
+    pub suffix_array_length: usize,
+    /// The sparseness factor used by the suffix array
+    pub sparseness_factor: u8,
+}
+
 /// Custom implementation of partialEq for SearchAllSuffixesResult
 /// We consider 2 SearchAllSuffixesResult equal if they exist of the same key, and the Vec contains the same values, but the order can be different
 impl PartialEq for SearchAllSuffixesResult {
@@ -72,25 +207,133 @@ impl PartialEq for SearchAllSuffixesResult {
     }
 }
 
+/// The false-positive rate of the k-mer filter used by the CLI, this costs about 9.6 bits per residue of the text
+pub const DEFAULT_KMER_FILTER_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Bloom filter over every (canonicalized) k-mer of the text, used to reject peptides with an absent k-mer without searching
+struct KmerFilter {
+    k: usize,
+    false_positive_rate: f64,
+    filter: BloomFilter,
+}
+
+impl KmerFilter {
+    /// Builds the filter over every k-mer of a text
+    ///
+    /// # Arguments
+    /// * `text` - The text of which the k-mers are inserted
+    /// * `k` - The length of the k-mers
+    /// * `false_positive_rate` - The probability that an absent k-mer is not rejected
+    /// * `equivalence_classes` - The residues that are equated, the k-mers are canonicalized before they are inserted
+    ///
+    /// # Returns
+    ///
+    /// Returns the filter that contains every k-mer of `text`
+    fn new(text: &[u8], k: usize, false_positive_rate: f64, equivalence_classes: &EquivalenceClasses) -> Self {
+        let mut canonical_text = text.to_vec();
+        equivalence_classes.canonicalize(&mut canonical_text);
+
+        // k-mers that span a separation character are inserted as well, they only make the filter slightly less selective
+        let mut filter = BloomFilter::new(text.len().saturating_sub(k - 1), false_positive_rate);
+        for kmer in canonical_text.windows(k) {
+            filter.insert(kmer);
+        }
+
+        Self { k, false_positive_rate, filter }
+    }
+
+    /// Checks if every k-mer of a search string may occur in the text
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide that is searched
+    /// * `equivalence_classes` - The residues that are equated, the same classes as used to build the filter
+    ///
+    /// # Returns
+    ///
+    /// Returns false if the search string certainly does not occur in the text, a search string shorter than k is never rejected
+    fn may_contain(&self, search_string: &[u8], equivalence_classes: &EquivalenceClasses) -> bool {
+        if search_string.len() < self.k {
+            return true;
+        }
+
+        let mut canonical_search_string = search_string.to_vec();
+        equivalence_classes.canonicalize(&mut canonical_search_string);
+        canonical_search_string.windows(self.k).all(|kmer| self.filter.contains(kmer))
+    }
+}
+
+/// The reasons why a `Searcher` could not be created from its parts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearcherError {
+    /// The sparseness factor is 0
+    ZeroSparsenessFactor,
+    /// The suffix array does not contain any suffixes
+    EmptySuffixArray,
+    /// The database does not contain any proteins
+    NoProteins,
+    /// The text of the proteins does not end with the `TERMINATION_CHARACTER`
+    MissingTerminationCharacter,
+    /// The length of the suffix array does not match the length of the text with the sparseness factor
+    SuffixArrayLengthMismatch { length: usize, expected_length: usize },
+    /// The text of the proteins is too long to index its suffixes with the type of the suffix array
+    TextTooLong { length: usize },
+}
+
+impl Display for SearcherError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearcherError::ZeroSparsenessFactor => write!(f, "the sparseness factor must be at least 1"),
+            SearcherError::EmptySuffixArray => write!(f, "the suffix array is empty"),
+            SearcherError::NoProteins => write!(f, "the database does not contain any proteins"),
+            SearcherError::MissingTerminationCharacter => {
+                write!(f, "the text of the proteins does not end with the termination character")
+            }
+            SearcherError::SuffixArrayLengthMismatch { length, expected_length } => write!(
+                f,
+                "the suffix array has {} entries, but the text of the proteins needs {} entries, \
+                 the suffix array is probably built for another database or with another sparseness factor",
+                length, expected_length
+            ),
+            SearcherError::TextTooLong { length } => write!(
+                f,
+                "the text of the proteins has {} characters, which is too long for the type of the suffix array",
+                length
+            ),
+        }
+    }
+}
+
+impl Error for SearcherError {}
+
 /// Struct that contains all the elements needed to search a peptide in the suffix array
 /// This struct also contains all the functions used for search
 ///
 /// # Arguments
 /// * `sa` - The sparse suffix array representing the protein database
 /// * `sparseness_factor` - The sparseness factor used by the suffix array
+/// * `appended_sa` - Secondary sparse suffix array over the proteins appended after the construction of `sa`
+/// * `max_protein_length` - The length of the longest protein, a longer peptide can never match
+/// * `protein_starts` - The index in the text where every protein starts, followed by the length of the text
+/// * `equivalence_classes` - The residues that are equated in the suffix array, by default I and L
+/// * `kmer_filter` - Optional Bloom filter over the k-mers of the text to reject absent peptides without searching
 /// * `suffix_index_to_protein` - Mapping from a suffix to the proteins to know which a suffix is part of
 /// * `taxon_id_calculator` - Object representing the used taxonomy and that calculates the taxonomic analysis provided by Unipept
 /// * `function_aggregator` - Object used to retrieve the functional annotations and to calculate the functional analysis provided by Unipept
-pub struct Searcher {
-    sa: Vec<i64>,
+pub struct Searcher<T: SuffixValue = i64> {
+    sa: Vec<T>,
     pub sparseness_factor: u8,
+    appended_sa: Vec<T>,
+    max_protein_length: usize,
+    protein_starts: Vec<usize>,
+    equivalence_classes: EquivalenceClasses,
+    kmer_filter: Option<KmerFilter>,
     suffix_index_to_protein: Box<dyn SuffixToProteinIndex>,
     proteins: Proteins,
     taxon_id_calculator: TaxonAggregator,
     function_aggregator: FunctionAggregator
 }
 
-impl Searcher {
+impl<T: SuffixValue> Searcher<T> {
     
     /// Creates a new Searcher object
     ///
@@ -106,25 +349,294 @@ impl Searcher {
     ///
     /// Returns a new Searcher object
     pub fn new(
-        sa: Vec<i64>,
+        sa: Vec<T>,
         sparseness_factor: u8,
         suffix_index_to_protein: Box<dyn SuffixToProteinIndex>,
         proteins: Proteins,
         taxon_id_calculator: TaxonAggregator,
         function_aggregator: FunctionAggregator
     ) -> Self {
+        let max_protein_length = Self::longest_protein(&proteins.input_string);
+        let protein_starts = Self::protein_starts(&proteins.input_string);
         Self {
             sa,
             sparseness_factor,
+            appended_sa: vec![],
+            max_protein_length,
+            protein_starts,
+            equivalence_classes: EquivalenceClasses::default(),
+            kmer_filter: None,
             suffix_index_to_protein,
             proteins,
             taxon_id_calculator,
             function_aggregator
         }
     }
-    
+
+    /// Creates a new Searcher object after checking that the suffix array can belong to the text of the proteins
+    /// These are cheap checks, e.g. on the length of the suffix array, which catch a misconfiguration such as a suffix array
+    /// that is paired with the wrong database before it results in wrong search results or a panic during a search
+    ///
+    /// # Arguments
+    /// * `sa` - The sparse suffix array representing the protein database
+    /// * `sparseness_factor` - The sparseness factor used by the suffix array
+    /// * `suffix_index_to_protein` - Mapping from a suffix to the proteins to know which a suffix is part of
+    /// * `proteins` - List of all the proteins where the suffix array is build on
+    /// * `taxon_id_calculator` - Object representing the used taxonomy and that calculates the taxonomic analysis provided by Unipept
+    /// * `function_aggregator` - Object used to retrieve the functional annotations and to calculate the functional analysis provided by Unipept
+    ///
+    /// # Returns
+    ///
+    /// Returns a new Searcher object
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sparseness factor is 0, if the suffix array or the proteins are empty, if the text does not end
+    /// with the termination character, if the length of the text does not fit in the type of the suffix array
+    /// or if the length of the suffix array does not match the length of the text with the sparseness factor
+    pub fn try_new(
+        sa: Vec<T>,
+        sparseness_factor: u8,
+        suffix_index_to_protein: Box<dyn SuffixToProteinIndex>,
+        proteins: Proteins,
+        taxon_id_calculator: TaxonAggregator,
+        function_aggregator: FunctionAggregator
+    ) -> Result<Self, SearcherError> {
+        if sparseness_factor == 0 {
+            return Err(SearcherError::ZeroSparsenessFactor);
+        }
+        if sa.is_empty() {
+            return Err(SearcherError::EmptySuffixArray);
+        }
+        if proteins.proteins.is_empty() {
+            return Err(SearcherError::NoProteins);
+        }
+        if proteins.input_string.last() != Some(&TERMINATION_CHARACTER) {
+            return Err(SearcherError::MissingTerminationCharacter);
+        }
+        if !Self::fits_text_length(proteins.input_string.len()) {
+            return Err(SearcherError::TextTooLong { length: proteins.input_string.len() });
+        }
+
+        // the sparse suffix array contains every suffix that starts at a multiple of the sparseness factor
+        let expected_length = proteins.input_string.len().div_ceil(sparseness_factor as usize);
+        if sa.len() != expected_length {
+            return Err(SearcherError::SuffixArrayLengthMismatch { length: sa.len(), expected_length });
+        }
+
+        Ok(Self::new(sa, sparseness_factor, suffix_index_to_protein, proteins, taxon_id_calculator, function_aggregator))
+    }
+
+    /// Sets the residues that are equated during search
+    /// The suffix array must be built with the same equivalence classes (see `build_sa_with_equivalence_classes`),
+    /// since the binary search relies on the order of the canonicalized suffixes
+    ///
+    /// # Arguments
+    /// * `equivalence_classes` - The residues that are equated, this replaces the default table that only equates I and L
+    ///
+    /// # Returns
+    ///
+    /// Returns the Searcher that uses `equivalence_classes` during search
+    pub fn with_equivalence_classes(mut self, equivalence_classes: EquivalenceClasses) -> Self {
+        self.equivalence_classes = equivalence_classes;
+        self.rebuild_kmer_filter();
+        self
+    }
+
+    /// Adds a Bloom filter over every k-mer of the text, so a peptide that contains a k-mer that does not occur in the text
+    /// is rejected without searching the suffix array. Peptides that do occur give exactly the same results as without the filter.
+    /// The filter uses about `-ln(false_positive_rate) / ln(2)^2` bits per residue of the text (9.6 bits for a rate of 1%),
+    /// an absent peptide with a single absent k-mer is still searched with a probability of `false_positive_rate`.
+    ///
+    /// # Arguments
+    /// * `k` - The length of the k-mers, at least 1. Shorter peptides are always searched, longer k-mers reject more peptides
+    /// * `false_positive_rate` - The probability that an absent k-mer is not rejected, between 0 and 1
+    ///
+    /// # Returns
+    ///
+    /// Returns the Searcher that first checks the k-mers of every peptide
+    pub fn with_kmer_filter(mut self, k: usize, false_positive_rate: f64) -> Self {
+        self.kmer_filter = Some(KmerFilter::new(&self.proteins.input_string, k.max(1), false_positive_rate, &self.equivalence_classes));
+        self
+    }
+
+    /// Rebuilds the k-mer filter (if any) over the current text and equivalence classes
+    fn rebuild_kmer_filter(&mut self) {
+        if let Some(KmerFilter { k, false_positive_rate, .. }) = self.kmer_filter {
+            self.kmer_filter = Some(KmerFilter::new(&self.proteins.input_string, k, false_positive_rate, &self.equivalence_classes));
+        }
+    }
+
+    /// Checks the k-mer filter to see if a search string may occur in the text
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide that is searched
+    ///
+    /// # Returns
+    ///
+    /// Returns false if the search string certainly does not occur in the text, always true if there is no k-mer filter
+    #[inline]
+    fn may_contain(&self, search_string: &[u8]) -> bool {
+        self.kmer_filter
+            .as_ref()
+            .is_none_or(|filter| filter.may_contain(search_string, &self.equivalence_classes))
+    }
+
+    /// Appends new proteins to the searcher without rebuilding the suffix array of the original proteins
+    /// A small secondary suffix array is built over the text of the new proteins only, and is merged into the secondary suffix array
+    /// of the earlier appended proteins, which is searched together with the original suffix array.
+    /// Only `search_bounds` and `search_bounds_with_depth` do not search the secondary suffix array, since their bounds index in the original suffix array.
+    /// This means every search performs 2 binary searches (one per suffix array) instead of 1, so the index should still be rebuilt once the appended part grows large.
+    /// The suffix to protein mapping, the protein starts and the k-mer filter are rebuilt over the complete extended text,
+    /// so every call costs time linear in the size of the complete text: append the new proteins in batches instead of one at a time.
+    ///
+    /// # Arguments
+    /// * `new_proteins` - The proteins that are appended, `new_proteins.input_string` should end with the `TERMINATION_CHARACTER`
+    /// * `construction_algorithm` - The algorithm used to construct the secondary suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the Searcher that also searches in the appended proteins
+    ///
+    /// # Errors
+    ///
+    /// Returns `SearcherError::TextTooLong` if the extended text is too long for the type of the suffix array,
+    /// next to the errors that occurred during the construction of the secondary suffix array
+    pub fn with_appended_proteins(
+        mut self,
+        new_proteins: Proteins,
+        construction_algorithm: &SAConstructionAlgorithm,
+    ) -> Result<Self, Box<dyn Error>> {
+        let offset = self.proteins.input_string.len();
+        let length = offset + new_proteins.input_string.len();
+        if !Self::fits_text_length(length) {
+            return Err(Box::new(SearcherError::TextTooLong { length }));
+        }
+
+        // build a suffix array over the new proteins only, and shift the suffixes so they index in the complete text
+        let mut new_text = new_proteins.input_string.to_vec();
+        let new_sa = build_sa_with_equivalence_classes(
+            &mut new_text,
+            &self.equivalence_classes,
+            construction_algorithm,
+            self.sparseness_factor
+        )?;
+        let new_sa = new_sa
+            .into_iter()
+            .map(|suffix| T::try_from_i64(suffix + offset as i64).ok_or(SearcherError::TextTooLong { length }))
+            .collect::<Result<Vec<T>, SearcherError>>()?;
+
+        self.proteins.input_string.to_mut().extend_from_slice(&new_proteins.input_string);
+        self.proteins.proteins.extend(new_proteins.proteins);
+        self.appended_sa = self.merge_suffix_arrays(&self.appended_sa, &new_sa);
+
+        self.suffix_index_to_protein = Box::new(SparseSuffixToProtein::new(&self.proteins.input_string));
+        self.max_protein_length = Self::longest_protein(&self.proteins.input_string);
+        self.protein_starts = Self::protein_starts(&self.proteins.input_string);
+        self.rebuild_kmer_filter();
+
+        Ok(self)
+    }
+
+    /// Merges 2 suffix arrays over (different parts of) the text into a single suffix array
+    ///
+    /// # Arguments
+    /// * `left` - A suffix array sorted on the canonicalized suffixes of the text
+    /// * `right` - Another suffix array sorted on the canonicalized suffixes of the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the suffixes of both suffix arrays, sorted on the canonicalized suffixes of the text
+    fn merge_suffix_arrays(&self, left: &[T], right: &[T]) -> Vec<T> {
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() && j < right.len() {
+            if self.compare_suffixes(left[i].to_usize(), right[j].to_usize()) == Ordering::Greater {
+                merged.push(right[j]);
+                j += 1;
+            } else {
+                merged.push(left[i]);
+                i += 1;
+            }
+        }
+        merged.extend_from_slice(&left[i..]);
+        merged.extend_from_slice(&right[j..]);
+        merged
+    }
+
+    /// Compares 2 suffixes of the text after canonicalizing them with the equivalence classes
+    /// A search string never contains a separation or termination character, so the comparison stops at the first one
+    /// and suffixes that are equal up to that character are considered equal
+    ///
+    /// # Arguments
+    /// * `first` - The start of the first suffix in the text
+    /// * `second` - The start of the second suffix in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the order of the canonicalized suffixes up to their first separation or termination character
+    fn compare_suffixes(&self, first: usize, second: usize) -> Ordering {
+        let text = &self.proteins.input_string;
+        for (&a, &b) in text[first..].iter().zip(&text[second..]) {
+            let (a, b) = (self.equivalence_classes.canonical(a), self.equivalence_classes.canonical(b));
+            if a != b {
+                return a.cmp(&b);
+            }
+            if AminoAcidAlphabet::is_sentinel(a) {
+                break;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Checks if every suffix of a text with the given length can be stored in the type of the suffix array
+    ///
+    /// # Arguments
+    /// * `length` - The length of the text
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the length of the text fits in the type of the suffix array
+    fn fits_text_length(length: usize) -> bool {
+        i64::try_from(length).ok().and_then(T::try_from_i64).is_some()
+    }
+
+    /// Calculates the length of the longest protein in the text
+    ///
+    /// # Arguments
+    /// * `text` - The concatenated proteins, separated by the `SEPARATION_CHARACTER`
+    ///
+    /// # Returns
+    ///
+    /// Returns the length of the longest protein in `text`
+    fn longest_protein(text: &[u8]) -> usize {
+        text.split(|&character| AminoAcidAlphabet::is_sentinel(character))
+            .map(|protein| protein.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Calculates the index in the text where every protein starts
+    ///
+    /// # Arguments
+    /// * `text` - The concatenated proteins, separated by the `SEPARATION_CHARACTER`
+    ///
+    /// # Returns
+    ///
+    /// Returns the start of every protein in `text`, followed by the length of `text` (one past the `TERMINATION_CHARACTER`),
+    /// so the end of every protein, including the last one, is the next start - 1
+    fn protein_starts(text: &[u8]) -> Vec<usize> {
+        let mut starts = vec![0];
+        for (index, &character) in text.iter().enumerate() {
+            if AminoAcidAlphabet::is_sentinel(character) {
+                starts.push(index + 1);
+            }
+        }
+        starts
+    }
+
     /// Compares the `search_string` to the `suffix`
-    /// During search this function performs extra logic since the suffix array is build with I == L, while ` self.proteins.input_string` is the original text where I != L
+    /// During search this function performs extra logic since the suffix array is build on the canonicalized text (e.g. I == L), while ` self.proteins.input_string` is the original text where I != L
     ///
     /// # Arguments
     /// * `search_string` - The string/peptide being searched in the suffix array
@@ -139,11 +651,11 @@ impl Searcher {
     fn compare(
         &self,
         search_string: &[u8],
-        suffix: i64,
+        suffix: T,
         skip: usize,
         bound: BoundSearch,
     ) -> (bool, usize) {
-        let mut index_in_suffix = (suffix as usize) + skip;
+        let mut index_in_suffix = suffix.to_usize() + skip;
         let mut index_in_search_string = skip;
         let mut is_cond_or_equal = false;
 
@@ -156,12 +668,10 @@ impl Searcher {
         // match as long as possible
         while index_in_search_string < search_string.len()
             && index_in_suffix < self.proteins.input_string.len()
-            && (search_string[index_in_search_string]
-                == self.proteins.input_string[index_in_suffix]
-                || (search_string[index_in_search_string] == b'L'
-                    && self.proteins.input_string[index_in_suffix] == b'I')
-                || (search_string[index_in_search_string] == b'I'
-                    && self.proteins.input_string[index_in_suffix] == b'L'))
+            && self.equivalence_classes.equivalent(
+                search_string[index_in_search_string],
+                self.proteins.input_string[index_in_suffix],
+            )
         {
             index_in_suffix += 1;
             index_in_search_string += 1;
@@ -171,18 +681,9 @@ impl Searcher {
             if index_in_search_string == search_string.len() {
                 is_cond_or_equal = true
             } else if index_in_suffix < self.proteins.input_string.len() {
-                // in our index every L was replaced by a I, so we need to replace them if we want to search in the right direction
-                let peptide_char = if search_string[index_in_search_string] == b'L' {
-                    b'I'
-                } else {
-                    search_string[index_in_search_string]
-                };
-
-                let protein_char = if self.proteins.input_string[index_in_suffix] == b'L' {
-                    b'I'
-                } else {
-                    self.proteins.input_string[index_in_suffix]
-                };
+                // in our index every residue was replaced by its canonical residue (e.g. every L by a I), so we need to replace them if we want to search in the right direction
+                let peptide_char = self.equivalence_classes.canonical(search_string[index_in_search_string]);
+                let protein_char = self.equivalence_classes.canonical(self.proteins.input_string[index_in_suffix]);
 
                 is_cond_or_equal = condition_check(peptide_char, protein_char);
             }
@@ -194,6 +695,7 @@ impl Searcher {
     /// Searches for the minimum or maximum bound for a string in the suffix array
     ///
     /// # Arguments
+    /// * `sa` - The suffix array we are searching in
     /// * `bound` - Indicates if we are searching the minimum or maximum bound
     /// * `search_string` - The string/peptide we are searching in the suffix array
     ///
@@ -201,20 +703,23 @@ impl Searcher {
     ///
     /// The first argument is true if a match was found
     /// The second argument indicates the index of the minimum or maximum bound for the match (depending on `bound`)
-    fn binary_search_bound(&self, bound: BoundSearch, search_string: &[u8]) -> (bool, usize) {
+    /// The third argument is the maximum number of characters of `search_string` that matched with any compared suffix
+    fn binary_search_bound(&self, sa: &[T], bound: BoundSearch, search_string: &[u8]) -> (bool, usize, usize) {
         let mut left: usize = 0;
-        let mut right: usize = self.sa.len();
+        let mut right: usize = sa.len();
         let mut lcp_left: usize = 0;
         let mut lcp_right: usize = 0;
+        let mut max_lcp: usize = 0;
         let mut found = false;
 
         // repeat until search window is minimum size OR we matched the whole search string last iteration
         while right - left > 1 {
             let center = (left + right) / 2;
             let skip = min(lcp_left, lcp_right);
-            let (retval, lcp_center) = self.compare(search_string, self.sa[center], skip, bound);
+            let (retval, lcp_center) = self.compare(search_string, sa[center], skip, bound);
 
             found |= lcp_center == search_string.len();
+            max_lcp = max_lcp.max(lcp_center);
 
             // update the left and right bound, depending on if we are searching the min or max bound
             if retval && bound == Minimum || !retval && bound == Maximum {
@@ -229,9 +734,10 @@ impl Searcher {
         // handle edge case to search at index 0
         if right == 1 && left == 0 {
             let (retval, lcp_center) =
-                self.compare(search_string, self.sa[0], min(lcp_left, lcp_right), bound);
+                self.compare(search_string, sa[0], min(lcp_left, lcp_right), bound);
 
             found |= lcp_center == search_string.len();
+            max_lcp = max_lcp.max(lcp_center);
 
             if bound == Minimum && retval {
                 right = 0;
@@ -239,12 +745,14 @@ impl Searcher {
         }
 
         match bound {
-            Minimum => (found, right),
-            Maximum => (found, left),
+            Minimum => (found, right, max_lcp),
+            Maximum => (found, left, max_lcp),
         }
     }
 
     /// Searches for the minimum and maximum bound for a string in the suffix array
+    /// The bounds always equate I and L (or the residues of the configured equivalence classes), so `search_string` does not have to be normalized
+    /// The bounds index in the original suffix array, so the proteins added with `with_appended_proteins` are not searched
     ///
     /// # Arguments
     /// * `search_string` - The string/peptide we are searching in the suffix array
@@ -253,24 +761,98 @@ impl Searcher {
     ///
     /// Returns the minimum and maximum bound of all matches in the suffix array, or `NoMatches` if no matches were found
     pub fn search_bounds(&self, search_string: &[u8]) -> BoundSearchResult {
-        let (found_min, min_bound) = self.binary_search_bound(Minimum, search_string);
+        if !self.may_contain(search_string) {
+            return BoundSearchResult::NoMatches;
+        }
+        self.search_bounds_in(&self.sa, search_string)
+    }
+
+    /// Searches for the minimum and maximum bound for a string in the suffix array, together with how far the string matched
+    /// The depth can be used to rank the nearest matches of a string that does not fully match
+    /// The bounds always equate I and L (or the residues of the configured equivalence classes), so `search_string` does not have to be normalized
+    /// The bounds and depth only concern the original suffix array, so the proteins added with `with_appended_proteins` are not searched
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// The first argument is the minimum and maximum bound of all matches in the suffix array, or `NoMatches` if no matches were found
+    /// The second argument is the maximum number of characters of `search_string` that matched with a suffix during the search
+    pub fn search_bounds_with_depth(&self, search_string: &[u8]) -> (BoundSearchResult, usize) {
+        let (found_min, min_bound, depth) = self.binary_search_bound(&self.sa, Minimum, search_string);
+
+        if !found_min {
+            return (BoundSearchResult::NoMatches, depth);
+        }
+
+        let (_, max_bound, _) = self.binary_search_bound(&self.sa, Maximum, search_string);
+
+        (BoundSearchResult::SearchResult((min_bound, max_bound + 1)), depth)
+    }
+
+    /// Searches for the minimum and maximum bound for a string in the given suffix array
+    ///
+    /// # Arguments
+    /// * `sa` - The suffix array we are searching in
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the minimum and maximum bound of all matches in `sa`, or `NoMatches` if no matches were found
+    fn search_bounds_in(&self, sa: &[T], search_string: &[u8]) -> BoundSearchResult {
+        // a string that is longer than every protein can never match
+        if search_string.len() > self.max_protein_length {
+            return BoundSearchResult::NoMatches;
+        }
+
+        let (found_min, min_bound, _) = self.binary_search_bound(sa, Minimum, search_string);
 
         if !found_min {
             return BoundSearchResult::NoMatches;
         }
 
-        let (_, max_bound) = self.binary_search_bound(Maximum, search_string);
+        let (_, max_bound, _) = self.binary_search_bound(sa, Maximum, search_string);
 
         BoundSearchResult::SearchResult((min_bound, max_bound + 1))
     }
 
+    /// Searches for the suffixes matching a search string, and returns them sorted ascending
+    /// The order of the suffixes returned by `search_matching_suffixes` is an implementation detail, this function
+    /// gives a deterministic order (e.g. for reproducible output), at the extra cost of sorting the m matches in O(m log m)
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
+    /// * `equalize_i_and_l` - True if we want to equate I and L (or the residues of the configured equivalence classes) during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns all the matching suffixes, sorted ascending
+    pub fn search_matching_suffixes_sorted(
+        &self,
+        search_string: &[u8],
+        max_matches: usize,
+        equalize_i_and_l: bool,
+    ) -> SearchAllSuffixesResult {
+        let mut result = self.search_matching_suffixes(search_string, max_matches, equalize_i_and_l);
+        match &mut result {
+            SearchAllSuffixesResult::MaxMatches(suffixes) | SearchAllSuffixesResult::SearchResult(suffixes) => suffixes.sort_unstable(),
+            SearchAllSuffixesResult::NoMatches => {}
+        }
+        result
+    }
+
     /// Searches for the suffixes matching a search string
-    /// During search I and L can be equated
+    /// During search I and L (or the residues of the configured equivalence classes) can be equated
+    /// The search is case-sensitive, the search string is not uppercased
+    /// The search string does not have to be normalized with `normalize_il`, both raw and normalized peptides give the same result when I and L are equated
+    /// A search string containing the `SEPARATION_CHARACTER` or `TERMINATION_CHARACTER` never matches, so no match spans 2 proteins
     ///
     /// # Arguments
     /// * `search_string` - The string/peptide we are searching in the suffix array
     /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
-    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    /// * `equalize_i_and_l` - True if we want to equate I and L (or the residues of the configured equivalence classes) during search, otherwise false
     ///
     /// # Returns
     ///
@@ -283,9 +865,71 @@ impl Searcher {
         equalize_i_and_l: bool,
     ) -> SearchAllSuffixesResult {
         let mut matching_suffixes: Vec<i64> = vec![];
+        let completed = self.visit_matching_suffixes(search_string, equalize_i_and_l, |suffix| {
+            matching_suffixes.push(suffix);
+            // stop if max number of matches is reached
+            matching_suffixes.len() < max_matches
+        });
+
+        if !completed {
+            SearchAllSuffixesResult::MaxMatches(matching_suffixes)
+        } else if matching_suffixes.is_empty() {
+            SearchAllSuffixesResult::NoMatches
+        } else {
+            SearchAllSuffixesResult::SearchResult(matching_suffixes)
+        }
+    }
+
+    /// Counts the suffixes matching a search string, without collecting them
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `equalize_i_and_l` - True if we want to equate I and L (or the residues of the configured equivalence classes) during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of matching suffixes, which is the number of occurrences of the search string in the proteins
+    pub fn count_matching_suffixes(&self, search_string: &[u8], equalize_i_and_l: bool) -> usize {
+        let mut count = 0;
+        self.visit_matching_suffixes(search_string, equalize_i_and_l, |_| {
+            count += 1;
+            true
+        });
+        count
+    }
+
+    /// Calls `visit` for every suffix matching a search string, until `visit` returns false
+    /// A search string that is more than 1 character shorter than the sparseness factor can not be searched and has no matching suffixes
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `equalize_i_and_l` - True if we want to equate I and L (or the residues of the configured equivalence classes) during search, otherwise false
+    /// * `visit` - Function called with every matching suffix, the search stops when it returns false
+    ///
+    /// # Returns
+    ///
+    /// Returns true if all the matching suffixes were visited, false if the search was stopped by `visit`
+    #[inline]
+    fn visit_matching_suffixes(
+        &self,
+        search_string: &[u8],
+        equalize_i_and_l: bool,
+        mut visit: impl FnMut(i64) -> bool,
+    ) -> bool {
+        // a peptide containing a separation or termination character can only match across the boundary of 2 proteins,
+        // so it has no matching suffixes, just like a peptide with an absent k-mer
+        if contains_sentinel(search_string) || !self.may_contain(search_string) {
+            return true;
+        }
+
+        // every match has to contain a sampled suffix, which a search string this short can skip over completely
+        if search_string.len() + 1 < self.sparseness_factor as usize {
+            return true;
+        }
+
         let mut il_locations = vec![];
         for (i, &character) in search_string.iter().enumerate() {
-            if character == b'I' || character == b'L' {
+            if self.equivalence_classes.is_ambiguous(character) {
                 il_locations.push(i);
             }
         }
@@ -299,53 +943,49 @@ impl Searcher {
             let il_locations_current_suffix = &il_locations[il_locations_start..];
             let current_search_string_prefix = &search_string[..skip];
             let current_search_string_suffix = &search_string[skip..];
-            let search_bound_result = self.search_bounds(&search_string[skip..]);
-            // if the shorter part is matched, see if what goes before the matched suffix matches the unmatched part of the prefix
-            if let BoundSearchResult::SearchResult((min_bound, max_bound)) = search_bound_result {
-                // try all the partially matched suffixes and store the matching suffixes in an array (stop when our max number of matches is reached)
-                let mut sa_index = min_bound;
-                while sa_index < max_bound {
-                    let suffix = self.sa[sa_index] as usize;
-                    // filter away matches where I was wrongfully equalized to L, and check the unmatched prefix
-                    // when I and L equalized, we only need to check the prefix, not the whole match, when the prefix is 0, we don't need to check at all
-                    if suffix >= skip
-                        && ((skip == 0
-                            || Self::check_prefix(
-                        current_search_string_prefix,
-                                &self.proteins.input_string[suffix - skip..suffix],
-                                equalize_i_and_l,
-                            ))
-                            && Self::check_suffix(
-                                skip,
-                                il_locations_current_suffix,
-                                current_search_string_suffix,
-                                &self.proteins.input_string
-                                    [suffix..suffix + search_string.len() - skip],
-                                equalize_i_and_l,
-                            ))
-                    {
-                        matching_suffixes.push((suffix - skip) as i64);
-
-                        // return if max number of matches is reached
-                        if matching_suffixes.len() >= max_matches {
-                            return SearchAllSuffixesResult::MaxMatches(matching_suffixes);
+            // search in the original suffix array and in the suffix array of the appended proteins (which is empty if nothing was appended)
+            for sa in [&self.sa, &self.appended_sa] {
+                let search_bound_result = self.search_bounds_in(sa, &search_string[skip..]);
+                // if the shorter part is matched, see if what goes before the matched suffix matches the unmatched part of the prefix
+                if let BoundSearchResult::SearchResult((min_bound, max_bound)) = search_bound_result {
+                    // try all the partially matched suffixes and visit the matching suffixes (stop when `visit` returns false)
+                    let mut sa_index = min_bound;
+                    while sa_index < max_bound {
+                        let suffix = sa[sa_index].to_usize();
+                        // filter away matches where I was wrongfully equalized to L, and check the unmatched prefix
+                        // when I and L equalized, we only need to check the prefix, not the whole match, when the prefix is 0, we don't need to check at all
+                        if suffix >= skip
+                            && ((skip == 0
+                                || self.check_prefix(
+                                    current_search_string_prefix,
+                                    &self.proteins.input_string[suffix - skip..suffix],
+                                    equalize_i_and_l,
+                                ))
+                                && Self::check_suffix(
+                                    skip,
+                                    il_locations_current_suffix,
+                                    current_search_string_suffix,
+                                    &self.proteins.input_string
+                                        [suffix..suffix + search_string.len() - skip],
+                                    equalize_i_and_l,
+                                ))
+                            // visit the matching suffix, and stop searching if `visit` asks to
+                            && !visit((suffix - skip) as i64)
+                        {
+                            return false;
                         }
+                        sa_index += 1;
                     }
-                    sa_index += 1;
                 }
             }
             skip += 1;
         }
 
-        if matching_suffixes.is_empty() {
-            SearchAllSuffixesResult::NoMatches
-        } else {
-            SearchAllSuffixesResult::SearchResult(matching_suffixes)
-        }
+        true
     }
 
     /// Returns true of the prefixes are the same
-    /// if `equalize_i_and_l` is set to true, L and I are considered the same
+    /// if `equalize_i_and_l` is set to true, L and I (or the residues of the configured equivalence classes) are considered the same
     ///
     /// # Arguments
     /// * `search_string_prefix` - The unchecked prefix of the string/peptide that is searched
@@ -357,6 +997,7 @@ impl Searcher {
     /// Returns true if `search_string_prefix` and `index_prefix` are considered the same, otherwise false
     #[inline]
     fn check_prefix(
+        &self,
         search_string_prefix: &[u8],
         index_prefix: &[u8],
         equalize_i_and_l: bool,
@@ -364,9 +1005,7 @@ impl Searcher {
         if equalize_i_and_l {
             search_string_prefix.iter().zip(index_prefix).all(
                 |(&search_character, &index_character)| {
-                    search_character == index_character
-                        || (search_character == b'I' && index_character == b'L')
-                        || (search_character == b'L' && index_character == b'I')
+                    self.equivalence_classes.equivalent(search_character, index_character)
                 },
             )
         } else {
@@ -380,7 +1019,7 @@ impl Searcher {
     ///
     /// # Arguments
     /// * `skip` - The used skip factor during the search iteration
-    /// * `il_locations` - The locations of the I's and L's in the **original** peptide
+    /// * `il_locations` - The locations of the I's and L's (or other equated residues) in the **original** peptide
     /// * `search_string` - The peptide that is being searched, but already with the skipped prefix removed from it
     /// * `index_string` - The suffix that search_string matches with when I and L were equalized during search
     /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
@@ -415,36 +1054,378 @@ impl Searcher {
     ///
     /// # Returns
     ///
-    /// Returns the proteins that every suffix is a part of 
+    /// Returns the proteins that every suffix is a part of, together with the index of each protein
     #[inline]
-    pub fn retrieve_proteins(&self, suffixes: &Vec<i64>) -> Vec<&Protein> {
-        let mut res = vec![];
-        for &suffix in suffixes {
-            let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
-            if !protein_index.is_null() {
-                res.push(&self.proteins[protein_index as usize]);
-            }
-        }
-        res
+    pub fn retrieve_proteins(&self, suffixes: &[i64]) -> Vec<IndexedProtein<'_>> {
+        suffixes.iter().filter_map(|&suffix| self.retrieve_protein(suffix)).collect()
     }
 
-    /// Searches all the matching proteins for a search_string/peptide in the suffix array
+    /// Returns the protein that corresponds with the provided suffix
     ///
     /// # Arguments
-    /// * `search_string` - The string/peptide being searched
-    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `suffix` - A suffix index
     ///
     /// # Returns
     ///
-    /// Returns the matching proteins for the search_string
-    pub fn search_proteins_for_peptide(&self, search_string: &[u8], equalize_i_and_l: bool) -> Vec<&Protein> {
-        let mut matching_suffixes = vec![];
+    /// Returns the index of the protein the suffix is a part of together with the protein,
+    /// or None if the suffix starts at a separation or termination character
+    #[inline]
+    pub fn retrieve_protein(&self, suffix: i64) -> Option<IndexedProtein<'_>> {
+        let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+        if protein_index.is_null() {
+            return None;
+        }
+        Some((protein_index as usize, &self.proteins[protein_index as usize]))
+    }
+
+    /// Explains the matches of a search string by listing every matched suffix with its protein, offset and context
+    /// This is meant for debugging why a peptide matches, it is not optimized for speed
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns a `MatchExplanation` with every suffix matching the search string, sorted by their position in the text
+    pub fn explain_match(&self, search_string: &[u8], equalize_i_and_l: bool) -> MatchExplanation {
+        let suffixes = match self.search_matching_suffixes_sorted(search_string, usize::MAX, equalize_i_and_l) {
+            SearchAllSuffixesResult::MaxMatches(suffixes) | SearchAllSuffixesResult::SearchResult(suffixes) => suffixes,
+            SearchAllSuffixesResult::NoMatches => vec![],
+        };
+
+        let mut matches = vec![];
+        for suffix in suffixes {
+            let Some((protein_index, protein)) = self.retrieve_protein(suffix) else {
+                continue;
+            };
+            let sequence = self.protein_sequence(protein_index);
+            let offset = suffix as usize - self.protein_starts[protein_index];
+            let end = offset + search_string.len();
+
+            matches.push(MatchedSuffix {
+                suffix,
+                uniprot_id: protein.uniprot_id.clone(),
+                offset,
+                before: String::from_utf8_lossy(&sequence[offset.saturating_sub(MATCH_CONTEXT_LENGTH)..offset]).to_string(),
+                matched: String::from_utf8_lossy(&sequence[offset..end]).to_string(),
+                after: String::from_utf8_lossy(&sequence[end..min(end + MATCH_CONTEXT_LENGTH, sequence.len())]).to_string(),
+                substitutions: (0..search_string.len()).filter(|&i| sequence[offset + i] != search_string[i]).collect(),
+            });
+        }
+
+        MatchExplanation {
+            search_string: String::from_utf8_lossy(search_string).to_string(),
+            matches,
+        }
+    }
+
+    /// Calculates how many matches of the peptides cover every residue of a protein, e.g. to visualize the coverage of a protein
+    ///
+    /// # Arguments
+    /// * `protein_index` - The index of the protein in the proteins of this searcher
+    /// * `peptides` - The peptides of which the matches in the protein are counted, peptides shorter than the sparseness factor are skipped
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of peptide matches that cover each residue of the protein,
+    /// or an empty vector if there is no protein with index `protein_index`
+    pub fn residue_coverage(&self, protein_index: usize, peptides: &[&[u8]], equalize_i_and_l: bool) -> Vec<u32> {
+        if protein_index >= self.proteins.len() {
+            return vec![];
+        }
+
+        let start = self.protein_starts[protein_index];
+        let end = self.protein_starts[protein_index + 1] - 1;
+        let mut coverage = vec![0; end - start];
+        for peptide in peptides.iter().filter(|peptide| peptide.len() >= self.sparseness_factor as usize) {
+            let suffixes = match self.search_matching_suffixes(peptide, usize::MAX, equalize_i_and_l) {
+                SearchAllSuffixesResult::MaxMatches(suffixes) | SearchAllSuffixesResult::SearchResult(suffixes) => suffixes,
+                SearchAllSuffixesResult::NoMatches => continue,
+            };
+
+            // a match never crosses the end of a protein, so only the start of the match has to be part of the protein
+            for suffix in suffixes.into_iter().map(|suffix| suffix as usize).filter(|suffix| (start..end).contains(suffix)) {
+                coverage[suffix - start..suffix - start + peptide.len()].iter_mut().for_each(|depth| *depth += 1);
+            }
+        }
+        coverage
+    }
+
+    /// Finds the k-mers of a protein that also occur in another protein, e.g. to find the peptides shared by orthologs
+    ///
+    /// # Arguments
+    /// * `protein_a` - The index of the protein of which the k-mers are enumerated
+    /// * `protein_b` - The index of the protein in which the k-mers of `protein_a` are searched
+    /// * `k` - The length of the k-mers, k-mers shorter than the sparseness factor can not be searched and are never shared
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the distinct k-mers of `protein_a` that occur in `protein_b`, in the order of their first occurrence in `protein_a`,
+    /// or an empty vector if one of the protein indices is out of range
+    pub fn shared_kmers(&self, protein_a: usize, protein_b: usize, k: usize, equalize_i_and_l: bool) -> Vec<String> {
+        if protein_a >= self.proteins.len() || protein_b >= self.proteins.len() || k == 0 || k < self.sparseness_factor as usize {
+            return vec![];
+        }
+
+        let sequence_a = &self.proteins.input_string[self.protein_starts[protein_a]..self.protein_starts[protein_a + 1] - 1];
+        let range_b = self.protein_starts[protein_b]..self.protein_starts[protein_b + 1] - 1;
+
+        let mut seen: HashSet<&[u8]> = HashSet::new();
+        sequence_a
+            .windows(k)
+            .filter(|kmer| seen.insert(kmer))
+            .filter(|kmer| match self.search_matching_suffixes(kmer, usize::MAX, equalize_i_and_l) {
+                SearchAllSuffixesResult::MaxMatches(suffixes) | SearchAllSuffixesResult::SearchResult(suffixes) => {
+                    // a match never crosses the end of a protein, so only the start of the match has to be part of protein B
+                    suffixes.into_iter().any(|suffix| range_b.contains(&(suffix as usize)))
+                }
+                SearchAllSuffixesResult::NoMatches => false,
+            })
+            .map(|kmer| String::from_utf8_lossy(kmer).to_string())
+            .collect()
+    }
+
+    /// Searches all the positions in the proteins that match a motif
+    /// The search branches at every ambiguous position of the motif, in the same way as the search branches for I and L,
+    /// and a branch is dropped as soon as its prefix no longer occurs in the proteins
+    /// At most `MAX_MOTIF_EXPANSIONS` branches are searched, the matches of the remaining branches are not returned
+    ///
+    /// # Arguments
+    /// * `motif` - The residues that are allowed at every position of the motif
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns a `MotifSearchResult` with the index of every matching protein together with the offset of the match in that protein,
+    /// sorted by their position in the text, and whether the search was truncated at `MAX_MOTIF_EXPANSIONS`
+    pub fn search_motif(&self, motif: &Motif, equalize_i_and_l: bool) -> MotifSearchResult {
+        if motif.is_empty() {
+            return MotifSearchResult { matches: vec![], truncated: false };
+        }
+
+        let mut suffixes = vec![];
+        let mut expansions = 0;
+        let completed =
+            self.expand_motif(motif, equalize_i_and_l, &mut Vec::with_capacity(motif.len()), &mut expansions, &mut suffixes);
+        suffixes.sort_unstable();
+
+        let matches = suffixes
+            .into_iter()
+            .filter_map(|suffix| {
+                let (protein_index, _) = self.retrieve_protein(suffix)?;
+                Some((protein_index, suffix as usize - self.protein_starts[protein_index]))
+            })
+            .collect();
+        MotifSearchResult { matches, truncated: !completed }
+    }
+
+    /// Extends a prefix of a motif with every residue allowed at the next position, and collects the suffixes matching the complete motif
+    ///
+    /// # Arguments
+    /// * `motif` - The residues that are allowed at every position of the motif
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `prefix` - The residues chosen for the first positions of the motif
+    /// * `expansions` - The number of branches searched so far, the expansion stops at `MAX_MOTIF_EXPANSIONS`
+    /// * `suffixes` - The suffixes that match the motif
+    ///
+    /// # Returns
+    ///
+    /// Returns false if a branch was not searched because `MAX_MOTIF_EXPANSIONS` was reached
+    fn expand_motif(
+        &self,
+        motif: &Motif,
+        equalize_i_and_l: bool,
+        prefix: &mut Vec<u8>,
+        expansions: &mut usize,
+        suffixes: &mut Vec<i64>,
+    ) -> bool {
+        let Some(allowed) = motif.get(prefix.len()) else {
+            self.visit_matching_suffixes(prefix, equalize_i_and_l, |suffix| {
+                suffixes.push(suffix);
+                true
+            });
+            return true;
+        };
+
+        // when I and L are equalized, both residues lead to the same matches, so only one of them has to be searched
+        let mut residues = allowed.clone();
+        if equalize_i_and_l {
+            residues.iter_mut().for_each(|residue| *residue = self.equivalence_classes.canonical(*residue));
+        }
+        residues.sort_unstable();
+        residues.dedup();
+
+        for residue in residues {
+            if *expansions >= MAX_MOTIF_EXPANSIONS {
+                return false;
+            }
+            *expansions += 1;
+
+            prefix.push(residue);
+            // a prefix shorter than the sparseness factor can not be searched, and the complete motif is searched by the next call
+            let completed = if prefix.len() < self.sparseness_factor as usize
+                || prefix.len() == motif.len()
+                || self.contains(prefix, equalize_i_and_l)
+            {
+                self.expand_motif(motif, equalize_i_and_l, prefix, expansions, suffixes)
+            } else {
+                true
+            };
+            prefix.pop();
+            if !completed {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Searches for the suffixes matching the reverse of a search string, to be used as a decoy for target-decoy FDR estimation
+    /// The reversed string is searched in exactly the same way as `search_matching_suffixes` searches the original string
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide of which the reverse is searched in the suffix array
+    /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
+    /// * `equalize_i_and_l` - True if we want to equate I and L (or the residues of the configured equivalence classes) during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns the reversed string together with all its matching suffixes
+    pub fn search_reversed(
+        &self,
+        search_string: &[u8],
+        max_matches: usize,
+        equalize_i_and_l: bool,
+    ) -> DecoySearchResult {
+        let reversed_peptide: Vec<u8> = search_string.iter().rev().copied().collect();
+        let result = self.search_matching_suffixes(&reversed_peptide, max_matches, equalize_i_and_l);
+
+        DecoySearchResult { reversed_peptide, result }
+    }
+
+    /// Searches all the matching proteins for a search_string/peptide in the suffix array
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching proteins for the search_string
+    pub fn search_proteins_for_peptide(&self, search_string: &[u8], equalize_i_and_l: bool) -> Vec<&Protein> {
+        let mut matching_suffixes = vec![];
         if let SearchAllSuffixesResult::SearchResult(suffixes) =
             self.search_matching_suffixes(search_string, usize::MAX, equalize_i_and_l)
         {
             matching_suffixes = suffixes;
         }
-        self.retrieve_proteins(&matching_suffixes)
+        self.retrieve_proteins(&matching_suffixes).into_iter().map(|(_, protein)| protein).collect()
+    }
+
+    /// Searches all the matching proteins for a search_string/peptide and groups them by their taxon id
+    /// A protein that matches the search string multiple times is only listed once in its group
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching proteins for the search_string, grouped by their taxon id
+    pub fn search_proteins_grouped_by_taxon(&self, search_string: &[u8], equalize_i_and_l: bool) -> HashMap<TaxonId, Vec<&Protein>> {
+        let mut grouped: HashMap<TaxonId, Vec<&Protein>> = HashMap::new();
+        for protein in self.search_proteins_for_peptide(search_string, equalize_i_and_l) {
+            let group = grouped.entry(protein.taxon_id).or_default();
+            if !group.iter().any(|&other| std::ptr::eq(other, protein)) {
+                group.push(protein);
+            }
+        }
+        grouped
+    }
+
+    /// Searches the matching proteins for a search_string/peptide that have a certain functional annotation
+    /// The annotations of the matching proteins are not decoded, the encoded annotations are scanned for the required annotation
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `required` - The functional annotation the proteins should have, including its prefix (e.g. `GO:0009279`)
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching proteins for the search_string that have the `required` functional annotation
+    pub fn search_proteins_with_annotation(&self, search_string: &[u8], required: &str, equalize_i_and_l: bool) -> Vec<&Protein> {
+        let mut proteins = self.search_proteins_for_peptide(search_string, equalize_i_and_l);
+        proteins.retain(|protein| protein.has_functional_annotation(required));
+        proteins
+    }
+
+    /// Searches the matching proteins for a search_string/peptide that belong to a clade of the taxonomy
+    /// A protein belongs to the clade if `root_taxon` is part of the lineage of its taxon
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `root_taxon` - The taxon at the root of the clade, e.g. 2 to only keep the proteins of Bacteria
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching proteins for the search_string whose taxon is `root_taxon` or one of its descendants
+    pub fn search_proteins_in_clade(&self, search_string: &[u8], root_taxon: TaxonId, equalize_i_and_l: bool) -> Vec<&Protein> {
+        let mut proteins = self.search_proteins_for_peptide(search_string, equalize_i_and_l);
+        proteins.retain(|protein| self.taxon_id_calculator.lineage(protein.taxon_id).contains(&root_taxon));
+        proteins
+    }
+
+    /// Checks if a search string occurs in the index, this is the cheapest possible query
+    /// The search stops at the first match, the matching suffixes are not collected and not mapped to their proteins
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns true if `search_string` has at least one match, otherwise false
+    pub fn contains(&self, search_string: &[u8], equalize_i_and_l: bool) -> bool {
+        // the visit stops (and returns false) as soon as the first matching suffix is visited
+        !self.visit_matching_suffixes(search_string, equalize_i_and_l, |_| false)
+    }
+
+    /// Searches the protein of a unique peptide, a peptide that only occurs in a single protein
+    /// The search stops as soon as a match in a second protein is found
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the protein if all the matches of `search_string` are part of the same protein
+    /// Returns None if `search_string` has no matches or matches multiple proteins
+    pub fn is_unique_peptide(&self, search_string: &[u8], equalize_i_and_l: bool) -> Option<&Protein> {
+        let mut protein_index = None;
+        let mut unique = true;
+        self.visit_matching_suffixes(search_string, equalize_i_and_l, |suffix| {
+            let current_protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+            if current_protein_index.is_null() {
+                return true;
+            }
+            match protein_index {
+                None => protein_index = Some(current_protein_index),
+                Some(index) if index != current_protein_index => unique = false,
+                _ => {}
+            }
+            unique
+        });
+
+        if unique {
+            protein_index.map(|index| &self.proteins[index as usize])
+        } else {
+            None
+        }
     }
 
     /// Retrieves the taxonomic analysis for a collection of proteins
@@ -457,10 +1438,67 @@ impl Searcher {
     /// Returns the taxonomic analysis result for the given list of proteins
     #[inline]
     pub fn retrieve_lca(&self, proteins: &[&Protein]) -> Option<TaxonId> {
-        let taxon_ids: Vec<TaxonId> = proteins.iter().map(|prot| prot.taxon_id).collect();
-        
+        self.retrieve_raw_lca(proteins)
+            .map(|id| self.taxon_id_calculator
+                .snap_taxon(id)
+            )
+    }
+
+    /// Retrieves the taxonomic analysis for a collection of proteins, without snapping the result to a valid taxon
+    /// The result differs from `retrieve_lca` when the aggregation results in an invalid or unranked ("no rank") taxon
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxon id of the aggregation as it is, or None if there are no proteins
+    #[inline]
+    pub fn retrieve_raw_lca(&self, proteins: &[&Protein]) -> Option<TaxonId> {
         self.taxon_id_calculator
-            .aggregate(taxon_ids)
+            .aggregate_iter(proteins.iter().flat_map(|prot| prot.taxa()))
+    }
+
+    /// Retrieves the taxonomic analysis for a collection of proteins, together with the taxa that were aggregated
+    /// This makes it possible to audit which taxa drove the aggregation to its result
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxon id of the aggregation and for every aggregated taxon the number of proteins with that taxon, sorted by taxon id.
+    /// Returns None if there are no proteins
+    pub fn retrieve_lca_with_support(&self, proteins: &[&Protein]) -> Option<(TaxonId, Vec<(TaxonId, usize)>)> {
+        let lca = self.retrieve_lca(proteins)?;
+
+        let mut support: HashMap<TaxonId, usize> = HashMap::new();
+        for taxon_id in proteins.iter().flat_map(|prot| prot.taxa()) {
+            *support.entry(taxon_id).or_insert(0) += 1;
+        }
+        let mut support: Vec<(TaxonId, usize)> = support.into_iter().collect();
+        support.sort_unstable();
+
+        Some((lca, support))
+    }
+
+    /// Retrieves the taxonomic analysis for the matches of multiple peptides, weighting the proteins of each peptide
+    ///
+    /// # Arguments
+    /// * `weighted_proteins` - For each peptide the proteins it matched, together with a weight such as the spectral count or intensity of the peptide
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxon id of the weighted aggregation, snapped to a valid taxon
+    /// Returns None if there are no proteins
+    pub fn retrieve_weighted_lca(&self, weighted_proteins: &[(Vec<&Protein>, f32)]) -> Option<TaxonId> {
+        let taxa_with_weights: Vec<(TaxonId, f32)> = weighted_proteins
+            .iter()
+            .flat_map(|(proteins, weight)| proteins.iter().flat_map(|prot| prot.taxa().map(|taxon_id| (taxon_id, *weight))))
+            .collect();
+
+        self.taxon_id_calculator
+            .aggregate_weighted(taxa_with_weights)
             .map(|id| self.taxon_id_calculator
                 .snap_taxon(id)
             )
@@ -473,9 +1511,9 @@ impl Searcher {
     ///
     /// # Returns
     ///
-    ///  Returns true if the protein is considered valid by the provided taxonomy file
+    ///  Returns true if the protein, or one of the proteins collapsed into it, is considered valid by the provided taxonomy file
     pub fn taxon_valid(&self, protein: &Protein) -> bool {
-        self.taxon_id_calculator.taxon_valid(protein.taxon_id)
+        protein.taxa().any(|taxon_id| self.taxon_id_calculator.taxon_valid(taxon_id))
     }
 
     /// Retrieves the functional analysis for a collection of proteins
@@ -491,6 +1529,21 @@ impl Searcher {
         Some(res)
     }
 
+    /// Retrieves the functional analysis for a collection of proteins, using a cache shared by all peptides of a batch
+    /// The functional annotations of every protein are decoded at most once per cache, which avoids repeating this work for proteins matched by many peptides
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins together with their index, as returned by `retrieve_proteins`
+    /// * `cache` - The cache with the already decoded functional annotations
+    ///
+    /// # Returns
+    ///
+    /// Returns the functional analysis result for the given list of proteins, identical to the result of `retrieve_function`
+    pub fn retrieve_function_cached(&self, proteins: &[IndexedProtein], cache: &AnnotationCache) -> Option<FunctionalAggregation> {
+        let res = self.function_aggregator.aggregate_cached(proteins, cache);
+        Some(res)
+    }
+
     /// Retrieves the all the functional annotations for a collection of proteins
     ///
     /// # Arguments
@@ -502,64 +1555,142 @@ impl Searcher {
     pub fn get_all_functional_annotations(&self, proteins: &[&Protein]) -> Vec<Vec<String>> {
         self.function_aggregator.get_all_functional_annotations(proteins)
     }
+
+    /// Retrieves the functional annotations for a collection of proteins, paired with the protein they belong to
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns a list of tuples with the uniprot id of each protein and its functional annotations
+    pub fn annotations_by_protein(&self, proteins: &[&Protein]) -> Vec<(String, Vec<String>)> {
+        self.function_aggregator.annotations_by_protein(proteins)
+    }
+
+    /// Retrieves the sequence of a protein
+    ///
+    /// # Arguments
+    /// * `protein_index` - The index of a protein of this searcher, e.g. retrieved with `retrieve_proteins`
+    ///
+    /// # Returns
+    ///
+    /// Returns the sequence of the protein as it is stored in the text, so I and L are not equated
+    pub fn protein_sequence(&self, protein_index: usize) -> &[u8] {
+        &self.proteins.input_string[self.protein_starts[protein_index]..self.protein_starts[protein_index + 1] - 1]
+    }
+
+    /// Returns the proteins in which the searcher searches
+    pub fn proteins(&self) -> &Proteins {
+        &self.proteins
+    }
+
+    /// Returns the taxonomy used for the taxonomic analysis, e.g. to look up the name and rank of a taxon
+    pub fn taxonomy(&self) -> &TaxonAggregator {
+        &self.taxon_id_calculator
+    }
+
+    /// Returns the (sparse) suffix array in which the searcher searches, without the suffixes of appended proteins
+    pub fn suffix_array(&self) -> &[T] {
+        &self.sa
+    }
+
+    /// Returns the sparseness factor used by the suffix array
+    pub fn sparseness_factor(&self) -> u8 {
+        self.sparseness_factor
+    }
+
+    /// Calculates a summary of the index in which the searcher searches
+    ///
+    /// # Returns
+    ///
+    /// Returns the `IndexStatistics` of the proteins and the suffix array of this searcher
+    pub fn statistics(&self) -> IndexStatistics {
+        // the separation (or termination) character after every protein is not part of its length
+        let mut lengths: Vec<usize> = self.protein_starts.windows(2).map(|bounds| bounds[1] - bounds[0] - 1).collect();
+        lengths.sort_unstable();
+        let distinct_taxa: HashSet<TaxonId> = self.proteins.iter().flat_map(Protein::taxa).collect();
+
+        IndexStatistics {
+            protein_count: self.proteins.len(),
+            residue_count: lengths.iter().sum(),
+            distinct_taxa: distinct_taxa.len(),
+            min_protein_length: lengths.first().copied().unwrap_or_default(),
+            median_protein_length: lengths.get(lengths.len() / 2).copied().unwrap_or_default(),
+            max_protein_length: lengths.last().copied().unwrap_or_default(),
+            suffix_array_length: self.sa.len() + self.appended_sa.len(),
+            sparseness_factor: self.sparseness_factor,
+        }
+    }
     
 }
 
 
 #[cfg(test)]
 mod tests {
+    use fa_compression::algorithm1::encode;
     use sa_mappings::functionality::FunctionAggregator;
     use sa_mappings::proteins::{Protein, Proteins};
     use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray_builder::{build_sa, build_sa_with_equivalence_classes, SAConstructionAlgorithm};
+    use suffixarray_builder::equivalence_classes::EquivalenceClasses;
     use crate::sa_searcher::{
-        BoundSearchResult, SearchAllSuffixesResult, Searcher,
+        BoundSearchResult, DecoySearchResult, IndexStatistics, MatchedSuffix, MAX_MOTIF_EXPANSIONS, MotifSearchResult, normalize_il, SearchAllSuffixesResult, Searcher, SearcherError,
     };
     use crate::suffix_to_protein_index::SparseSuffixToProtein;
 
     fn get_example_proteins() -> Proteins {
         let text = "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes();
         Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![
                 Protein {
                     uniprot_id: String::new(),
                     taxon_id: 0,
                     functional_annotations: vec![],
+                    extra_accessions: vec![],
                 },
                 Protein {
                     uniprot_id: String::new(),
                     taxon_id: 0,
                     functional_annotations: vec![],
+                    extra_accessions: vec![],
                 },
                 Protein {
                     uniprot_id: String::new(),
                     taxon_id: 0,
                     functional_annotations: vec![],
+                    extra_accessions: vec![],
                 },
                 Protein {
                     uniprot_id: String::new(),
                     taxon_id: 0,
                     functional_annotations: vec![],
+                    extra_accessions: vec![],
                 },
             ],
         }
     }
 
-    #[test]
-    fn test_search_simple() {
-        let proteins = get_example_proteins();
-        let sa = vec![
-            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
-        ];
+    fn get_example_searcher(sparseness_factor: u8) -> Searcher {
+        get_searcher(get_example_proteins(), sparseness_factor)
+    }
 
-        let searcher = Searcher::new(
+    fn get_searcher(proteins: Proteins, sparseness_factor: u8) -> Searcher {
+        let sa = build_sa(&mut proteins.input_string.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor).unwrap();
+        Searcher::new(
             sa,
-            1,
+            sparseness_factor,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
             TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
             FunctionAggregator {}
-        );
+        )
+    }
+
+    #[test]
+    fn test_search_simple() {
+        let searcher = get_example_searcher(1);
 
         // search bounds 'A'
         let bounds_res = searcher.search_bounds(&[b'A']);
@@ -576,17 +1707,7 @@ mod tests {
 
     #[test]
     fn test_search_sparse() {
-        let proteins = get_example_proteins();
-        let sa = vec![9, 0, 3, 12, 15, 6, 18];
-
-        let searcher = Searcher::new(
-            sa,
-            3,
-            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
-            proteins,
-            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
-            FunctionAggregator {}
-        );
+        let searcher = get_example_searcher(3);
 
         // search suffix 'VAA'
         let found_suffixes =
@@ -606,19 +1727,7 @@ mod tests {
 
     #[test]
     fn test_il_equality() {
-        let proteins = get_example_proteins();
-        let sa = vec![
-            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
-        ];
-
-        let searcher = Searcher::new(
-            sa,
-            1,
-            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
-            proteins,
-            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
-            FunctionAggregator {}
-        );
+        let searcher = get_example_searcher(1);
 
         let bounds_res = searcher.search_bounds(&[b'I']);
         assert_eq!(bounds_res, BoundSearchResult::SearchResult((13, 16)));
@@ -630,17 +1739,7 @@ mod tests {
 
     #[test]
     fn test_il_equality_sparse() {
-        let proteins = get_example_proteins();
-        let sa = vec![9, 0, 3, 12, 15, 6, 18];
-
-        let searcher = Searcher::new(
-            sa,
-            3,
-            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
-            proteins,
-            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
-            FunctionAggregator {}
-        );
+        let searcher = get_example_searcher(3);
 
         // search bounds 'RIZ' with equal I and L
         let found_suffixes =
@@ -662,11 +1761,12 @@ mod tests {
         let text = "LMOXZ$".to_string().into_bytes();
 
         let proteins = Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![Protein {
                 uniprot_id: String::new(),
                 taxon_id: 0,
                 functional_annotations: vec![],
+                extra_accessions: vec![],
             }],
         };
 
@@ -693,11 +1793,12 @@ mod tests {
         let text = "AAILLL$".to_string().into_bytes();
 
         let proteins = Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![Protein {
                 uniprot_id: String::new(),
                 taxon_id: 0,
                 functional_annotations: vec![],
+                extra_accessions: vec![],
             }],
         };
 
@@ -723,11 +1824,12 @@ mod tests {
         let text = "IIIILL$".to_string().into_bytes();
 
         let proteins = Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![Protein {
                 uniprot_id: String::new(),
                 taxon_id: 0,
                 functional_annotations: vec![],
+                extra_accessions: vec![],
             }],
         };
 
@@ -753,11 +1855,12 @@ mod tests {
         let text = "IIIILL$".to_string().into_bytes();
 
         let proteins = Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![Protein {
                 uniprot_id: String::new(),
                 taxon_id: 0,
                 functional_annotations: vec![],
+                extra_accessions: vec![],
             }],
         };
 
@@ -785,11 +1888,12 @@ mod tests {
         let text = "IILLLL$".to_string().into_bytes();
 
         let proteins = Proteins {
-            input_string: text,
+            input_string: text.into(),
             proteins: vec![Protein {
                 uniprot_id: String::new(),
                 taxon_id: 0,
                 functional_annotations: vec![],
+                extra_accessions: vec![],
             }],
         };
 
@@ -810,4 +1914,697 @@ mod tests {
             SearchAllSuffixesResult::SearchResult(vec![0, 1, 2, 3, 4])
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_append_proteins() {
+        let searcher = get_example_searcher(1);
+
+        // 'MQWY' is not part of the original proteins
+        assert!(searcher.search_proteins_for_peptide(&[b'Q', b'W', b'Y'], false).is_empty());
+
+        let new_proteins = Proteins {
+            input_string: "MQWY-ACN$".to_string().into_bytes().into(),
+            proteins: vec![
+                Protein {
+                    uniprot_id: "P1".to_string(),
+                    taxon_id: 0,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![],
+                },
+                Protein {
+                    uniprot_id: "P2".to_string(),
+                    taxon_id: 0,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![],
+                },
+            ],
+        };
+        let searcher = searcher
+            .with_appended_proteins(new_proteins, &SAConstructionAlgorithm::LibSais)
+            .unwrap();
+
+        // the unique peptide of the appended protein is found
+        let found_proteins = searcher.search_proteins_for_peptide(&[b'Q', b'W', b'Y'], false);
+        assert_eq!(found_proteins.len(), 1);
+        assert_eq!(found_proteins[0].uniprot_id, "P1");
+
+        // matches in the original and appended proteins are combined
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'C'], usize::MAX, false);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![5, 11, 25])
+        );
+    }
+
+    #[test]
+    fn test_append_proteins_in_batches() {
+        let searcher = get_example_searcher(1);
+
+        let new_protein = |input_string: &str, uniprot_id: &str| Proteins {
+            input_string: input_string.to_string().into_bytes().into(),
+            proteins: vec![Protein {
+                uniprot_id: uniprot_id.to_string(),
+                taxon_id: 0,
+                functional_annotations: vec![],
+                extra_accessions: vec![],
+            }],
+        };
+        let searcher = searcher
+            .with_appended_proteins(new_protein("MQWYAC$", "P1"), &SAConstructionAlgorithm::LibSais)
+            .unwrap()
+            .with_appended_proteins(new_protein("ACNQWY$", "P2"), &SAConstructionAlgorithm::LibSais)
+            .unwrap();
+
+        // the merged secondary suffix array contains the suffixes of both batches
+        assert_eq!(searcher.appended_sa.len(), 14);
+
+        // matches in both batches are found
+        let found_proteins = searcher.search_proteins_for_peptide(&[b'Q', b'W', b'Y'], false);
+        let mut found_ids: Vec<&str> = found_proteins.iter().map(|protein| protein.uniprot_id.as_str()).collect();
+        found_ids.sort();
+        assert_eq!(found_ids, vec!["P1", "P2"]);
+
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'C'], usize::MAX, false);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![5, 11, 24, 27])
+        );
+
+        // the bounds only index in the original suffix array, so they exclude the appended proteins
+        assert_eq!(searcher.search_bounds(b"AC"), BoundSearchResult::SearchResult((6, 8)));
+        assert_eq!(searcher.search_bounds(b"QWY"), BoundSearchResult::NoMatches);
+        assert_eq!(searcher.search_bounds_with_depth(b"QWY"), (BoundSearchResult::NoMatches, 0));
+    }
+
+    #[test]
+    fn test_search_longer_than_proteins() {
+        let searcher = get_example_searcher(1);
+        assert_eq!(searcher.max_protein_length, 7);
+
+        // the longest protein can still be found
+        let bounds_res = searcher.search_bounds("BLACVAA".as_bytes());
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((9, 10)));
+
+        // a peptide longer than the longest protein returns immediately
+        let bounds_res = searcher.search_bounds("BLACVAAA".as_bytes());
+        assert_eq!(bounds_res, BoundSearchResult::NoMatches);
+        let matches = searcher.search_matching_suffixes("BLACVAAA".as_bytes(), usize::MAX, false);
+        assert_eq!(matches, SearchAllSuffixesResult::NoMatches);
+    }
+
+    #[test]
+    fn test_equivalence_classes() {
+        let mut text = "AKC-AQC-AIC$".to_string().into_bytes();
+        let proteins = Proteins {
+            input_string: text.clone().into(),
+            proteins: (0..3)
+                .map(|_| Protein {
+                    uniprot_id: String::new(),
+                    taxon_id: 0,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![],
+                })
+                .collect(),
+        };
+        let sa = build_sa_with_equivalence_classes(
+            &mut text,
+            &EquivalenceClasses::new(&["IL", "KQ"]),
+            &SAConstructionAlgorithm::LibSais,
+            1
+        ).unwrap();
+
+        let searcher = Searcher::new(
+            sa,
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        ).with_equivalence_classes(EquivalenceClasses::new(&["IL", "KQ"]));
+
+        // K and Q are equated
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'Q', b'C'], usize::MAX, true);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![0, 4]));
+
+        // without equating, only the exact match is found
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'Q', b'C'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![4]));
+        let found_suffixes = searcher.search_matching_suffixes(&[b'K', b'C'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![1]));
+
+        // I and L are still equated
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'L', b'C'], usize::MAX, true);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![8]));
+    }
+
+    #[test]
+    fn test_search_bounds_with_depth() {
+        let searcher = get_example_searcher(1);
+
+        // only the first 3 characters of the 5-mer match (with ACVAA)
+        let (bounds_res, depth) = searcher.search_bounds_with_depth("ACVQQ".as_bytes());
+        assert_eq!(bounds_res, BoundSearchResult::NoMatches);
+        assert_eq!(depth, 3);
+
+        // a full match reaches the length of the search string
+        let (bounds_res, depth) = searcher.search_bounds_with_depth("ACVAA".as_bytes());
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((7, 8)));
+        assert_eq!(depth, 5);
+    }
+
+    #[test]
+    fn test_search_32_and_64_bit() {
+        let sa: Vec<i64> = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+        let proteins = get_example_proteins();
+        let searcher_64: Searcher<i64> = Searcher::new(
+            sa.clone(),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+        let proteins = get_example_proteins();
+        let searcher_32: Searcher<i32> = Searcher::new(
+            sa.iter().map(|&suffix| suffix as i32).collect(),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        for search_string in ["A", "AC", "IA", "BLACVAA", "KCRLZ", "Q"] {
+            assert_eq!(
+                searcher_32.search_bounds(search_string.as_bytes()),
+                searcher_64.search_bounds(search_string.as_bytes())
+            );
+            assert_eq!(
+                searcher_32.search_matching_suffixes(search_string.as_bytes(), usize::MAX, false),
+                searcher_64.search_matching_suffixes(search_string.as_bytes(), usize::MAX, false)
+            );
+        }
+        assert_eq!(
+            searcher_32.search_matching_suffixes(&[b'A', b'C'], usize::MAX, false),
+            SearchAllSuffixesResult::SearchResult(vec![5, 11])
+        );
+    }
+
+    #[test]
+    fn test_is_unique_peptide() {
+        let searcher = get_example_searcher(1);
+
+        // VAA only occurs in the second protein, AA occurs twice in the second protein
+        let protein = searcher.is_unique_peptide("VAA".as_bytes(), false).unwrap();
+        assert!(std::ptr::eq(protein, &searcher.proteins[1]));
+        let protein = searcher.is_unique_peptide("AA".as_bytes(), false).unwrap();
+        assert!(std::ptr::eq(protein, &searcher.proteins[1]));
+
+        // AC occurs in the second and third protein
+        assert!(searcher.is_unique_peptide("AC".as_bytes(), false).is_none());
+        // no matches
+        assert!(searcher.is_unique_peptide("QQ".as_bytes(), false).is_none());
+    }
+
+    #[test]
+    fn test_normalize_il() {
+        let mut peptide = "LEIL".as_bytes().to_vec();
+        normalize_il(&mut peptide);
+        assert_eq!(peptide, "IEII".as_bytes());
+    }
+
+    #[test]
+    fn test_normalized_search_equals_raw_search() {
+        let searcher = get_example_searcher(1);
+
+        let mut normalized = "BIAC".as_bytes().to_vec();
+        normalize_il(&mut normalized);
+        assert_eq!(
+            searcher.search_matching_suffixes("BLAC".as_bytes(), usize::MAX, true),
+            searcher.search_matching_suffixes(&normalized, usize::MAX, true)
+        );
+        assert_eq!(
+            searcher.search_matching_suffixes(&normalized, usize::MAX, true),
+            SearchAllSuffixesResult::SearchResult(vec![3])
+        );
+    }
+
+    #[test]
+    fn test_search_reversed() {
+        let searcher = get_example_searcher(1);
+
+        // a palindromic peptide gives the same matches as the forward search
+        let decoy = searcher.search_reversed("AA".as_bytes(), usize::MAX, false);
+        assert_eq!(decoy, DecoySearchResult {
+            reversed_peptide: "AA".as_bytes().to_vec(),
+            result: searcher.search_matching_suffixes("AA".as_bytes(), usize::MAX, false),
+        });
+
+        // AC occurs twice, but CA does not occur
+        let decoy = searcher.search_reversed("AC".as_bytes(), usize::MAX, false);
+        assert_eq!(decoy.reversed_peptide, "CA".as_bytes());
+        assert_eq!(decoy.result, SearchAllSuffixesResult::NoMatches);
+        assert_eq!(searcher.search_matching_suffixes("AC".as_bytes(), usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![5, 11]));
+
+        // I and L are equated in the same way as in the forward search
+        let decoy = searcher.search_reversed("IR".as_bytes(), usize::MAX, true);
+        assert_eq!(decoy.result, SearchAllSuffixesResult::SearchResult(vec![16]));
+    }
+
+    #[test]
+    fn test_search_matching_suffixes_sorted() {
+        let searcher = get_example_searcher(1);
+
+        // compare the variants directly, since the PartialEq of SearchAllSuffixesResult ignores the order
+        let get_suffixes = |result: SearchAllSuffixesResult| match result {
+            SearchAllSuffixesResult::SearchResult(suffixes) => suffixes,
+            _ => panic!("Expected matches"),
+        };
+
+        let first = get_suffixes(searcher.search_matching_suffixes_sorted("A".as_bytes(), usize::MAX, true));
+        assert_eq!(first, vec![0, 5, 8, 9, 11]);
+        for _ in 0..5 {
+            assert_eq!(get_suffixes(searcher.search_matching_suffixes_sorted("A".as_bytes(), usize::MAX, true)), first);
+        }
+
+        let il_matches = get_suffixes(searcher.search_matching_suffixes_sorted("I".as_bytes(), usize::MAX, true));
+        assert_eq!(il_matches, vec![1, 4, 17]);
+        assert_eq!(searcher.search_matching_suffixes_sorted("Q".as_bytes(), usize::MAX, true), SearchAllSuffixesResult::NoMatches);
+    }
+
+    #[test]
+    fn test_suffix_array_access() {
+        let proteins = get_example_proteins();
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            sa.clone(),
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        assert_eq!(searcher.suffix_array().len(), sa.len());
+        assert_eq!(searcher.suffix_array(), &sa[..]);
+        assert_eq!(searcher.sparseness_factor(), 3);
+    }
+
+    #[test]
+    fn test_contains() {
+        let searcher = get_example_searcher(3);
+
+        assert!(searcher.contains(b"AC", false));
+        assert!(searcher.contains(b"KCRL", false));
+        assert!(searcher.contains(b"VAA", false));
+        assert!(!searcher.contains(b"ACK", false));
+
+        // search strings shorter than the sparseness factor that do not occur
+        assert!(!searcher.contains(b"W", false));
+        assert!(!searcher.contains(b"AW", false));
+
+        // "BLAC" only occurs when I and L are equated
+        assert!(!searcher.contains(b"BIAC", false));
+        assert!(searcher.contains(b"BIAC", true));
+    }
+
+    #[test]
+    fn test_search_proteins_with_annotation() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[1].functional_annotations = encode("GO:0009279;IPR:IPR016364");
+        proteins.proteins[2].functional_annotations = encode("EC:1.1.1.-");
+        let searcher = get_searcher(proteins, 1);
+
+        // AC occurs in the second and third protein, but only the second one has the GO term
+        assert_eq!(searcher.search_proteins_for_peptide(b"AC", false).len(), 2);
+        let matches = searcher.search_proteins_with_annotation(b"AC", "GO:0009279", false);
+        assert_eq!(matches.len(), 1);
+        assert!(std::ptr::eq(matches[0], &searcher.proteins[1]));
+
+        assert_eq!(searcher.search_proteins_with_annotation(b"AC", "EC:1.1.1.-", false).len(), 1);
+        assert!(searcher.search_proteins_with_annotation(b"AC", "GO:0000001", false).is_empty());
+    }
+
+    #[test]
+    fn test_retrieve_lca_deduplicated() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[1].taxon_id = 7;
+        proteins.proteins[1].extra_accessions = vec![("P2".to_string(), 9)];
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            sa,
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::Lca).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // the taxon of the collapsed protein is part of the aggregation
+        let matches = searcher.search_proteins_for_peptide(b"BLAC", false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(searcher.retrieve_lca(&matches), Some(6));
+        assert_eq!(searcher.retrieve_weighted_lca(&[(matches, 1.0)]), Some(6));
+    }
+
+    #[test]
+    fn test_explain_match() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[1].uniprot_id = "P1".to_string();
+        let searcher = get_searcher(proteins, 3);
+
+        // the I of the search string is matched to the L of "BLACVAA"
+        let explanation = searcher.explain_match(b"BIAC", true);
+        assert_eq!(
+            explanation.matches,
+            vec![MatchedSuffix {
+                suffix: 3,
+                uniprot_id: "P1".to_string(),
+                offset: 0,
+                before: String::new(),
+                matched: "BLAC".to_string(),
+                after: "VAA".to_string(),
+                substitutions: vec![1],
+            }]
+        );
+        assert_eq!(explanation.to_string(), "BIAC: 1 matches\nP1\t0\t[BlAC]VAA\n");
+
+        let explanation = searcher.explain_match(b"AC", false);
+        assert_eq!(explanation.matches.len(), 2);
+        assert_eq!(explanation.matches[0].offset, 2);
+        assert_eq!(explanation.matches[0].before, "BL");
+        assert_eq!(explanation.matches[1].offset, 0);
+        assert!(explanation.matches.iter().all(|matched_suffix| matched_suffix.substitutions.is_empty()));
+
+        assert!(searcher.explain_match(b"BIAC", false).matches.is_empty());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[0].taxon_id = 6;
+        proteins.proteins[1].taxon_id = 7;
+        proteins.proteins[2].taxon_id = 6;
+        proteins.proteins[3].extra_accessions = vec![("P5".to_string(), 9)];
+        let searcher = get_searcher(proteins, 3);
+
+        // the proteins are AI, BLACVAA, AC and KCRLZ
+        assert_eq!(searcher.statistics(), IndexStatistics {
+            protein_count: 4,
+            residue_count: 16,
+            distinct_taxa: 4,
+            min_protein_length: 2,
+            median_protein_length: 5,
+            max_protein_length: 7,
+            suffix_array_length: 7,
+            sparseness_factor: 3,
+        });
+    }
+
+    #[test]
+    fn test_kmer_filter() {
+        let searcher = get_example_searcher(1);
+        let filtered_searcher = get_example_searcher(1).with_kmer_filter(2, 0.01);
+
+        // present peptides give the same results with and without the filter, also when I and L are equated
+        for peptide in [b"AC".as_slice(), b"BLAC", b"BIAC", b"KCRLZ", b"A", b"VAA"] {
+            for equalize_i_and_l in [false, true] {
+                assert_eq!(
+                    filtered_searcher.search_matching_suffixes(peptide, usize::MAX, equalize_i_and_l),
+                    searcher.search_matching_suffixes(peptide, usize::MAX, equalize_i_and_l)
+                );
+            }
+            assert_eq!(filtered_searcher.search_bounds(peptide), searcher.search_bounds(peptide));
+        }
+
+        // absent peptides are rejected by the filter, without searching the suffix array
+        for peptide in [b"WW".as_slice(), b"ACWW", b"KCRLZQ"] {
+            assert!(!filtered_searcher.may_contain(peptide));
+            assert_eq!(filtered_searcher.search_matching_suffixes(peptide, usize::MAX, true), SearchAllSuffixesResult::NoMatches);
+            assert_eq!(filtered_searcher.search_bounds(peptide), BoundSearchResult::NoMatches);
+        }
+
+        // absent peptides with only present k-mers are still rejected by the search itself
+        assert!(filtered_searcher.may_contain(b"ACR"));
+        assert_eq!(filtered_searcher.search_matching_suffixes(b"ACR", usize::MAX, false), SearchAllSuffixesResult::NoMatches);
+    }
+
+    #[test]
+    fn test_search_string_with_sentinel() {
+        let searcher = get_example_searcher(1);
+
+        // "AI-B" occurs in the text, but spans the boundary of the first 2 proteins
+        for equalize_i_and_l in [false, true] {
+            assert_eq!(searcher.search_matching_suffixes(b"AI-B", usize::MAX, equalize_i_and_l), SearchAllSuffixesResult::NoMatches);
+            assert_eq!(searcher.count_matching_suffixes(b"AI-B", equalize_i_and_l), 0);
+        }
+        assert_eq!(searcher.search_matching_suffixes(b"LZ$", usize::MAX, false), SearchAllSuffixesResult::NoMatches);
+        assert_eq!(searcher.search_matching_suffixes(b"-", usize::MAX, false), SearchAllSuffixesResult::NoMatches);
+        // the parts of the search string on both sides of the boundary still match
+        assert_eq!(searcher.search_matching_suffixes(b"AI", usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![0]));
+    }
+
+    #[test]
+    fn test_searchers_share_mapped_text() {
+        let tmp_dir = std::env::temp_dir().join(format!("test_searchers_share_mapped_text_{}", std::process::id()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let text_file = tmp_dir.join("text.bin");
+        let metadata_file = tmp_dir.join("metadata.tsv");
+        let (text_file, metadata_file) = (text_file.to_str().unwrap(), metadata_file.to_str().unwrap());
+        get_example_proteins().write_mmap_files(text_file, metadata_file).unwrap();
+
+        // 2 searchers that map the same files, like 2 server processes would
+        let open_searcher = || get_searcher(Proteins::try_from_mmap(text_file, metadata_file).unwrap(), 1);
+        let searcher1 = open_searcher();
+        let searcher2 = open_searcher();
+
+        for peptide in [b"A".as_slice(), b"AC", b"BLAC", b"BIAC", b"KCRLZ", b"Q"] {
+            for equalize_i_and_l in [false, true] {
+                assert_eq!(
+                    searcher1.search_matching_suffixes_sorted(peptide, usize::MAX, equalize_i_and_l),
+                    searcher2.search_matching_suffixes_sorted(peptide, usize::MAX, equalize_i_and_l)
+                );
+            }
+        }
+
+        // searching never copies the mapped text into private memory
+        assert!(searcher1.proteins.input_string.is_mapped());
+        assert!(searcher2.proteins.input_string.is_mapped());
+        assert_eq!(&searcher1.proteins.input_string[..], &get_example_proteins().input_string[..]);
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_proteins_grouped_by_taxon() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[0].taxon_id = 6;
+        proteins.proteins[1].taxon_id = 6;
+        proteins.proteins[2].taxon_id = 7;
+        let searcher = get_searcher(proteins, 1);
+
+        // AC occurs in the second protein (taxon 6) and the third protein (taxon 7)
+        let grouped = searcher.search_proteins_grouped_by_taxon(b"AC", false);
+        assert_eq!(grouped.len(), 2);
+        assert!(std::ptr::eq(grouped[&6][0], &searcher.proteins[1]));
+        assert!(std::ptr::eq(grouped[&7][0], &searcher.proteins[2]));
+
+        // A occurs 3 times in the second protein, which is only listed once
+        let grouped = searcher.search_proteins_grouped_by_taxon(b"A", false);
+        assert_eq!(grouped[&6].len(), 2);
+        assert_eq!(grouped[&7].len(), 1);
+
+        assert!(searcher.search_proteins_grouped_by_taxon(b"W", false).is_empty());
+    }
+
+    #[test]
+    fn test_try_new_checks_suffix_array_length() {
+        let create_searcher = |sa: Vec<i64>, sparseness_factor: u8| {
+            let proteins = get_example_proteins();
+            Searcher::try_new(
+                sa,
+                sparseness_factor,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+                FunctionAggregator {}
+            )
+        };
+
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+        assert!(create_searcher(sa.clone(), 1).is_ok());
+
+        // the text has 20 characters, so a sparseness factor of 3 keeps 7 suffixes
+        let sparse_sa: Vec<i64> = sa.iter().copied().filter(|suffix| suffix % 3 == 0).collect();
+        assert!(create_searcher(sparse_sa, 3).is_ok());
+
+        // a suffix array of another (shorter) database, or with another sparseness factor
+        assert_eq!(
+            create_searcher(sa[..12].to_vec(), 1).err(),
+            Some(SearcherError::SuffixArrayLengthMismatch { length: 12, expected_length: 20 })
+        );
+        assert_eq!(
+            create_searcher(sa.clone(), 2).err(),
+            Some(SearcherError::SuffixArrayLengthMismatch { length: 20, expected_length: 10 })
+        );
+    }
+
+    #[test]
+    fn test_try_new_rejects_misconfiguration() {
+        let create_searcher = |sa: Vec<i64>, sparseness_factor: u8, proteins: Proteins| {
+            Searcher::try_new(
+                sa,
+                sparseness_factor,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+                FunctionAggregator {}
+            )
+            .err()
+        };
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+
+        assert_eq!(create_searcher(sa.clone(), 0, get_example_proteins()), Some(SearcherError::ZeroSparsenessFactor));
+        assert_eq!(create_searcher(vec![], 1, get_example_proteins()), Some(SearcherError::EmptySuffixArray));
+
+        let mut proteins = get_example_proteins();
+        proteins.proteins.clear();
+        assert_eq!(create_searcher(sa.clone(), 1, proteins), Some(SearcherError::NoProteins));
+
+        let mut proteins = get_example_proteins();
+        proteins.input_string = proteins.input_string[..19].to_vec().into();
+        assert_eq!(create_searcher(sa[1..].to_vec(), 1, proteins), Some(SearcherError::MissingTerminationCharacter));
+    }
+
+    #[test]
+    fn test_search_proteins_in_clade() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[0].taxon_id = 7;
+        proteins.proteins[1].taxon_id = 11;
+        proteins.proteins[2].taxon_id = 2;
+        proteins.proteins[3].taxon_id = 14;
+        let searcher = get_searcher(proteins, 1);
+
+        // AC occurs in the second protein (taxon 11, part of the genus 10) and the third protein (Bacteria, not part of the genus 10)
+        let matches = searcher.search_proteins_in_clade(b"AC", 10, false);
+        assert_eq!(matches.len(), 1);
+        assert!(std::ptr::eq(matches[0], &searcher.proteins[1]));
+
+        // the genus 6 contains the first two proteins, the root contains all of them
+        let matches = searcher.search_proteins_in_clade(b"A", 6, false);
+        assert!(matches.iter().all(|&protein| std::ptr::eq(protein, &searcher.proteins[0]) || std::ptr::eq(protein, &searcher.proteins[1])));
+        assert_eq!(matches.len(), 4);
+        assert_eq!(searcher.search_proteins_in_clade(b"A", 1, false).len(), searcher.search_proteins_for_peptide(b"A", false).len());
+        assert!(searcher.search_proteins_in_clade(b"AC", 16, false).is_empty());
+    }
+
+    #[test]
+    fn test_residue_coverage() {
+        let searcher = get_example_searcher(1);
+
+        // LAC and ACV overlap in BLACVAA, AC also matches the third protein, which does not count
+        let peptides: [&[u8]; 4] = [b"LAC", b"ACV", b"AC", b"W"];
+        assert_eq!(searcher.residue_coverage(1, &peptides, false), vec![0, 1, 3, 3, 1, 0, 0]);
+        assert_eq!(searcher.residue_coverage(2, &peptides, false), vec![1, 1]);
+        assert_eq!(searcher.residue_coverage(3, &peptides, false), vec![0; 5]);
+        assert!(searcher.residue_coverage(4, &peptides, false).is_empty());
+
+        // peptides shorter than the sparseness factor are skipped
+        let sparse_searcher = get_example_searcher(3);
+        let peptides: [&[u8]; 4] = [b"LAC", b"ACV", b"AC", b"A"];
+        assert_eq!(sparse_searcher.residue_coverage(1, &peptides, false), vec![0, 1, 2, 2, 1, 0, 0]);
+        assert_eq!(sparse_searcher.residue_coverage(2, &peptides, false), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_retrieve_lca_with_support() {
+        let mut proteins = get_example_proteins();
+        proteins.proteins[0].taxon_id = 17;
+        proteins.proteins[1].taxon_id = 19;
+        proteins.proteins[2].taxon_id = 20;
+        proteins.proteins[3].taxon_id = 14;
+        let searcher = get_searcher(proteins, 1);
+
+        let matches = searcher.search_proteins_for_peptide(b"A", false);
+        let (lca, support) = searcher.retrieve_lca_with_support(&matches).unwrap();
+
+        assert_eq!(Some(lca), searcher.retrieve_lca(&matches));
+        assert_eq!(support, vec![(17, 1), (19, 3), (20, 1)]);
+        assert_eq!(support.iter().map(|(_, count)| count).sum::<usize>(), matches.len());
+
+        assert!(searcher.retrieve_lca_with_support(&[]).is_none());
+    }
+
+    #[test]
+    fn test_shared_kmers() {
+        let searcher = get_example_searcher(1);
+
+        // BLACVAA and AC share the motif AC
+        assert_eq!(searcher.shared_kmers(1, 2, 2, false), vec!["AC"]);
+        assert_eq!(searcher.shared_kmers(2, 1, 2, false), vec!["AC"]);
+        assert_eq!(searcher.shared_kmers(2, 1, 1, false), vec!["A", "C"]);
+        // every k-mer is only reported once, in the order of its first occurrence
+        assert_eq!(searcher.shared_kmers(1, 3, 1, false), vec!["L", "C"]);
+        assert_eq!(searcher.shared_kmers(1, 1, 2, false), vec!["BL", "LA", "AC", "CV", "VA", "AA"]);
+
+        // the I of AI only matches the L of KCRLZ when I and L are equalized
+        assert!(searcher.shared_kmers(0, 3, 1, false).is_empty());
+        assert_eq!(searcher.shared_kmers(0, 3, 1, true), vec!["I"]);
+
+        assert!(searcher.shared_kmers(1, 2, 3, false).is_empty());
+        assert!(searcher.shared_kmers(1, 4, 2, false).is_empty());
+
+        // k-mers shorter than the sparseness factor are never shared
+        let sparse_searcher = get_example_searcher(3);
+        assert!(sparse_searcher.shared_kmers(1, 2, 1, false).is_empty());
+        assert!(sparse_searcher.shared_kmers(1, 2, 2, false).is_empty());
+        assert_eq!(sparse_searcher.shared_kmers(1, 1, 3, false), vec!["BLA", "LAC", "ACV", "CVA", "VAA"]);
+    }
+
+    #[test]
+    fn test_search_motif() {
+        let searcher = get_example_searcher(1);
+        let matches = |motif: &[Vec<u8>], equalize_i_and_l: bool| {
+            let result = searcher.search_motif(motif, equalize_i_and_l);
+            assert!(!result.truncated);
+            result.matches
+        };
+
+        // the first position allows A or K, only ACV of BLACVAA and KCR of KCRLZ exist
+        let motif = [vec![b'A', b'K'], vec![b'C'], vec![b'V']];
+        assert_eq!(matches(&motif, false), vec![(1, 2)]);
+        let motif = [vec![b'A', b'K'], vec![b'C'], vec![b'V', b'R']];
+        assert_eq!(matches(&motif, false), vec![(1, 2), (3, 0)]);
+
+        // the I only matches the L of KCRLZ when I and L are equalized
+        let motif = [vec![b'C'], vec![b'R'], vec![b'I', b'Z']];
+        assert!(matches(&motif, false).is_empty());
+        assert_eq!(matches(&motif, true), vec![(3, 1)]);
+
+        assert!(matches(&[], false).is_empty());
+        assert!(matches(&[vec![b'A'], vec![]], false).is_empty());
+
+        // the motif gives the same matches in a sparse suffix array
+        let sparse_searcher = get_example_searcher(3);
+        let motif = [vec![b'A', b'K'], vec![b'C'], vec![b'V', b'R']];
+        assert_eq!(
+            sparse_searcher.search_motif(&motif, false),
+            MotifSearchResult { matches: vec![(1, 2), (3, 0)], truncated: false }
+        );
+
+        // the prefixes shorter than the sparseness factor are never dropped, so a motif that allows every residue
+        // at its first 3 positions has more branches than are searched
+        let every_residue: Vec<u8> = (b'A'..=b'Z').collect();
+        let motif = vec![every_residue; 3];
+        assert!(motif[0].len().pow(3) > MAX_MOTIF_EXPANSIONS);
+        assert!(sparse_searcher.search_motif(&motif, false).truncated);
+    }
+}
@@ -1,5 +1,10 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
 use clap::ValueEnum;
 use sa_mappings::proteins::{SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+use suffixarray_builder::binary::write_atomically;
 use crate::Nullable;
 
 /// Enum used to define the commandline arguments and choose which index style is used
@@ -21,6 +26,25 @@ pub trait SuffixToProteinIndex: Send + Sync {
     ///
     /// Returns the index of the protein in the proteins list of which the suffix is a part
     fn suffix_to_protein(&self, suffix: i64) -> u32;
+
+    /// Returns the length of the text over which the mapping was built
+    fn text_length(&self) -> usize;
+
+    /// Writes the mapping in binary form, so it can be read again with `read_suffix_to_protein_mapping`
+    /// The binary form starts with a byte with the style of the mapping, followed by the number of entries as a u64
+    /// and the entries themselves, all in little endian
+    ///
+    /// # Arguments
+    /// * `writer` - The writer the mapping is written to
+    ///
+    /// # Returns
+    ///
+    /// Returns () if writing the mapping succeeded
+    ///
+    /// # Errors
+    ///
+    /// Returns an io::Error if writing the mapping failed
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), io::Error>;
 }
 
 /// Mapping that uses O(n) memory with n the size of the input text, but retrieval of the protein is in O(1)
@@ -40,6 +64,14 @@ impl SuffixToProteinIndex for DenseSuffixToProtein {
     fn suffix_to_protein(&self, suffix: i64) -> u32 {
         self.mapping[suffix as usize]
     }
+
+    fn text_length(&self) -> usize {
+        self.mapping.len()
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        write_entries(writer, SuffixToProteinMappingStyle::Dense, self.mapping.iter().map(|entry| entry.to_le_bytes()))
+    }
 }
 
 impl SuffixToProteinIndex for SparseSuffixToProtein {
@@ -52,6 +84,172 @@ impl SuffixToProteinIndex for SparseSuffixToProtein {
         }
         protein_index as u32
     }
+
+    fn text_length(&self) -> usize {
+        // the last entry is the start of the (non-existing) protein after the termination character
+        self.mapping.last().map_or(0, |&end| end as usize)
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        write_entries(writer, SuffixToProteinMappingStyle::Sparse, self.mapping.iter().map(|entry| entry.to_le_bytes()))
+    }
+}
+
+impl SuffixToProteinMappingStyle {
+    /// Returns the byte that identifies this style in the binary form of a mapping
+    fn tag(&self) -> u8 {
+        match self {
+            SuffixToProteinMappingStyle::Dense => 0,
+            SuffixToProteinMappingStyle::Sparse => 1,
+        }
+    }
+}
+
+/// Writes the style, the number of entries and the entries of a mapping
+///
+/// # Arguments
+/// * `writer` - The writer the mapping is written to
+/// * `style` - The style of the mapping
+/// * `entries` - The little endian bytes of every entry of the mapping
+///
+/// # Returns
+///
+/// Returns () if writing the mapping succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing the mapping failed
+fn write_entries<const N: usize>(
+    writer: &mut dyn Write,
+    style: SuffixToProteinMappingStyle,
+    entries: impl ExactSizeIterator<Item = [u8; N]>,
+) -> Result<(), io::Error> {
+    writer.write_all(&[style.tag()])?;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for entry in entries {
+        writer.write_all(&entry)?;
+    }
+    Ok(())
+}
+
+/// Reads the style of a mapping in binary form
+///
+/// # Arguments
+/// * `reader` - The reader positioned at the start of the mapping
+///
+/// # Returns
+///
+/// Returns the style of the mapping
+///
+/// # Errors
+///
+/// Returns any error from reading, or an error if the byte does not identify a style
+fn read_style(reader: &mut impl Read) -> Result<SuffixToProteinMappingStyle, Box<dyn Error>> {
+    let mut tag = [0; 1];
+    reader.read_exact(&mut tag)?;
+    [SuffixToProteinMappingStyle::Dense, SuffixToProteinMappingStyle::Sparse]
+        .into_iter()
+        .find(|style| style.tag() == tag[0])
+        .ok_or_else(|| format!("Unknown suffix-to-protein mapping style {}", tag[0]).into())
+}
+
+/// Reads the number of entries and the entries of a mapping in binary form
+///
+/// # Arguments
+/// * `reader` - The reader positioned right after the style of the mapping
+/// * `decode` - Function that converts the little endian bytes of an entry to the entry
+///
+/// # Returns
+///
+/// Returns the entries of the mapping
+///
+/// # Errors
+///
+/// Returns any error from reading, or an error if the data ends before all the entries are read
+fn read_entries<const N: usize, V>(reader: &mut impl Read, decode: fn([u8; N]) -> V) -> Result<Vec<V>, Box<dyn Error>> {
+    let mut length = [0; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+
+    // the entries are pushed one by one, so a corrupt length can not cause a huge allocation
+    let mut entries = vec![];
+    let mut entry = [0; N];
+    for index in 0..length {
+        reader
+            .read_exact(&mut entry)
+            .map_err(|err| format!("The mapping ends after {} of its {} entries: {}", index, length, err))?;
+        entries.push(decode(entry));
+    }
+    entries.shrink_to_fit();
+    Ok(entries)
+}
+
+/// Reads a mapping of any style that was written with `write_to`
+///
+/// # Arguments
+/// * `reader` - The reader positioned at the start of the mapping
+///
+/// # Returns
+///
+/// Returns the mapping, in the style it was written in
+///
+/// # Errors
+///
+/// Returns any error from reading, or an error if the data is not a valid mapping
+pub fn read_suffix_to_protein_mapping(reader: &mut impl Read) -> Result<Box<dyn SuffixToProteinIndex>, Box<dyn Error>> {
+    Ok(match read_style(reader)? {
+        SuffixToProteinMappingStyle::Dense => Box::new(DenseSuffixToProtein { mapping: read_entries(reader, u32::from_le_bytes)? }),
+        SuffixToProteinMappingStyle::Sparse => Box::new(SparseSuffixToProtein { mapping: read_entries(reader, i64::from_le_bytes)? }),
+    })
+}
+
+/// Writes a mapping to the file with the given `filename`
+/// The mapping is first written to `<filename>.tmp`, so an existing file is only replaced once the new mapping is completely written
+///
+/// # Arguments
+/// * `mapping` - The mapping that is stored
+/// * `filename` - The name of the file we want to write the mapping to
+///
+/// # Returns
+///
+/// Returns () if writing away the mapping succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the mapping failed
+pub fn write_suffix_to_protein_mapping(mapping: &dyn SuffixToProteinIndex, filename: &str) -> Result<(), io::Error> {
+    write_atomically(filename, |file| {
+        let mut writer = BufWriter::new(file);
+        mapping.write_to(&mut writer)?;
+        writer.flush()
+    })
+}
+
+/// Loads a mapping from the file with the given `filename`, instead of building it over the text again
+///
+/// # Arguments
+/// * `filename` - The name of the file where the mapping is stored
+/// * `text` - The text the mapping is used for
+///
+/// # Returns
+///
+/// Returns the mapping, in the style it was written in
+///
+/// # Errors
+///
+/// Returns any error from opening or reading the file, or an error if the mapping was built over a text of a different length
+pub fn load_suffix_to_protein_mapping(filename: &str, text: &[u8]) -> Result<Box<dyn SuffixToProteinIndex>, Box<dyn Error>> {
+    let mapping = read_suffix_to_protein_mapping(&mut BufReader::new(File::open(filename)?))?;
+    if mapping.text_length() != text.len() {
+        return Err(format!(
+            "The suffix-to-protein mapping in {} was built over a text of length {}, but the proteins have length {}",
+            filename,
+            mapping.text_length(),
+            text.len()
+        )
+        .into());
+    }
+    Ok(mapping)
 }
 
 impl DenseSuffixToProtein {
@@ -79,6 +277,25 @@ impl DenseSuffixToProtein {
         suffix_index_to_protein.shrink_to_fit();
         DenseSuffixToProtein { mapping: suffix_index_to_protein }
     }
+
+    /// Reads a dense mapping that was written with `write_to`
+    ///
+    /// # Arguments
+    /// * `reader` - The reader positioned at the start of the mapping
+    ///
+    /// # Returns
+    ///
+    /// Returns the DenseSuffixToProtein that was written
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading, or an error if the data is not a valid dense mapping
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Box<dyn Error>> {
+        match read_style(reader)? {
+            SuffixToProteinMappingStyle::Dense => Ok(DenseSuffixToProtein { mapping: read_entries(reader, u32::from_le_bytes)? }),
+            style => Err(format!("Expected a dense suffix-to-protein mapping, but found a {:?} mapping", style).into()),
+        }
+    }
 }
 
 impl SparseSuffixToProtein {
@@ -102,6 +319,25 @@ impl SparseSuffixToProtein {
         SparseSuffixToProtein { mapping: suffix_index_to_protein }
     }
 
+    /// Reads a sparse mapping that was written with `write_to`
+    ///
+    /// # Arguments
+    /// * `reader` - The reader positioned at the start of the mapping
+    ///
+    /// # Returns
+    ///
+    /// Returns the SparseSuffixToProtein that was written
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading, or an error if the data is not a valid sparse mapping
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Box<dyn Error>> {
+        match read_style(reader)? {
+            SuffixToProteinMappingStyle::Sparse => Ok(SparseSuffixToProtein { mapping: read_entries(reader, i64::from_le_bytes)? }),
+            style => Err(format!("Expected a sparse suffix-to-protein mapping, but found a {:?} mapping", style).into()),
+        }
+    }
+
 }
 
 
@@ -109,7 +345,7 @@ impl SparseSuffixToProtein {
 mod tests {
     use sa_mappings::proteins::{SEPARATION_CHARACTER, TERMINATION_CHARACTER};
     use crate::Nullable;
-    use crate::suffix_to_protein_index::{DenseSuffixToProtein, SparseSuffixToProtein, SuffixToProteinIndex};
+    use crate::suffix_to_protein_index::{DenseSuffixToProtein, read_suffix_to_protein_mapping, SparseSuffixToProtein, SuffixToProteinIndex};
 
     fn build_text() -> Vec<u8> {
         let mut text = ["ACG", "CG", "AAA"].join(&format!("{}", SEPARATION_CHARACTER as char));
@@ -156,4 +392,36 @@ mod tests {
         // suffix that starts with TERMINATION_CHARACTER
         assert_eq!(index.suffix_to_protein(10), u32::NULL);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_dense_write_read() {
+        let u8_text = &build_text();
+        let index = DenseSuffixToProtein::new(u8_text);
+        let mut data = vec![];
+        index.write_to(&mut data).unwrap();
+
+        assert_eq!(DenseSuffixToProtein::read_from(&mut data.as_slice()).unwrap(), index);
+        assert!(SparseSuffixToProtein::read_from(&mut data.as_slice()).is_err());
+        assert!(DenseSuffixToProtein::read_from(&mut &data[..data.len() - 1]).is_err());
+
+        let read_index = read_suffix_to_protein_mapping(&mut data.as_slice()).unwrap();
+        assert_eq!(read_index.text_length(), u8_text.len());
+        assert_eq!(read_index.suffix_to_protein(5), 1);
+    }
+
+    #[test]
+    fn test_sparse_write_read() {
+        let u8_text = &build_text();
+        let index = SparseSuffixToProtein::new(u8_text);
+        let mut data = vec![];
+        index.write_to(&mut data).unwrap();
+
+        assert_eq!(SparseSuffixToProtein::read_from(&mut data.as_slice()).unwrap(), index);
+        assert!(DenseSuffixToProtein::read_from(&mut data.as_slice()).is_err());
+        assert!(SparseSuffixToProtein::read_from(&mut &data[..data.len() - 1]).is_err());
+
+        let read_index = read_suffix_to_protein_mapping(&mut data.as_slice()).unwrap();
+        assert_eq!(read_index.text_length(), u8_text.len());
+        assert_eq!(read_index.suffix_to_protein(3), u32::NULL);
+    }
+}
@@ -0,0 +1,211 @@
+use sa_mappings::functionality::AnnotationCache;
+use sa_mappings::proteins::SEPARATION_CHARACTER;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::peptide_search::{analyse_peptide, CutoffPolicy, SearchOptions, SearchResultWithAnalysis, ANNOTATION_CACHE_CAPACITY};
+use crate::sa_searcher::Searcher;
+
+/// The standard genetic code, indexed by `16 * first + 4 * second + third` with the nucleotides numbered T=0, C=1, A=2, G=3
+/// Stop codons are translated to the `SEPARATION_CHARACTER`
+const CODON_TABLE: &[u8; 64] = b"FFLLSSSSYY--CC-WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// The amino acid used for codons that contain an unknown nucleotide, such as N
+const UNKNOWN_AMINO_ACID: u8 = b'X';
+
+/// Struct representing the search result of a peptide translated from one of the six reading frames of a DNA sequence
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DnaSearchResult {
+    /// The reading frame the peptide was translated from, 0 to 2 are the forward frames and 3 to 5 the reverse complement frames
+    pub frame: usize,
+    /// The search result of the translated peptide
+    pub result: SearchResultWithAnalysis,
+}
+
+/// Gets the number of a nucleotide in the codon table
+///
+/// # Arguments
+/// * `nucleotide` - The nucleotide, either in upper or lower case. U is treated as T
+///
+/// # Returns
+///
+/// Returns the number of the nucleotide, or None if it is not one of A, C, G, T or U
+fn nucleotide_index(nucleotide: u8) -> Option<usize> {
+    match nucleotide.to_ascii_uppercase() {
+        b'T' | b'U' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+/// Translates a codon to an amino acid using the standard genetic code
+///
+/// # Arguments
+/// * `codon` - The 3 nucleotides of the codon
+///
+/// # Returns
+///
+/// Returns the amino acid, the `SEPARATION_CHARACTER` for a stop codon or X if the codon contains an unknown nucleotide
+fn translate_codon(codon: &[u8]) -> u8 {
+    let index = codon
+        .iter()
+        .try_fold(0, |index, &nucleotide| Some(4 * index + nucleotide_index(nucleotide)?));
+    index.map_or(UNKNOWN_AMINO_ACID, |index| CODON_TABLE[index])
+}
+
+/// Calculates the reverse complement of a DNA sequence
+///
+/// # Arguments
+/// * `dna` - The DNA sequence
+///
+/// # Returns
+///
+/// Returns the reverse complement, unknown nucleotides are complemented to N
+fn reverse_complement(dna: &[u8]) -> Vec<u8> {
+    dna.iter()
+        .rev()
+        .map(|nucleotide| match nucleotide.to_ascii_uppercase() {
+            b'A' => b'T',
+            b'T' | b'U' => b'A',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'N',
+        })
+        .collect()
+}
+
+/// Translates the six reading frames of a DNA sequence using the standard genetic code
+///
+/// # Arguments
+/// * `dna` - The DNA sequence
+///
+/// # Returns
+///
+/// Returns the translation of the 3 forward frames followed by the 3 frames of the reverse complement.
+/// Stop codons are translated to the `SEPARATION_CHARACTER` and incomplete codons at the end of a frame are dropped
+pub fn six_frame_translate(dna: &[u8]) -> [Vec<u8>; 6] {
+    let complement = reverse_complement(dna);
+    let translate_frame = |strand: &[u8], offset: usize| -> Vec<u8> {
+        strand.get(offset..).unwrap_or_default().chunks_exact(3).map(translate_codon).collect()
+    };
+
+    [
+        translate_frame(dna, 0),
+        translate_frame(dna, 1),
+        translate_frame(dna, 2),
+        translate_frame(&complement, 0),
+        translate_frame(&complement, 1),
+        translate_frame(&complement, 2),
+    ]
+}
+
+/// Translates the six reading frames of a DNA sequence and searches the peptides between the stop codons in the index
+///
+/// # Arguments
+/// * `searcher` - The Searcher which contains the protein database
+/// * `dna` - The DNA sequence of which the translations are searched
+/// * `cutoff` - The policy that determines the maximum amount of matches we want to process from the index for a peptide
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `functional_analysis` - Boolean indicating if we want to perform the functional analysis, otherwise `fa` is None
+///
+/// # Returns
+///
+/// Returns the search results of the translated peptides that have matches, tagged with the frame they were translated from
+pub fn search_dna(
+    searcher: &Searcher,
+    dna: &[u8],
+    cutoff: CutoffPolicy,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+    functional_analysis: bool,
+) -> Vec<DnaSearchResult> {
+    let annotation_cache = AnnotationCache::with_capacity(ANNOTATION_CACHE_CAPACITY);
+    // the translation is always uppercase, so it does not need to be uppercased again
+    let options = SearchOptions { equalize_i_and_l, clean_taxa, preserve_case: true, functional_analysis, ..Default::default() };
+
+    six_frame_translate(dna)
+        .iter()
+        .enumerate()
+        .flat_map(|(frame, translation)| {
+            translation
+                .split(|&amino_acid| amino_acid == SEPARATION_CHARACTER)
+                .filter(|peptide| !peptide.is_empty())
+                .map(move |peptide| (frame, peptide))
+        })
+        .filter_map(|(frame, peptide)| {
+            // the translation only contains ASCII characters
+            let peptide = std::str::from_utf8(peptide).ok()?;
+            analyse_peptide(searcher, peptide, cutoff, &options, Some(&annotation_cache))
+            .map(|result| DnaSearchResult { frame, result })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
+
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+
+    use super::*;
+
+    #[test]
+    fn test_six_frame_translate() {
+        let frames = six_frame_translate(b"ATGGCCTAAGG");
+
+        assert_eq!(frames[0], b"MA-");
+        assert_eq!(frames[1], b"WPK");
+        assert_eq!(frames[2], b"GLR");
+        // the reverse complement is CCTTAGGCCAT
+        assert_eq!(frames[3], b"P-A");
+        assert_eq!(frames[4], b"LRP");
+        assert_eq!(frames[5], b"LGH");
+
+        // lower case, U and unknown nucleotides
+        assert_eq!(six_frame_translate(b"auggcnTAA")[0], b"MX-");
+        assert!(six_frame_translate(b"AT").iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn test_search_dna() {
+        let text = "MLPGLALLLLAAWTARALEV-PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG$".to_string().into_bytes();
+        let sa = build_sa(&mut text.clone(), &SAConstructionAlgorithm::LibSais, 1).unwrap();
+        let proteins = Proteins {
+            input_string: text.into(),
+            proteins: [1, 2]
+                .iter()
+                .enumerate()
+                .map(|(i, &taxon_id)| Protein {
+                    uniprot_id: format!("P{}", i + 1),
+                    taxon_id,
+                    functional_annotations: vec![],
+                    extra_accessions: vec![],
+                })
+                .collect(),
+        };
+        let searcher = Searcher::new(
+            sa,
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // the second frame translates to MFCGR, followed by a stop codon and WWW
+        let dna = b"AATGTTTTGTGGTCGTTAATGGTGGTGG";
+        let results = search_dna(&searcher, dna, CutoffPolicy::Constant(10000), false, false, false);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].frame, 1);
+        let result = serde_json::to_value(&results[0].result).unwrap();
+        assert_eq!(result["sequence"], "MFCGR");
+        assert_eq!(result["lca"], 2);
+    }
+}
@@ -2,22 +2,21 @@ use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::path::Path;
-use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::time::Instant;
 
 use crate::sa_searcher::Searcher;
 
-/// Gets the current time in ms
+/// Gets the time in ms that elapsed since `start`
+/// A monotonic clock is used, so unlike the wall-clock time this can never go backwards
+///
+/// # Arguments
+/// * `start` - The moment from which the elapsed time is measured
 ///
 /// # Returns
 ///
-/// Returns the current time in ms
-/// 
-/// # Errors
-/// 
-/// Returns a SystemTimeError if getting the current time somehow fails
-#[allow(unused)]
-pub fn get_time_ms() -> Result<f64, SystemTimeError> {
-    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as f64 * 1e-6)
+/// Returns the elapsed time in ms
+pub fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1e3
 }
 
 /// Times how long the function `f`, that has the searcher as only argument executed
@@ -25,19 +24,14 @@ pub fn get_time_ms() -> Result<f64, SystemTimeError> {
 /// # Returns
 ///
 /// Returns the execution time of `f`in ms
-///
-/// # Errors
-///
-/// Returns a SystemTimeError if getting the start or end time failed
 #[allow(unused)]
 pub fn time_execution(
     searcher: &mut Searcher,
     f: &dyn Fn(&mut Searcher) -> bool,
-) -> Result<(bool, f64), SystemTimeError> {
-    let start_ms = get_time_ms()?;
+) -> (bool, f64) {
+    let start = Instant::now();
     let found = f(searcher);
-    let end_ms = get_time_ms()?;
-    Ok((found, end_ms - start_ms))
+    (found, elapsed_ms(start))
 }
 
 /// Opens `filename` and creates an iterator over it per line
@@ -53,3 +47,20 @@ pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::util::elapsed_ms;
+
+    #[test]
+    fn test_elapsed_ms() {
+        let start = Instant::now();
+        let first = elapsed_ms(start);
+        let second = elapsed_ms(start);
+
+        assert!(first >= 0.0);
+        assert!(second >= first);
+    }
+}
@@ -1,10 +1,34 @@
 use std::cmp::min;
 use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 
 const ONE_GIB: usize = 2usize.pow(30);
 
+/// The size in bytes of the header that precedes the entries of the suffix array in the binary file
+const HEADER_SIZE: u64 = 1;
+
+/// The bit of the header byte that is set when the entries of the suffix array are gzip compressed
+/// The other bits of the header byte contain the sparseness factor, which therefore has to be smaller than 128
+const COMPRESSED_FLAG: u8 = 0x80;
+
+/// Struct representing the header of a binary suffix array file, together with the information derived from the file size
+#[derive(Debug, PartialEq)]
+pub struct IndexHeader {
+    /// The sparseness factor of the suffix array
+    pub sparseness_factor: u8,
+    /// The number of entries in the suffix array
+    pub suffix_array_length: u64,
+    /// True if the entries of the suffix array are gzip compressed
+    pub compressed: bool,
+    /// The size of the file in bytes
+    pub file_size: u64,
+}
+
 /// Trait implemented by structs that are binary serializable
 /// In our case this is will be a [i64] since the suffix array is a Vec<i64>
 pub trait Serializable {
@@ -35,23 +59,101 @@ impl Serializable for [i64] {
 /// # Returns
 ///
 /// Returns the suffix array, a Vec<i64>
-fn deserialize_sa(data: &[u8]) -> Vec<i64> {
-    let mut res = vec![];
-    if data.len() % 8 != 0 {
-        panic!("Serialized data is not a multiple of 8 bytes long!")
+///
+/// # Errors
+///
+/// Returns an error if the length of `data` is not a multiple of 8 bytes
+fn deserialize_sa(data: &[u8]) -> Result<Vec<i64>, Box<dyn Error>> {
+    if !data.len().is_multiple_of(8) {
+        return Err(format!("Serialized data is not a multiple of 8 bytes long ({} bytes)", data.len()).into());
     }
-    for start in (0..data.len()).step_by(8) {
-        res.push(i64::from_le_bytes(data[start..start + 8].try_into().unwrap()));
+    Ok(data
+        .chunks_exact(8)
+        .map(|bytes| i64::from_le_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
+
+/// Reads a serialized suffix array in chunks of at most `chunk_size` bytes
+/// A chunk does not have to end on an entry boundary, the trailing bytes of a chunk are kept for the next chunk
+///
+/// # Arguments
+/// * `reader` - The reader positioned at the start of the serialized suffix array
+/// * `chunk_size` - The maximum number of bytes that are read at once
+///
+/// # Returns
+///
+/// Returns the suffix array, a Vec<i64>
+///
+/// # Errors
+///
+/// Returns any error from reading, or an error if the data ends in the middle of an entry
+fn read_suffix_array(mut reader: impl Read, chunk_size: usize) -> Result<Vec<i64>, Box<dyn Error>> {
+    let mut sa = vec![];
+    let mut buffer = vec![];
+    loop {
+        // use take in combination with read_to_end to ensure that the buffer will be completely filled (except when the file is smaller than the buffer)
+        let count = reader.by_ref().take(chunk_size as u64).read_to_end(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+
+        // only deserialize the complete entries, the remaining bytes are completed by the next chunk
+        let complete_length = buffer.len() - buffer.len() % 8;
+        sa.extend_from_slice(&deserialize_sa(&buffer[..complete_length])?);
+        buffer.drain(..complete_length);
+    }
+
+    if !buffer.is_empty() {
+        return Err(format!("The suffix array ends with an incomplete entry of {} bytes", buffer.len()).into());
     }
-    res
+
+    Ok(sa)
+}
+
+/// Writes a file by first writing to `<filename>.tmp` and only renaming it to `filename` after everything is written and synced
+/// An interrupted or failed write therefore never replaces an existing file with a truncated one
+///
+/// # Arguments
+/// * `filename` - The name of the file we want to write
+/// * `write` - Function that writes the content to the given (temporary) file
+///
+/// # Returns
+///
+/// Returns () if writing and renaming the file succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing, syncing or renaming the file failed, the temporary file is removed in that case
+pub fn write_atomically(filename: &str, write: impl FnOnce(&mut File) -> Result<(), std::io::Error>) -> Result<(), std::io::Error> {
+    let tmp_filename = format!("{}.tmp", filename);
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true) // if a temporary file of a previous interrupted write exists, empty the file
+        .open(&tmp_filename)?;
+
+    let result = write(&mut f)
+        .and_then(|_| f.flush())
+        .and_then(|_| f.sync_all())
+        .and_then(|_| fs::rename(&tmp_filename, filename));
+
+    if result.is_err() {
+        // ignore the error of the cleanup, the original error is more relevant
+        let _ = fs::remove_file(&tmp_filename);
+    }
+
+    result
 }
 
 /// Writes the given suffix array with the `sparseness_factor` factor to the given file
+/// The suffix array is first written to `<filename>.tmp`, so an existing file is only replaced once the new suffix array is completely written
+/// When compressed, the entries are gzip compressed, which trades CPU time while loading for a smaller file on disk
 ///
 /// # Arguments
 /// * `sparseness_factor` - The sparseness factor of the suffix array
 /// * `suffix_array` - The suffix array
 /// * `filename` - The name of the file we want to write the suffix array to
+/// * `compress` - If true, the entries of the suffix array are gzip compressed and the compression flag is set in the header
 ///
 /// # Returns
 ///
@@ -59,27 +161,56 @@ fn deserialize_sa(data: &[u8]) -> Vec<i64> {
 ///
 /// # Errors
 ///
-/// Returns an io::Error if writing away the suffix array failed
-pub fn write_suffix_array(sparseness_factor: u8, suffix_array: &[i64], filename: &str) -> Result<(), std::io::Error> {
-    // create the file
-    let mut f = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true) // if the file already exists, empty the file
-        .open(filename)?;
-    f.write_all(&[sparseness_factor])?; // write the sample rate as the first byte
+/// Returns an io::Error if writing away the suffix array failed, or if the sparseness factor does not fit in the header (128 or more)
+pub fn write_suffix_array(sparseness_factor: u8, suffix_array: &[i64], filename: &str, compress: bool) -> Result<(), std::io::Error> {
+    if sparseness_factor & COMPRESSED_FLAG != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The sparseness factor should be smaller than 128, got {}", sparseness_factor)
+        ));
+    }
+
+    write_atomically(filename, |f| {
+        if compress {
+            f.write_all(&[sparseness_factor | COMPRESSED_FLAG])?;
+            let mut encoder = GzEncoder::new(f, Compression::default());
+            write_entries(&mut encoder, suffix_array)?;
+            encoder.finish()?;
+        } else {
+            f.write_all(&[sparseness_factor])?; // write the sample rate as the first byte
+            write_entries(f, suffix_array)?;
+        }
+
+        Ok(())
+    })
+}
 
+/// Writes the serialized entries of the suffix array to the given writer
+///
+/// # Arguments
+/// * `writer` - The writer to which the entries are written
+/// * `suffix_array` - The suffix array
+///
+/// # Returns
+///
+/// Returns () if writing the entries succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing the entries failed
+fn write_entries(writer: &mut impl Write, suffix_array: &[i64]) -> Result<(), std::io::Error> {
     // write 1 GiB at a time, to minimize extra used memory since we need to translate i64 to [u8; 8]
     let sa_len = suffix_array.len();
     for start_index in (0..sa_len).step_by(ONE_GIB/8) {
         let end_index = min(start_index + ONE_GIB/8, sa_len);
-        f.write_all(&suffix_array[start_index..end_index].serialize())?;
+        writer.write_all(&suffix_array[start_index..end_index].serialize())?;
     }
 
     Ok(())
 }
 
 /// Loads the suffix array from the file with the given `filename`
+/// A compressed suffix array is detected from the header and decompressed transparently
 ///
 /// # Arguments
 /// * `filename` - The filename of the file where the suffix array is stored
@@ -90,37 +221,88 @@ pub fn write_suffix_array(sparseness_factor: u8, suffix_array: &[i64], filename:
 ///
 /// # Errors
 ///
-/// Returns any error from opening the file or reading the file
+/// Returns any error from opening the file or reading the file, or an error if the file ends in the middle of an entry
 pub fn load_suffix_array(filename: &str) -> Result<(u8, Vec<i64>), Box<dyn Error>> {
     let mut file = &File::open(filename)?;
-    let mut sparseness_factor_buffer = [0_u8; 1];
-    file.read_exact(&mut sparseness_factor_buffer).map_err(|_| "Could not read the sample rate from the binary file")?;
-    let sparseness_factor = sparseness_factor_buffer[0];
+    let (sparseness_factor, compressed) = read_header_byte(&mut file)?;
 
-    let mut sa = vec![];
-    loop {
-        let mut buffer = vec![];
-        // use take in combination with read_to_end to ensure that the buffer will be completely filled (except when the file is smaller than the buffer)
-        let count = file.take(ONE_GIB as u64).read_to_end(&mut buffer)?;
-        if count == 0 {
-            break;
-        }
-        sa.extend_from_slice(&deserialize_sa(&buffer[..count]));
-    }
+    let sa = if compressed {
+        read_suffix_array(GzDecoder::new(BufReader::new(file)), ONE_GIB)?
+    } else {
+        read_suffix_array(file, ONE_GIB)?
+    };
 
     Ok((sparseness_factor, sa))
 }
 
+/// Reads the header of the file with the given `filename`, without loading the suffix array itself
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the suffix array is stored
+///
+/// # Returns
+///
+/// Returns the header of the file, the length of the suffix array is derived from the size of the file
+/// For a compressed file, the entries are decompressed (without keeping them in memory) to count them
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading the header, or an error if the file ends in the middle of an entry
+pub fn read_header(filename: &str) -> Result<IndexHeader, Box<dyn Error>> {
+    let mut file = File::open(filename)?;
+    let file_size = file.metadata()?.len();
+    let (sparseness_factor, compressed) = read_header_byte(&mut file)?;
+
+    let entry_bytes = if compressed {
+        io::copy(&mut GzDecoder::new(BufReader::new(file)), &mut io::sink())?
+    } else {
+        file_size - HEADER_SIZE
+    };
+    if !entry_bytes.is_multiple_of(8) {
+        return Err(format!("The binary file ends in the middle of an entry, {} bytes are left over", entry_bytes % 8).into());
+    }
+
+    Ok(IndexHeader {
+        sparseness_factor,
+        suffix_array_length: entry_bytes / 8,
+        compressed,
+        file_size,
+    })
+}
+
+/// Reads the header byte from the start of a binary suffix array file
+///
+/// # Arguments
+/// * `reader` - The reader positioned at the start of the file
+///
+/// # Returns
+///
+/// Returns the sparseness factor of the suffix array and whether the entries are compressed
+///
+/// # Errors
+///
+/// Returns an error if the file is empty
+fn read_header_byte(reader: &mut impl Read) -> Result<(u8, bool), Box<dyn Error>> {
+    let mut header_buffer = [0_u8; 1];
+    reader.read_exact(&mut header_buffer).map_err(|_| "Could not read the sample rate from the binary file")?;
+    Ok((header_buffer[0] & !COMPRESSED_FLAG, header_buffer[0] & COMPRESSED_FLAG != 0))
+}
+
 
 #[cfg(test)]
 mod tests {
-    use crate::binary::{deserialize_sa, Serializable};
+    use std::io::{Error, Write};
+    use std::path::Path;
+
+    use tempdir::TempDir;
+
+    use crate::binary::{deserialize_sa, IndexHeader, load_suffix_array, read_header, read_suffix_array, Serializable, write_atomically, write_suffix_array};
 
     #[test]
     fn test_serialize_deserialize() {
         let data: Vec<i64> = vec![5, 2165487362, -12315135];
         let serialized = data.serialize();
-        let deserialized = deserialize_sa(serialized.as_ref());
+        let deserialized = deserialize_sa(serialized.as_ref()).unwrap();
         assert_eq!(data, deserialized);
     }
 
@@ -128,7 +310,97 @@ mod tests {
     fn test_serialize_deserialize_empty() {
         let data: Vec<i64> = vec![];
         let serialized = data.serialize();
-        let deserialized = deserialize_sa(serialized.as_ref());
+        let deserialized = deserialize_sa(serialized.as_ref()).unwrap();
         assert_eq!(data, deserialized);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_load_suffix_array() {
+        let tmp_dir = TempDir::new("test_write_load_suffix_array").unwrap();
+        let filename = tmp_dir.path().join("index.bin");
+        let filename = filename.to_str().unwrap();
+
+        write_suffix_array(3, &[9, 3, 0, 6], filename, false).unwrap();
+
+        assert_eq!(load_suffix_array(filename).unwrap(), (3, vec![9, 3, 0, 6]));
+        assert!(!Path::new(&format!("{}.tmp", filename)).exists());
+    }
+
+    #[test]
+    fn test_failed_write_keeps_original_file() {
+        let tmp_dir = TempDir::new("test_failed_write_keeps_original_file").unwrap();
+        let filename = tmp_dir.path().join("index.bin");
+        let filename = filename.to_str().unwrap();
+        write_suffix_array(1, &[2, 1, 0], filename, false).unwrap();
+
+        // simulate a crash halfway through writing a new suffix array
+        let result = write_atomically(filename, |f| {
+            f.write_all(&[2, 0, 0])?;
+            Err(Error::other("simulated failure"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(load_suffix_array(filename).unwrap(), (1, vec![2, 1, 0]));
+        assert!(!Path::new(&format!("{}.tmp", filename)).exists());
+    }
+
+    #[test]
+    fn test_deserialize_partial_entry() {
+        let serialized = [5_i64, 2165487362].serialize();
+        assert!(deserialize_sa(&serialized[..13]).is_err());
+    }
+
+    #[test]
+    fn test_read_suffix_array_in_chunks() {
+        let data: Vec<i64> = vec![5, 2165487362, -12315135, 0, 42];
+        let serialized = data.serialize();
+
+        // chunks of 3 and 13 bytes never end on an entry boundary
+        assert_eq!(read_suffix_array(serialized.as_slice(), 3).unwrap(), data);
+        assert_eq!(read_suffix_array(serialized.as_slice(), 13).unwrap(), data);
+        assert_eq!(read_suffix_array(serialized.as_slice(), 8).unwrap(), data);
+
+        // a truncated suffix array is an error instead of a panic
+        assert!(read_suffix_array(&serialized[..serialized.len() - 3], 13).is_err());
+    }
+
+    #[test]
+    fn test_read_header() {
+        let tmp_dir = TempDir::new("test_read_header").unwrap();
+        let filename = tmp_dir.path().join("index.bin");
+        let filename = filename.to_str().unwrap();
+
+        write_suffix_array(3, &[9, 3, 0, 6], filename, false).unwrap();
+        assert_eq!(
+            read_header(filename).unwrap(),
+            IndexHeader { sparseness_factor: 3, suffix_array_length: 4, compressed: false, file_size: 33 }
+        );
+
+        // a truncated or empty file has no valid header
+        std::fs::write(filename, [3, 0, 0]).unwrap();
+        assert!(read_header(filename).is_err());
+        std::fs::write(filename, []).unwrap();
+        assert!(read_header(filename).is_err());
+    }
+
+    #[test]
+    fn test_write_load_compressed_suffix_array() {
+        let tmp_dir = TempDir::new("test_write_load_compressed_suffix_array").unwrap();
+        let filename = tmp_dir.path().join("index.bin");
+        let filename = filename.to_str().unwrap();
+
+        // a suffix array with a lot of repetition, which compresses well
+        let sa: Vec<i64> = (0 .. 10_000).map(|i| i % 100).collect();
+        write_suffix_array(3, &sa, filename, true).unwrap();
+
+        assert_eq!(load_suffix_array(filename).unwrap(), (3, sa.clone()));
+
+        let header = read_header(filename).unwrap();
+        assert_eq!((header.sparseness_factor, header.suffix_array_length, header.compressed), (3, 10_000, true));
+        assert!(header.file_size < 10_000 * 8);
+
+        // the sparseness factor shares the header byte with the compression flag
+        assert!(write_suffix_array(128, &sa, filename, true).is_err());
+        assert_eq!(load_suffix_array(filename).unwrap(), (3, sa));
+    }
+}
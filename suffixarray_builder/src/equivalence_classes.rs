@@ -0,0 +1,109 @@
+//! This module contains the `EquivalenceClasses` struct that is used to equate residues
+//! that can not be distinguished from each other (e.g. I and L in mass spectrometry).
+
+use sa_mappings::alphabet::AminoAcidAlphabet;
+
+/// A table that maps every character to the canonical character of its equivalence class
+/// The suffix array is built on the canonicalized text, so the same table has to be used during construction and search
+pub struct EquivalenceClasses {
+    /// The canonical character for every possible byte
+    canonical: [u8; 256],
+    /// True for every byte that is equivalent to at least one other byte
+    ambiguous: [bool; 256],
+}
+
+impl EquivalenceClasses {
+    /// Creates a new `EquivalenceClasses` table
+    ///
+    /// # Arguments
+    /// * `classes` - The equivalence classes, the first character of each class is used as the canonical character for that class
+    ///
+    /// # Returns
+    ///
+    /// Returns the table where every character of a class maps to the first character of that class,
+    /// and every character that is not part of a class maps to itself
+    pub fn new(classes: &[&str]) -> Self {
+        let mut canonical = [0; 256];
+        for (i, character) in canonical.iter_mut().enumerate() {
+            *character = i as u8;
+        }
+        let mut ambiguous = [false; 256];
+
+        for class in classes.iter().map(|class| class.as_bytes()).filter(|class| class.len() > 1) {
+            for &character in class.iter() {
+                canonical[character as usize] = class[0];
+                ambiguous[character as usize] = true;
+            }
+        }
+
+        Self { canonical, ambiguous }
+    }
+
+    /// Returns the canonical character of the equivalence class of `character`
+    #[inline]
+    pub fn canonical(&self, character: u8) -> u8 {
+        self.canonical[character as usize]
+    }
+
+    /// Returns true if `character` is equivalent to at least one other character
+    #[inline]
+    pub fn is_ambiguous(&self, character: u8) -> bool {
+        self.ambiguous[character as usize]
+    }
+
+    /// Returns true if `a` and `b` are part of the same equivalence class
+    #[inline]
+    pub fn equivalent(&self, a: u8, b: u8) -> bool {
+        self.canonical(a) == self.canonical(b)
+    }
+
+    /// Replaces every character in `data` by its canonical character
+    ///
+    /// # Arguments
+    /// * `data` - The text that is canonicalized in place
+    pub fn canonicalize(&self, data: &mut [u8]) {
+        for character in data.iter_mut() {
+            *character = self.canonical(*character);
+        }
+    }
+}
+
+impl Default for EquivalenceClasses {
+    /// The default table equates the `EQUIVALENT_RESIDUES` of the `AminoAcidAlphabet`, every L is mapped to an I
+    fn default() -> Self {
+        Self::new(AminoAcidAlphabet::EQUIVALENT_RESIDUES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::alphabet::AminoAcidAlphabet;
+
+    use crate::equivalence_classes::EquivalenceClasses;
+
+    #[test]
+    fn test_default_equates_i_and_l() {
+        let classes = EquivalenceClasses::default();
+
+        assert_eq!(classes.canonical(b'L'), b'I');
+        assert_eq!(classes.canonical(b'I'), b'I');
+        assert_eq!(classes.canonical(b'K'), b'K');
+        assert!(classes.is_ambiguous(b'L'));
+        assert!(!classes.is_ambiguous(b'K'));
+
+        // the default table agrees with the canonicalization of the alphabet for every byte
+        assert!((0..=u8::MAX).all(|character| classes.canonical(character) == AminoAcidAlphabet::canonical(character)));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let classes = EquivalenceClasses::new(&["IL", "QK"]);
+        let mut data = "LIKQ-AK$".as_bytes().to_vec();
+
+        classes.canonicalize(&mut data);
+
+        assert_eq!(data, "IIQQ-AQ$".as_bytes());
+        assert!(classes.equivalent(b'K', b'Q'));
+        assert!(!classes.equivalent(b'K', b'I'));
+    }
+}
@@ -1,17 +1,24 @@
 pub mod binary;
+pub mod equivalence_classes;
 
 use std::error::Error;
+use std::mem::size_of;
 use clap::{Parser, ValueEnum};
+use log::LevelFilter;
+use sa_mappings::alphabet::AminoAcidAlphabet;
+use sa_mappings::proteins::{Proteins, SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+
+use crate::equivalence_classes::EquivalenceClasses;
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
 pub struct Arguments {
-    /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
-    #[arg(short, long)]
-    pub database_file: String,
-    #[arg(short, long)]
+    /// File with the proteins used to build the suffix tree. The proteins are concatenated using the `-` separation character.
+    #[arg(short, long, required_unless_present = "index_info")]
+    pub database_file: Option<String>,
+    #[arg(short, long, required_unless_present = "index_info")]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
-    pub taxonomy: String,
+    pub taxonomy: Option<String>,
     /// Output file to store the built index.
     #[arg(short, long)]
     pub output: String,
@@ -20,6 +27,62 @@ pub struct Arguments {
     pub sparseness_factor: u8,
     #[arg(short, long, value_enum, default_value_t = SAConstructionAlgorithm::LibSais)]
     pub construction_algorithm: SAConstructionAlgorithm,
+    /// Only use the proteins of these taxa (comma separated), e.g. to build an organism-specific index
+    #[arg(long, value_delimiter = ',')]
+    pub keep_taxa: Option<Vec<usize>>,
+    /// Collapse the proteins with an identical sequence, so every sequence only occurs once in the index
+    #[arg(long)]
+    pub deduplicate: bool,
+    /// Drop the proteins flagged as a fragment, i.e. with a non-empty value in this (zero-based) column of the database file
+    #[arg(long, value_name = "COLUMN")]
+    pub exclude_fragments: Option<usize>,
+    /// The character that separates the fields of a line in the database file
+    #[arg(long, default_value_t = '\t')]
+    pub separator: char,
+    /// The (zero-based) columns of the uniprot id, taxon id, sequence and functional annotations in the database file (comma separated)
+    #[arg(long, value_delimiter = ',', default_values_t = [0, 1, 2, 3], value_name = "UNIPROT,TAXON,SEQUENCE,ANNOTATIONS")]
+    pub columns: Vec<usize>,
+    /// Do not build the suffix array, but validate that the suffix array stored in `output` is a valid suffix array of the database
+    #[arg(long)]
+    pub validate: bool,
+    /// Only validate every n-th pair of adjacent entries in the suffix array during validation (default value 1, which means every pair is validated)
+    #[arg(long, default_value_t = 1)]
+    pub validation_step: usize,
+    /// Check that the text only contains the protein alphabet (A-Z), the separation character and the termination character before building the suffix array
+    #[arg(long)]
+    pub validate_alphabet: bool,
+    /// Only read the database and report the expected sizes of the index, without building the suffix array
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Gzip compress the written suffix array, which makes the file smaller but loading the suffix array slower
+    #[arg(long)]
+    pub compress: bool,
+    /// Only print the header of the suffix array stored in `output`, without loading the suffix array or the database
+    #[arg(long)]
+    pub index_info: bool,
+    /// The verbosity of the log messages (off, error, warn, info, debug or trace)
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    pub log_level: LevelFilter,
+}
+
+/// Struct representing the expected sizes (in bytes) of an index built over a text
+#[derive(Debug, PartialEq)]
+pub struct SizeEstimate {
+    pub text_length: usize,
+    pub protein_count: usize,
+    pub sa_size: usize,
+    pub dense_mapping_size: usize,
+    pub sparse_mapping_size: usize,
+    pub peak_memory: usize,
+}
+
+/// Struct representing the memory saved by a sparse suffix array and the extra search work it costs, compared to a dense suffix array
+#[derive(Debug, PartialEq)]
+pub struct SparsenessTradeoff {
+    pub sa_size: usize,
+    pub dense_sa_size: usize,
+    pub saved_bytes: usize,
+    pub searches_per_peptide: usize,
 }
 
 /// Enum representing the two possible algorithms to construct the suffix array
@@ -44,14 +107,79 @@ pub enum SAConstructionAlgorithm {
 ///
 /// The errors that occurred during the building of the suffix array itself
 pub fn build_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sparseness_factor: u8) -> Result<Vec<i64>, Box<dyn Error>> {
-    
     // translate all L's to a I
-    for character in data.iter_mut() {
-        if *character == b'L' {
-            *character = b'I'
-        }
+    build_sa_with_equivalence_classes(data, &EquivalenceClasses::default(), construction_algorithm, sparseness_factor)
+}
+
+/// Builds the suffix array over the text where every residue is replaced by the canonical residue of its equivalence class
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the suffix array, this text is canonicalized in place
+/// * `equivalence_classes` - The residues that are equated, the searcher should use the same equivalence classes
+/// * `construction_algorithm` - The algorithm used during construction
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+///
+/// # Returns
+///
+/// Returns the constructed suffix array
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array itself
+pub fn build_sa_with_equivalence_classes(
+    data: &mut Vec<u8>,
+    equivalence_classes: &EquivalenceClasses,
+    construction_algorithm: &SAConstructionAlgorithm,
+    sparseness_factor: u8
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    equivalence_classes.canonicalize(data);
+    construct_sa(data, construction_algorithm, sparseness_factor)
+}
+
+/// Builds the suffix array over the text of `proteins`, without the caller having to copy the text first
+///
+/// # Arguments
+/// * `proteins` - The proteins on which we want to build the suffix array
+/// * `construction_algorithm` - The algorithm used during construction
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `equalize_i_and_l` - If true every L is replaced by an I before construction, this is required for the default equivalence classes of the searcher
+///
+/// # Returns
+///
+/// Returns the constructed suffix array
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array itself
+pub fn build_sa_from_proteins(
+    proteins: &Proteins,
+    construction_algorithm: &SAConstructionAlgorithm,
+    sparseness_factor: u8,
+    equalize_i_and_l: bool
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    // the text of the proteins is kept for searching, so the construction works on a single copy of the text
+    let mut data = proteins.input_string.to_vec();
+    if equalize_i_and_l {
+        EquivalenceClasses::default().canonicalize(&mut data);
     }
-    
+    construct_sa(&mut data, construction_algorithm, sparseness_factor)
+}
+
+/// Constructs the (sparse) suffix array over the text as is
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the suffix array
+/// * `construction_algorithm` - The algorithm used during construction
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+///
+/// # Returns
+///
+/// Returns the constructed suffix array
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array itself
+fn construct_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sparseness_factor: u8) -> Result<Vec<i64>, Box<dyn Error>> {
     let mut sa = match construction_algorithm {
         SAConstructionAlgorithm::LibSais => libsais64_rs::sais64(data),
         SAConstructionAlgorithm::LibDivSufSort => {
@@ -74,4 +202,299 @@ pub fn build_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgor
     }
 
     Ok(sa)
-}
\ No newline at end of file
+}
+
+/// Derives a sparser suffix array from an existing suffix array, without rebuilding it from the text
+/// Only the suffixes that start at a multiple of `new_factor` are kept, which is the same sampling as done by `build_sa`
+///
+/// # Arguments
+/// * `sa` - The existing (sparse) suffix array
+/// * `old_factor` - The sparseness factor used by `sa`
+/// * `new_factor` - The sparseness factor of the resulting suffix array
+///
+/// # Returns
+///
+/// Returns the suffix array with sparseness factor `new_factor`
+///
+/// # Errors
+///
+/// Returns an error if one of the factors is 0, or if `new_factor` is not a multiple of `old_factor`
+pub fn resample_suffix_array(sa: &[i64], old_factor: u8, new_factor: u8) -> Result<Vec<i64>, Box<dyn Error>> {
+    if old_factor == 0 || new_factor == 0 {
+        return Err("The sparseness factor should be at least 1".into());
+    }
+    // the suffixes that are not in the existing suffix array can not be recovered without rebuilding
+    if !new_factor.is_multiple_of(old_factor) {
+        return Err(format!(
+            "The new sparseness factor {} is not a multiple of the old sparseness factor {}",
+            new_factor, old_factor
+        ).into());
+    }
+
+    Ok(sa
+        .iter()
+        .copied()
+        .filter(|&suffix| suffix % new_factor as i64 == 0)
+        .collect())
+}
+
+/// Calculates the tradeoff of a sparseness factor, to make an informed choice of the sparseness factor
+///
+/// # Arguments
+/// * `text_length` - The length of the text on which the suffix array is built
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+///
+/// # Returns
+///
+/// Returns the size (in bytes) of the sparse and the dense suffix array, the bytes saved by the sparse suffix array
+/// and the number of binary searches needed for a peptide in the worst case, which is one search per skipped offset
+pub fn sparseness_tradeoff(text_length: usize, sparseness_factor: u8) -> SparsenessTradeoff {
+    let sparseness_factor = sparseness_factor.max(1) as usize;
+    let sa_size = text_length.div_ceil(sparseness_factor) * size_of::<i64>();
+    let dense_sa_size = text_length * size_of::<i64>();
+
+    SparsenessTradeoff {
+        sa_size,
+        dense_sa_size,
+        saved_bytes: dense_sa_size - sa_size,
+        searches_per_peptide: sparseness_factor,
+    }
+}
+
+/// Estimates the sizes of the index that would be built over `data`, without building it
+///
+/// # Arguments
+/// * `data` - The text on which the suffix array would be built
+/// * `sparseness_factor` - The sparseness factor that would be used on the suffix array
+///
+/// # Returns
+///
+/// Returns the expected size of the suffix array, of both suffix to protein mappings and the peak memory during construction
+pub fn estimate_sizes(data: &[u8], sparseness_factor: u8) -> SizeEstimate {
+    let text_length = data.len();
+    let protein_count = data
+        .iter()
+        .filter(|&&character| AminoAcidAlphabet::is_sentinel(character))
+        .count();
+
+    SizeEstimate {
+        text_length,
+        protein_count,
+        sa_size: sparseness_tradeoff(text_length, sparseness_factor).sa_size,
+        dense_mapping_size: text_length * size_of::<u32>(),
+        sparse_mapping_size: (protein_count + 1) * size_of::<i64>(),
+        // the text and the full (not yet sparse) suffix array are in memory at the same time during construction
+        peak_memory: text_length + text_length * size_of::<i64>(),
+    }
+}
+
+/// Validates that a text only consists of the bytes the suffix array construction and the search expect
+/// The termination character must be the smallest byte of the text, any byte that sorts before it breaks the order of the suffix array
+///
+/// # Arguments
+/// * `data` - The text on which the suffix array would be built
+///
+/// # Returns
+///
+/// Returns () if the text consists of uppercase residues (A-Z) and separation characters, ended by a single termination character
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` describing the first byte that is not part of the alphabet, or the missing termination character
+pub fn validate_alphabet(data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let Some((&last, text)) = data.split_last() else {
+        return Err("The text is empty, it should at least contain the termination character".into());
+    };
+
+    if let Some(position) = text
+        .iter()
+        .position(|&character| !AminoAcidAlphabet::is_residue(character) && character != SEPARATION_CHARACTER)
+    {
+        let character = text[position];
+        let reason = if character < TERMINATION_CHARACTER {
+            format!("it sorts before the termination character '{}' and breaks the order of the suffix array", TERMINATION_CHARACTER as char)
+        } else if character == TERMINATION_CHARACTER {
+            "the termination character is only allowed at the end of the text".to_string()
+        } else {
+            format!(
+                "only the residues A-Z, the separation character '{}' and the termination character '{}' are allowed",
+                SEPARATION_CHARACTER as char,
+                TERMINATION_CHARACTER as char
+            )
+        };
+        return Err(format!("Invalid byte 0x{:02x} at position {} of the text, {}", character, position, reason).into());
+    }
+
+    if last != TERMINATION_CHARACTER {
+        return Err(format!("The text does not end with the termination character '{}'", TERMINATION_CHARACTER as char).into());
+    }
+
+    Ok(())
+}
+
+/// Validates that `sa` is a correctly sorted (sparse) suffix array of `data`
+/// During the comparison I and L are considered equal, since the suffix array is built with every L replaced by an I
+///
+/// # Arguments
+/// * `data` - The text on which the suffix array should be built
+/// * `sa` - The suffix array that is validated
+/// * `step` - Only every `step`-th pair of adjacent suffixes is validated, a step of 1 validates the whole suffix array
+///
+/// # Returns
+///
+/// Returns None if the validated part of the suffix array is valid
+/// Returns Some with the first index `i` in the suffix array for which `data[sa[i]..] <= data[sa[i + 1]..]` does not hold
+pub fn validate_sa(data: &[u8], sa: &[i64], step: usize) -> Option<usize> {
+    let equivalence_classes = EquivalenceClasses::default();
+    let equalize_il = |&character: &u8| equivalence_classes.canonical(character);
+    let in_bounds = |suffix: i64| suffix >= 0 && (suffix as usize) < data.len();
+
+    for i in (0..sa.len().saturating_sub(1)).step_by(step.max(1)) {
+        let (current, next) = (sa[i], sa[i + 1]);
+        if !in_bounds(current) || !in_bounds(next) {
+            return Some(i);
+        }
+        let current_suffix = data[current as usize..].iter().map(equalize_il);
+        let next_suffix = data[next as usize..].iter().map(equalize_il);
+        if current_suffix.gt(next_suffix) {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::alphabet::AminoAcidAlphabet;
+    use sa_mappings::proteins::{LoadOptions, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+
+    use crate::equivalence_classes::EquivalenceClasses;
+    use crate::{build_sa, build_sa_from_proteins, build_sa_with_equivalence_classes, estimate_sizes, resample_suffix_array, SAConstructionAlgorithm, SizeEstimate, sparseness_tradeoff, SparsenessTradeoff, validate_alphabet, validate_sa};
+
+    #[test]
+    fn test_validate_sa() {
+        let data = "AI-BLACVAA-AC-KCRLZ$".as_bytes().to_vec();
+        let mut sa = build_sa(&mut data.clone(), &SAConstructionAlgorithm::LibSais, 1).unwrap();
+        assert_eq!(validate_sa(&data, &sa, 1), None);
+
+        // swapping 2 entries should be detected
+        sa.swap(4, 5);
+        assert_eq!(validate_sa(&data, &sa, 1), Some(4));
+    }
+
+    #[test]
+    fn test_validate_sparse_sa() {
+        let data = "AI-BLACVAA-AC-KCRLZ$".as_bytes().to_vec();
+        let sa = build_sa(&mut data.clone(), &SAConstructionAlgorithm::LibSais, 3).unwrap();
+        assert_eq!(validate_sa(&data, &sa, 1), None);
+    }
+
+    #[test]
+    fn test_validate_sa_out_of_bounds() {
+        let data = "ACG$".as_bytes().to_vec();
+        assert_eq!(validate_sa(&data, &[3, 0, 4, 2], 1), Some(1));
+    }
+
+    #[test]
+    fn test_estimate_sizes() {
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap();
+        let (data, _) = Proteins::try_from_database_file_without_annotations("../testfiles/small_database.tsv", &taxon_aggregator, &LoadOptions::default()).unwrap();
+
+        assert_eq!(estimate_sizes(&data, 3), SizeEstimate {
+            text_length: 68,
+            protein_count: 3,
+            sa_size: 23 * 8,
+            dense_mapping_size: 68 * 4,
+            sparse_mapping_size: 4 * 8,
+            peak_memory: 68 * 9,
+        });
+    }
+
+    #[test]
+    fn test_sparseness_tradeoff() {
+        assert_eq!(sparseness_tradeoff(68, 1), SparsenessTradeoff {
+            sa_size: 68 * 8,
+            dense_sa_size: 68 * 8,
+            saved_bytes: 0,
+            searches_per_peptide: 1,
+        });
+        assert_eq!(sparseness_tradeoff(68, 3), SparsenessTradeoff {
+            sa_size: 23 * 8,
+            dense_sa_size: 68 * 8,
+            saved_bytes: 45 * 8,
+            searches_per_peptide: 3,
+        });
+        assert_eq!(sparseness_tradeoff(1_000_000, 4).sa_size, 2_000_000);
+        assert_eq!(sparseness_tradeoff(1_000_000, 4).saved_bytes, 6_000_000);
+        assert_eq!(sparseness_tradeoff(0, 2).sa_size, 0);
+    }
+
+    #[test]
+    fn test_resample_suffix_array() {
+        let data = "AI-BLACVAA-AC-KCRLZ$".as_bytes().to_vec();
+        let dense_sa = build_sa(&mut data.clone(), &SAConstructionAlgorithm::LibSais, 1).unwrap();
+        let sparse_sa = build_sa(&mut data.clone(), &SAConstructionAlgorithm::LibSais, 3).unwrap();
+
+        assert_eq!(resample_suffix_array(&dense_sa, 1, 3).unwrap(), sparse_sa);
+
+        let sparsest_sa = build_sa(&mut data.clone(), &SAConstructionAlgorithm::LibSais, 6).unwrap();
+        assert_eq!(resample_suffix_array(&sparse_sa, 3, 6).unwrap(), sparsest_sa);
+    }
+
+    #[test]
+    fn test_resample_suffix_array_invalid_factor() {
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+        assert!(resample_suffix_array(&sa, 3, 4).is_err());
+        assert!(resample_suffix_array(&sa, 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_build_sa_from_proteins() {
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap();
+        let (proteins, _) = Proteins::try_from_database_file("../testfiles/small_database.tsv", &taxon_aggregator, &LoadOptions::default()).unwrap();
+
+        for sparseness_factor in [1, 3] {
+            let expected = build_sa(&mut proteins.input_string.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor).unwrap();
+            let sa = build_sa_from_proteins(&proteins, &SAConstructionAlgorithm::LibSais, sparseness_factor, true).unwrap();
+            assert_eq!(sa, expected);
+        }
+
+        // without equating I and L the text is used as is
+        let expected = build_sa_with_equivalence_classes(
+            &mut proteins.input_string.to_vec(),
+            &EquivalenceClasses::new(&[]),
+            &SAConstructionAlgorithm::LibSais,
+            1
+        ).unwrap();
+        let sa = build_sa_from_proteins(&proteins, &SAConstructionAlgorithm::LibSais, 1, false).unwrap();
+        assert_eq!(sa, expected);
+    }
+
+    #[test]
+    fn test_validate_alphabet() {
+        assert!(validate_alphabet(b"AI-BLACVAA-AC-KCRLZ$").is_ok());
+
+        // a control character sorts before the termination character
+        let error = validate_alphabet(b"AI-BLA\x1fCVAA-AC-KCRLZ$").unwrap_err();
+        assert!(error.to_string().contains("0x1f at position 6"));
+        assert!(error.to_string().contains("sorts before the termination character"));
+
+        assert!(validate_alphabet(b"AI-BLA CVAA$").is_err());
+        assert!(validate_alphabet(b"AI-blacvaa$").is_err());
+        assert!(validate_alphabet(b"AI$BLACVAA$").is_err());
+        assert!(validate_alphabet(b"AI-BLACVAA").is_err());
+        assert!(validate_alphabet(b"").is_err());
+    }
+
+    #[test]
+    fn test_validate_alphabet_agrees_with_alphabet() {
+        // every valid byte is accepted inside a protein, except the termination character which may only end the text
+        for character in 0..=u8::MAX {
+            let accepted = validate_alphabet(&[b'A', character, b'A', AminoAcidAlphabet::TERMINATION_CHARACTER]).is_ok();
+            let expected = AminoAcidAlphabet::is_valid(character) && character != AminoAcidAlphabet::TERMINATION_CHARACTER;
+            assert_eq!(accepted, expected, "byte {:#04x}", character);
+        }
+    }
+}
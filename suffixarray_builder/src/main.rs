@@ -1,12 +1,51 @@
-use clap::Parser;
-use sa_mappings::proteins::Proteins;
+use std::collections::HashSet;
+
+use clap::{CommandFactory, Parser};
+use clap::error::ErrorKind;
+use log::info;
+use sa_mappings::proteins::{DatabaseSchema, LoadOptions, Proteins};
 use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray_builder::{Arguments, build_sa};
-use suffixarray_builder::binary::write_suffix_array;
+use suffixarray_builder::{Arguments, build_sa, estimate_sizes, sparseness_tradeoff, SparsenessTradeoff, validate_alphabet, validate_sa};
+use suffixarray_builder::binary::{load_suffix_array, read_header, write_suffix_array};
 
 fn main() {
     let args = Arguments::parse();
-    let Arguments { database_file, taxonomy, output, sparseness_factor, construction_algorithm } = args;
+    env_logger::Builder::new().filter_level(args.log_level).init();
+    let Arguments { database_file, taxonomy, output, sparseness_factor, construction_algorithm, keep_taxa, deduplicate, exclude_fragments, separator, columns, validate, validation_step, validate_alphabet: check_alphabet, dry_run, index_info, compress, .. } = args;
+
+    if index_info {
+        match read_header(&output) {
+            Ok(header) => {
+                println!("Sparseness factor: {}", header.sparseness_factor);
+                println!("Suffix array length: {}", header.suffix_array_length);
+                println!("Compressed: {}", header.compressed);
+                println!("File size: {} bytes", header.file_size);
+                // the text length is only known up to the sparseness factor, which does not matter for this estimate
+                let text_length = header.suffix_array_length as usize * header.sparseness_factor as usize;
+                print_sparseness_tradeoff(&sparseness_tradeoff(text_length, header.sparseness_factor));
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let (Some(database_file), Some(taxonomy)) = (database_file, taxonomy) else {
+        Arguments::command()
+            .error(ErrorKind::MissingRequiredArgument, "--database-file and --taxonomy are required unless --index-info is used")
+            .exit()
+    };
+
+    let database_schema = DatabaseSchema::try_new(separator, &columns);
+    if let Err(err) = database_schema {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let database_schema = database_schema.unwrap();
+
+    info!("Loading taxon file...");
     let taxon_id_calculator = TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar);  
     if let Err(err) = taxon_id_calculator {
         eprintln!("{}", err);
@@ -16,13 +55,62 @@ fn main() {
     let taxon_id_calculator = taxon_id_calculator.unwrap();
     
     // read input
-    let data = Proteins::try_from_database_file_without_annotations(&database_file, &taxon_id_calculator);
+    info!("Loading proteins...");
+    let keep_taxa: Option<HashSet<usize>> = keep_taxa.map(|taxa| taxa.into_iter().collect());
+    let load_options = LoadOptions {
+        keep_taxa: keep_taxa.as_ref(),
+        fragment_column: exclude_fragments,
+        schema: database_schema,
+        deduplicate,
+        ..Default::default()
+    };
+    let data = Proteins::try_from_database_file_without_annotations(&database_file, &taxon_id_calculator, &load_options);
     if let Err(err) = data {
         eprintln!("{}", err);
         std::process::exit(1);
     }
-    let mut data = data.unwrap();
+    let (mut data, _) = data.unwrap();
+
+    if check_alphabet {
+        if let Err(err) = validate_alphabet(&data) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if dry_run {
+        let estimate = estimate_sizes(&data, sparseness_factor);
+        println!("Text length: {} characters", estimate.text_length);
+        println!("Proteins: {}", estimate.protein_count);
+        println!("Suffix array size: {} bytes", estimate.sa_size);
+        print_sparseness_tradeoff(&sparseness_tradeoff(estimate.text_length, sparseness_factor));
+        println!("Dense suffix to protein mapping size: {} bytes", estimate.dense_mapping_size);
+        println!("Sparse suffix to protein mapping size: {} bytes", estimate.sparse_mapping_size);
+        println!("Estimated peak memory during construction: {} bytes", estimate.peak_memory);
+        return;
+    }
+
+    if validate {
+        info!("Loading suffix array...");
+        let (_, sa) = match load_suffix_array(&output) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        match validate_sa(&data, &sa, validation_step) {
+            None => println!("OK"),
+            Some(index) => {
+                eprintln!("Suffix array is not sorted at index {}", index);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     // calculate sa
+    info!("Building suffix array...");
     let sa = build_sa(&mut data, &construction_algorithm, sparseness_factor);
     if let Err(err) = sa {
         eprintln!("{}", err);
@@ -31,8 +119,19 @@ fn main() {
     let sa = sa.unwrap();
     
     // output the build SA
-    if let Err(err) = write_suffix_array(sparseness_factor, &sa, &output) {
+    info!("Writing suffix array...");
+    if let Err(err) = write_suffix_array(sparseness_factor, &sa, &output, compress) {
         eprintln!("{}", err);
         std::process::exit(1);
     };
-}
\ No newline at end of file
+}
+
+/// Prints the memory saved by the sparseness factor and the extra search work it costs
+///
+/// # Arguments
+/// * `tradeoff` - The tradeoff of the sparseness factor
+fn print_sparseness_tradeoff(tradeoff: &SparsenessTradeoff) {
+    println!("Dense suffix array size: {} bytes", tradeoff.dense_sa_size);
+    println!("Saved by the sparseness factor: {} bytes", tradeoff.saved_bytes);
+    println!("Binary searches per peptide (worst case): {}", tradeoff.searches_per_peptide);
+}
@@ -1,24 +1,33 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::sync::Arc;
 
 use axum::{http::StatusCode, Json, Router};
-use axum::extract::{DefaultBodyLimit, State};
+use axum::extract::{DefaultBodyLimit, MatchedPath, Path, Request, State};
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use clap::Parser;
+use log::{info, LevelFilter};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder, TEXT_FORMAT};
+use schemars::{JsonSchema, schema_for};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use sa_mappings::functionality::FunctionAggregator;
-use sa_mappings::proteins::Proteins;
+use sa_mappings::proteins::{AccessionIndex, DatabaseSchema, LoadOptions, Proteins};
 use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray::peptide_search::{OutputData, analyse_all_peptides, SearchResultWithAnalysis, SearchOnlyResult, search_all_peptides};
-use suffixarray::sa_searcher::Searcher;
-use suffixarray::suffix_to_protein_index::SparseSuffixToProtein;
+use suffixarray::peptide_search::{CutoffPolicy, OutputData, OutputDataWithStats, analyse_all_peptides, analyse_all_peptides_with_stats, SearchResultWithAnalysis, SearchOnlyResult, search_all_peptides, SearchOptions, taxonomic_profile, TaxonomicProfile};
+use suffixarray::sa_searcher::{IndexStatistics, Searcher};
+use suffixarray::suffix_to_protein_index::{load_suffix_to_protein_mapping, SparseSuffixToProtein, SuffixToProteinIndex};
 use suffixarray_builder::binary::load_suffix_array;
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
 pub struct Arguments {
-    /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
+    /// File with the proteins used to build the suffix tree. The proteins are concatenated using the `-` separation character.
     #[arg(short, long)]
     database_file: String,
     #[arg(short, long)]
@@ -26,15 +35,35 @@ pub struct Arguments {
     #[arg(short, long)]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
     taxonomy: String,
+    /// Only use the proteins of these taxa (comma separated), this should be the same list as used to build the index
+    #[arg(long, value_delimiter = ',')]
+    keep_taxa: Option<Vec<usize>>,
+    /// Collapse the proteins with an identical sequence, this should be the same as used to build the index
+    #[arg(long)]
+    deduplicate: bool,
+    /// Drop the proteins flagged as a fragment in this (zero-based) column, this should be the same column as used to build the index
+    #[arg(long, value_name = "COLUMN")]
+    exclude_fragments: Option<usize>,
+    /// The character that separates the fields of a line in the database file, this should be the same as used to build the index
+    #[arg(long, default_value_t = '\t')]
+    separator: char,
+    /// The (zero-based) columns of the uniprot id, taxon id, sequence and functional annotations in the database file (comma separated), these should be the same as used to build the index
+    #[arg(long, value_delimiter = ',', default_values_t = [0, 1, 2, 3], value_name = "UNIPROT,TAXON,SEQUENCE,ANNOTATIONS")]
+    columns: Vec<usize>,
+    /// File with the suffix-to-protein mapping stored with `--mapping-output`, it is loaded instead of building the sparse mapping at startup
+    #[arg(long)]
+    mapping_file: Option<String>,
+    /// The verbosity of the log messages (off, error, warn, info, debug or trace)
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    log_level: LevelFilter,
 }
 
 /// Function used by serde to place a default value in the cutoff field of the input
-fn default_cutoff() -> usize {
-    10000
+fn default_cutoff() -> CutoffPolicy {
+    CutoffPolicy::Constant(10000)
 }
 
 /// Function used by serde to use `true` as a default value
-#[allow(dead_code)]
 fn default_true() -> bool {
     true
 }
@@ -43,25 +72,198 @@ fn default_true() -> bool {
 /// 
 /// # Arguments
 /// * `peptides` - List of peptides we want to process
-/// * `cutoff` - The maximum amount of matches to process, default value 10000
+/// * `cutoff` - The maximum amount of matches to process, either a number or a length dependent `{base, factor, max_length}` object, default value 10000
 /// * `equalize_I_and_L` - True if we want to equalize I and L during search
 /// * `clean_taxa` - True if we only want to use proteins marked as "valid"
-#[derive(Debug, Deserialize, Serialize)]
+/// * `preserve_case` - True if we want to search case-sensitive instead of uppercasing the peptides
+/// * `include_index` - True if we want to add the position of each peptide in `peptides` to its result
+/// * `include_sequences` - True if we want to add the sequence of every matched protein to the result of the `/search` endpoint
+/// * `include_taxa` - True if we want to add the taxon of every matched protein to the analysis result, default value true
+/// * `include_raw_lca` - True if we want to add the lca before it is snapped to a valid taxon to the analysis result
+/// * `functional_analysis` - True if we want to perform the functional analysis, default value true
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[allow(non_snake_case)]
 struct InputData {
     peptides: Vec<String>,
     #[serde(default = "default_cutoff")] // default value is 10000
-    cutoff: usize,
+    cutoff: CutoffPolicy,
     #[serde(default = "bool::default")]
     // default value is false // TODO: maybe default should be true?
     equalize_I_and_L: bool,
     #[serde(default = "bool::default")] // default value is false
     clean_taxa: bool,
+    #[serde(default = "bool::default")] // default value is false
+    preserve_case: bool,
+    #[serde(default = "bool::default")] // default value is false
+    include_index: bool,
+    #[serde(default = "bool::default")] // default value is false
+    include_sequences: bool,
+    #[serde(default = "default_true")] // default value is true
+    include_taxa: bool,
+    #[serde(default = "bool::default")] // default value is false
+    include_raw_lca: bool,
+    #[serde(default = "default_true")] // default value is true
+    functional_analysis: bool,
+    #[serde(default)] // default value is None, which returns every matched protein
+    max_proteins_per_peptide: Option<usize>,
+}
+
+impl InputData {
+    /// Collects the options of the request that determine how the peptides are searched and analysed
+    ///
+    /// # Returns
+    ///
+    /// Returns the `SearchOptions` of the request
+    fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            equalize_i_and_l: self.equalize_I_and_L,
+            clean_taxa: self.clean_taxa,
+            preserve_case: self.preserve_case,
+            include_index: self.include_index,
+            include_sequences: self.include_sequences,
+            include_taxa: self.include_taxa,
+            include_raw_lca: self.include_raw_lca,
+            functional_analysis: self.functional_analysis,
+        }
+    }
+}
+
+/// Struct representing the full record of a protein, returned by the `/protein` endpoint
+///
+/// # Arguments
+/// * `uniprot_id` - The uniprot id of the protein
+/// * `taxon_id` - The taxon id of the protein
+/// * `sequence` - The sequence of the protein
+/// * `functional_annotations` - The decoded functional annotations of the protein
+#[derive(Debug, Serialize, JsonSchema)]
+struct ProteinRecord {
+    uniprot_id: String,
+    taxon_id: usize,
+    sequence: String,
+    functional_annotations: Vec<String>,
+}
+
+/// State used by the `/protein` endpoint to look up proteins by their uniprot id
+#[derive(Clone)]
+struct ProteinLookup {
+    searcher: Arc<Searcher>,
+    accession_index: Arc<AccessionIndex>,
+}
+
+/// The prefix of the names of all the metrics exposed by the `/metrics` endpoint
+const METRICS_PREFIX: &str = "suffixarray_server";
+
+/// The metrics of the server, exposed in the Prometheus text format by the `/metrics` endpoint
+struct ServerMetrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    errors: IntCounterVec,
+    request_duration: HistogramVec,
+}
+
+impl ServerMetrics {
+    /// Creates the metrics of the server, the size of the index is recorded once since the index does not change
+    ///
+    /// # Arguments
+    /// * `statistics` - The statistics of the loaded index
+    ///
+    /// # Returns
+    ///
+    /// Returns the metrics with all request counters at 0
+    ///
+    /// # Errors
+    ///
+    /// Returns a `prometheus::Error` if a metric could not be created or registered
+    fn try_new(statistics: &IndexStatistics) -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some(METRICS_PREFIX.to_string()), None)?;
+
+        let requests = IntCounterVec::new(Opts::new("requests_total", "The number of handled requests per endpoint"), &["endpoint"])?;
+        let errors = IntCounterVec::new(
+            Opts::new("request_errors_total", "The number of requests per endpoint that returned an error status"),
+            &["endpoint", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "The time it took to handle a request per endpoint, including the search"),
+            &["endpoint"],
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+
+        for (name, help, value) in [
+            ("index_proteins", "The number of proteins in the index", statistics.protein_count),
+            ("index_residues", "The total number of residues of all proteins in the index", statistics.residue_count),
+            ("index_suffix_array_entries", "The number of entries in the (sparse) suffix array", statistics.suffix_array_length),
+            ("index_sparseness_factor", "The sparseness factor used by the suffix array", statistics.sparseness_factor as usize),
+        ] {
+            let gauge = IntGauge::new(name, help)?;
+            gauge.set(value as i64);
+            registry.register(Box::new(gauge))?;
+        }
+
+        Ok(ServerMetrics { registry, requests, errors, request_duration })
+    }
+}
+
+/// The media type of a MessagePack response, a compact binary alternative to the JSON responses
+const MSGPACK_MEDIA_TYPE: &str = "application/x-msgpack";
+
+/// Enum that represents the formats in which the search and analysis results can be returned
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ResponseFormat {
+    Json,
+    MessagePack,
+}
+
+impl ResponseFormat {
+    /// Chooses the format of the response based on the `Accept` header of the request
+    ///
+    /// # Arguments
+    /// * `headers` - The headers of the request
+    ///
+    /// # Returns
+    ///
+    /// Returns `MessagePack` if the client accepts `application/x-msgpack`, otherwise `Json`
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let accepts_msgpack = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media_type| {
+                // ignore parameters such as the quality value
+                media_type.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case(MSGPACK_MEDIA_TYPE)
+            });
+
+        if accepts_msgpack {
+            ResponseFormat::MessagePack
+        } else {
+            ResponseFormat::Json
+        }
+    }
+}
+
+/// A response that is serialized in the format requested by the client
+struct Negotiated<T>(T, ResponseFormat);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Negotiated(data, format) = self;
+        match format {
+            ResponseFormat::Json => Json(data).into_response(),
+            // the fields are serialized by name, so the optional fields that are skipped in the JSON output can be skipped as well
+            ResponseFormat::MessagePack => match rmp_serde::to_vec_named(&data) {
+                Ok(body) => ([(CONTENT_TYPE, MSGPACK_MEDIA_TYPE)], body).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            },
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     let args = Arguments::parse();
+    env_logger::Builder::new().filter_level(args.log_level).init();
     if let Err(err) = start_server(args).await {
         eprintln!("{}", err);
         std::process::exit(1);
@@ -77,48 +279,210 @@ async fn root() -> &'static str {
 ///
 /// # Arguments
 /// * `state(searcher)` - The searcher object provided by the server
+/// * `headers` - The headers of the request, `Accept: application/x-msgpack` returns the results as MessagePack instead of JSON
 /// * `data` - InputData object provided by the user with the peptides to be searched and the config
 /// 
 /// # Returns
 ///
-/// Returns the search and analysis results from the index as a JSON
+/// Returns the search and analysis results from the index as a JSON or MessagePack
 async fn analyse(
     State(searcher): State<Arc<Searcher>>,
+    headers: HeaderMap,
+    data: Json<InputData>,
+) -> Result<Negotiated<OutputData<SearchResultWithAnalysis>>, StatusCode> {
+    let search_result = analyse_all_peptides(&searcher, &data.peptides, data.cutoff, &data.search_options());
+
+    Ok(Negotiated(search_result, ResponseFormat::from_headers(&headers)))
+}
+
+/// Endpoint executed for peptide matching and taxonomic and functional analysis, that also returns
+/// a histogram of the number of matched proteins per peptide to help choosing the cutoff
+///
+/// # Arguments
+/// * `state(searcher)` - The searcher object provided by the server
+/// * `headers` - The headers of the request, `Accept: application/x-msgpack` returns the results as MessagePack instead of JSON
+/// * `data` - InputData object provided by the user with the peptides to be searched and the config
+///
+/// # Returns
+///
+/// Returns the search and analysis results from the index, together with the match count histogram as a JSON or MessagePack
+async fn analyse_with_stats(
+    State(searcher): State<Arc<Searcher>>,
+    headers: HeaderMap,
     data: Json<InputData>,
-) -> Result<Json<OutputData<SearchResultWithAnalysis>>, StatusCode> {
-    let search_result = analyse_all_peptides(
-        &searcher,
-        &data.peptides,
-        data.cutoff,
-        data.equalize_I_and_L,
-        data.clean_taxa,
-    );
+) -> Result<Negotiated<OutputDataWithStats<SearchResultWithAnalysis>>, StatusCode> {
+    let search_result = analyse_all_peptides_with_stats(&searcher, &data.peptides, data.cutoff, &data.search_options());
 
-    Ok(Json(search_result))
+    Ok(Negotiated(search_result, ResponseFormat::from_headers(&headers)))
 }
 
 /// Endpoint executed for peptide matching, without any analysis
 ///
 /// # Arguments
 /// * `state(searcher)` - The searcher object provided by the server
+/// * `headers` - The headers of the request, `Accept: application/x-msgpack` returns the results as MessagePack instead of JSON
 /// * `data` - InputData object provided by the user with the peptides to be searched and the config
 ///
 /// # Returns
 ///
-/// Returns the search results from the index as a JSON
+/// Returns the search results from the index as a JSON or MessagePack
 async fn search(
     State(searcher): State<Arc<Searcher>>,
+    headers: HeaderMap,
     data: Json<InputData>,
-) -> Result<Json<OutputData<SearchOnlyResult>>, StatusCode> {
-    let search_result = search_all_peptides(
-        &searcher,
-        &data.peptides,
-        data.cutoff,
-        data.equalize_I_and_L,
-        data.clean_taxa,
-    );
+) -> Result<Negotiated<OutputData<SearchOnlyResult>>, StatusCode> {
+    let mut search_result = search_all_peptides(&searcher, &data.peptides, data.cutoff, &data.search_options());
+
+    // only the returned proteins are limited, the cutoff still determines how many matches are processed
+    if let Some(max_proteins) = data.max_proteins_per_peptide {
+        search_result.truncate_proteins(max_proteins);
+    }
+
+    Ok(Negotiated(search_result, ResponseFormat::from_headers(&headers)))
+}
+
+/// Endpoint that retrieves the full record of a single protein, e.g. to inspect a protein found during search
+///
+/// # Arguments
+/// * `state(lookup)` - The searcher and the accession index provided by the server
+/// * `accession` - The uniprot id of the requested protein
+///
+/// # Returns
+///
+/// Returns the uniprot id, taxon id, sequence and functional annotations of the protein as a JSON
+///
+/// # Errors
+///
+/// Returns `NOT_FOUND` if there is no protein with the given uniprot id
+async fn protein(
+    State(lookup): State<ProteinLookup>,
+    Path(accession): Path<String>,
+) -> Result<Json<ProteinRecord>, StatusCode> {
+    let (index, range) = lookup.accession_index.get(&accession).ok_or(StatusCode::NOT_FOUND)?;
+    let proteins = lookup.searcher.proteins();
+    let protein = &proteins[index];
+
+    Ok(Json(ProteinRecord {
+        uniprot_id: protein.uniprot_id.clone(),
+        taxon_id: protein.taxon_id,
+        sequence: String::from_utf8_lossy(&proteins.input_string[range]).to_string(),
+        functional_annotations: lookup.searcher.get_all_functional_annotations(&[protein]).pop().unwrap_or_default(),
+    }))
+}
+
+/// Endpoint executed for the taxonomic profile of a list of peptides
+///
+/// # Arguments
+/// * `state(searcher)` - The searcher object provided by the server
+/// * `data` - InputData object provided by the user with the peptides to be searched and the config
+///
+/// # Returns
+///
+/// Returns for every rank the number of peptides per taxon as a JSON, a peptide is counted for its lca and every ranked ancestor of its lca
+async fn taxa_profile(
+    State(searcher): State<Arc<Searcher>>,
+    data: Json<InputData>,
+) -> Result<Json<TaxonomicProfile>, StatusCode> {
+    // only the lca of every peptide is needed for the profile
+    let options = SearchOptions {
+        include_index: false,
+        include_taxa: false,
+        include_raw_lca: false,
+        functional_analysis: false,
+        ..data.search_options()
+    };
+    let search_result = analyse_all_peptides(&searcher, &data.peptides, data.cutoff, &options);
+
+    Ok(Json(taxonomic_profile(&searcher, &search_result)))
+}
+
+/// Endpoint that lists every taxon in the database together with its number of proteins
+///
+/// # Arguments
+/// * `state(searcher)` - The searcher object provided by the server
+///
+/// # Returns
+///
+/// Returns a JSON object that maps every taxon id to the number of proteins of that taxon
+async fn taxa(State(searcher): State<Arc<Searcher>>) -> Json<HashMap<usize, usize>> {
+    Json(searcher.proteins().taxon_histogram())
+}
+
+/// Endpoint that summarizes the loaded index
+///
+/// # Arguments
+/// * `state(searcher)` - The searcher object provided by the server
+///
+/// # Returns
+///
+/// Returns the number of proteins, residues and distinct taxa, the protein lengths and the suffix array size as a JSON
+async fn statistics(State(searcher): State<Arc<Searcher>>) -> Json<IndexStatistics> {
+    Json(searcher.statistics())
+}
+
+/// Endpoint that exposes the request counts, error counts, request latencies and index size for monitoring
+///
+/// # Arguments
+/// * `state(server_metrics)` - The metrics of the server
+///
+/// # Returns
+///
+/// Returns all the metrics in the Prometheus text format
+///
+/// # Errors
+///
+/// Returns `INTERNAL_SERVER_ERROR` if the metrics could not be encoded
+async fn metrics(State(server_metrics): State<Arc<ServerMetrics>>) -> Result<Response, StatusCode> {
+    let body = TextEncoder::new()
+        .encode_to_string(&server_metrics.registry.gather())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(([(CONTENT_TYPE, TEXT_FORMAT)], body).into_response())
+}
 
-    Ok(Json(search_result))
+/// Middleware that counts every request and error of an endpoint and observes how long the endpoint took
+///
+/// # Arguments
+/// * `state(server_metrics)` - The metrics of the server
+/// * `request` - The request that is handled
+/// * `next` - The endpoint (and the other layers) that handle the request
+///
+/// # Returns
+///
+/// Returns the response of the endpoint
+async fn record_metrics(State(server_metrics): State<Arc<ServerMetrics>>, request: Request, next: Next) -> Response {
+    // use the route instead of the path, so every protein accession does not get its own label
+    let endpoint = match request.extensions().get::<MatchedPath>() {
+        Some(matched_path) => matched_path.as_str().to_string(),
+        None => request.uri().path().to_string(),
+    };
+
+    let timer = server_metrics.request_duration.with_label_values(&[&endpoint]).start_timer();
+    let response = next.run(request).await;
+    timer.observe_duration();
+
+    server_metrics.requests.with_label_values(&[&endpoint]).inc();
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        server_metrics.errors.with_label_values(&[&endpoint, status.as_str()]).inc();
+    }
+
+    response
+}
+
+/// Endpoint that describes the accepted input and returned output of the other endpoints
+///
+/// # Returns
+///
+/// Returns the JSON Schema of the input data and of the output of the `/analyse`, `/analyse_with_stats`, `/search` and `/taxa_profile` endpoints
+async fn schema() -> Json<Value> {
+    Json(json!({
+        "input": schema_for!(InputData),
+        "analyse": schema_for!(OutputData<SearchResultWithAnalysis>),
+        "analyse_with_stats": schema_for!(OutputDataWithStats<SearchResultWithAnalysis>),
+        "search": schema_for!(OutputData<SearchOnlyResult>),
+        "taxa_profile": schema_for!(TaxonomicProfile),
+    }))
 }
 
 /// Starts the server with the provided commandline arguments
@@ -138,47 +502,327 @@ async fn start_server(args: Arguments) -> Result<(), Box<dyn Error>> {
         database_file,
         index_file,
         taxonomy,
+        keep_taxa,
+        deduplicate,
+        exclude_fragments,
+        separator,
+        columns,
+        mapping_file,
+        ..
     } = args;
 
-    eprintln!("Loading suffix array...");
+    let database_schema = DatabaseSchema::try_new(separator, &columns)?;
+
+    info!("Loading suffix array...");
     let (sparseness_factor, sa) = load_suffix_array(&index_file)?;
 
-    eprintln!("Loading taxon file...");
+    info!("Loading taxon file...");
     let taxon_id_calculator =
         TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar)?;
 
     let function_aggregator = FunctionAggregator {};
 
-    eprintln!("Loading proteins...");
-    let proteins = Proteins::try_from_database_file(&database_file, &taxon_id_calculator)?;
-    let suffix_index_to_protein = Box::new(SparseSuffixToProtein::new(&proteins.input_string));
+    info!("Loading proteins...");
+    let keep_taxa: Option<HashSet<usize>> = keep_taxa.map(|taxa| taxa.into_iter().collect());
+    let load_options = LoadOptions {
+        keep_taxa: keep_taxa.as_ref(),
+        fragment_column: exclude_fragments,
+        schema: database_schema,
+        deduplicate,
+        ..Default::default()
+    };
+    let (proteins, _) = Proteins::try_from_database_file(&database_file, &taxon_id_calculator, &load_options)?;
+    let suffix_index_to_protein: Box<dyn SuffixToProteinIndex> = match mapping_file {
+        Some(mapping_file) => {
+            info!("Loading suffix-to-protein mapping...");
+            load_suffix_to_protein_mapping(&mapping_file, &proteins.input_string)?
+        }
+        None => Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+    };
+    let accession_index = Arc::new(AccessionIndex::new(&proteins));
 
-    eprintln!("Creating searcher...");
-    let searcher = Arc::new(Searcher::new(
+    info!("Creating searcher...");
+    let searcher = Arc::new(Searcher::try_new(
         sa,
         sparseness_factor,
         suffix_index_to_protein,
         proteins,
         taxon_id_calculator,
         function_aggregator,
-    ));
+    )?);
+
+    let server_metrics = Arc::new(ServerMetrics::try_new(&searcher.statistics())?);
+    let app = create_app(searcher, accession_index, server_metrics);
+
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+    info!("server is ready...");
+    axum::serve(listener, app).await?;
 
+    Ok(())
+}
+
+/// Creates the router with all the endpoints of the server
+///
+/// # Arguments
+/// * `searcher` - The searcher used by the endpoints
+/// * `accession_index` - The index used to look up proteins by their uniprot id
+/// * `server_metrics` - The metrics that are updated by every request and exposed by the `/metrics` endpoint
+///
+/// # Returns
+///
+/// Returns the router of the server
+fn create_app(searcher: Arc<Searcher>, accession_index: Arc<AccessionIndex>, server_metrics: Arc<ServerMetrics>) -> Router {
     // build our application with a route
-    let app = Router::new()
+    Router::new()
         // `GET /` goes to `root`
         .route("/", get(root))
+        // `GET /schema` goes to `schema`
+        .route("/schema", get(schema))
+        // `GET /taxa` goes to `taxa`
+        .route("/taxa", get(taxa))
+        // `GET /statistics` goes to `statistics`
+        .route("/statistics", get(statistics))
+        .with_state(searcher.clone())
+        // `GET /protein/{accession}` goes to `protein`
+        .route("/protein/:accession", get(protein))
+        .with_state(ProteinLookup { searcher: searcher.clone(), accession_index })
         // `POST /analyse` goes to `analyse` and set max payload size to 5 MB
         .route("/analyse", post(analyse))
         .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
         .with_state(searcher.clone())
+        // `POST /analyse_with_stats` goes to `analyse_with_stats` and set max payload size to 5 MB
+        .route("/analyse_with_stats", post(analyse_with_stats))
+        .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
+        .with_state(searcher.clone())
         // `POST /search` goes to `search` and set max payload size to 5 MB
         .route("/search", post(search))
         .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
-        .with_state(searcher);
+        .with_state(searcher.clone())
+        // `POST /taxa_profile` goes to `taxa_profile` and set max payload size to 5 MB
+        .route("/taxa_profile", post(taxa_profile))
+        .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
+        .with_state(searcher)
+        // record the metrics of all the routes above
+        .route_layer(middleware::from_fn_with_state(server_metrics.clone(), record_metrics))
+        // `GET /metrics` goes to `metrics`
+        .route("/metrics", get(metrics))
+        .with_state(server_metrics)
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    println!("server is ready...");
-    axum::serve(listener, app).await?;
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-    Ok(())
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{AccessionIndex, LoadOptions, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray::peptide_search::{OutputData, SearchResultWithAnalysis};
+    use suffixarray::sa_searcher::Searcher;
+    use suffixarray::suffix_to_protein_index::SparseSuffixToProtein;
+    use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
+
+    use axum::body::{Body, to_bytes};
+    use axum::extract::Request;
+    use axum::http::header::{ACCEPT, CONTENT_TYPE};
+    use axum::http::HeaderMap;
+    use axum::response::IntoResponse;
+    use axum::Json;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use crate::{analyse, create_app, protein, ProteinLookup, schema, search, ServerMetrics, statistics, taxa, taxa_profile, MSGPACK_MEDIA_TYPE};
+
+    fn get_protein_lookup() -> ProteinLookup {
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap();
+        let (proteins, _) = Proteins::try_from_database_file("../testfiles/small_database.tsv", &taxon_aggregator, &LoadOptions::default()).unwrap();
+        let sa = build_sa(&mut proteins.input_string.to_vec(), &SAConstructionAlgorithm::LibSais, 1).unwrap();
+        let accession_index = Arc::new(AccessionIndex::new(&proteins));
+
+        let searcher = Searcher::new(
+            sa,
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            taxon_aggregator,
+            FunctionAggregator {},
+        );
+
+        ProteinLookup { searcher: Arc::new(searcher), accession_index }
+    }
+
+    #[tokio::test]
+    async fn test_schema() {
+        let schema = schema().await.0;
+
+        // the schema should be valid JSON
+        let serialized = serde_json::to_string(&schema).unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&serialized).is_ok());
+
+        assert!(schema["input"]["properties"]["peptides"].is_object());
+        assert!(schema["analyse"]["properties"]["result"].is_object());
+        assert!(schema["analyse_with_stats"]["properties"]["match_count_histogram"].is_object());
+        assert!(schema["search"]["properties"]["result"].is_object());
+        assert!(schema["taxa_profile"]["properties"]["ranks"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_protein() {
+        let lookup = get_protein_lookup();
+
+        let record = protein(State(lookup.clone()), Path("P54321".to_string())).await.unwrap().0;
+        assert_eq!(record.uniprot_id, "P54321");
+        assert_eq!(record.taxon_id, 2);
+        assert_eq!(record.sequence, "PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG");
+        assert!(record.functional_annotations.is_empty());
+
+        let record = protein(State(lookup.clone()), Path("P67890".to_string())).await.unwrap().0;
+        assert_eq!(record.sequence, "KWDSDPSGTKTCIDT");
+
+        let error = protein(State(lookup), Path("Q00000".to_string())).await.unwrap_err();
+        assert_eq!(error, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_taxa() {
+        let lookup = get_protein_lookup();
+
+        let histogram = taxa(State(lookup.searcher)).await.0;
+        assert_eq!(histogram.len(), 3);
+        assert_eq!(histogram[&1], 1);
+        assert_eq!(histogram[&2], 1);
+        assert_eq!(histogram[&6], 1);
+    }
+
+    #[tokio::test]
+    async fn test_statistics() {
+        let lookup = get_protein_lookup();
+
+        let statistics = statistics(State(lookup.searcher)).await.0;
+        assert_eq!(statistics.protein_count, 3);
+        assert_eq!(statistics.distinct_taxa, 3);
+        assert_eq!(statistics.residue_count, 65);
+        assert_eq!(
+            (statistics.min_protein_length, statistics.median_protein_length, statistics.max_protein_length),
+            (15, 20, 30)
+        );
+        assert_eq!(statistics.suffix_array_length, 68);
+    }
+
+    #[tokio::test]
+    async fn test_taxa_profile() {
+        let lookup = get_protein_lookup();
+
+        // KWDSD and TCIDT only occur in P67890 (genus 6), PTDGN only occurs in P54321 (superkingdom 2)
+        let data = serde_json::from_value(json!({"peptides": ["KWDSD", "PTDGN", "TCIDT", "WWWWW"]})).unwrap();
+        let profile = taxa_profile(State(lookup.searcher), Json(data)).await.unwrap().0;
+
+        assert_eq!(profile.peptides_with_lca, 3);
+        assert_eq!(profile.ranks.len(), 2);
+        assert_eq!(profile.ranks["genus"], [(6, 2)].into_iter().collect());
+        assert_eq!(profile.ranks["superkingdom"], [(2, 1)].into_iter().collect());
+    }
+
+    #[tokio::test]
+    async fn test_search_max_proteins_per_peptide() {
+        let lookup = get_protein_lookup();
+
+        // G occurs 6 times in the 3 proteins, TCIDT only occurs once in P67890
+        let data = serde_json::from_value(json!({"peptides": ["G", "TCIDT"], "max_proteins_per_peptide": 2})).unwrap();
+        let output = search(State(lookup.searcher.clone()), HeaderMap::new(), Json(data)).await.unwrap().0;
+        let output = serde_json::to_value(&output).unwrap();
+
+        assert_eq!(output["result"][0]["proteins"].as_array().unwrap().len(), 2);
+        assert_eq!(output["result"][0]["truncated"], true);
+        assert_eq!(output["result"][1]["proteins"].as_array().unwrap().len(), 1);
+        assert_eq!(output["result"][1]["truncated"], false);
+
+        // without a limit every matched protein is returned
+        let data = serde_json::from_value(json!({"peptides": ["G"]})).unwrap();
+        let output = search(State(lookup.searcher), HeaderMap::new(), Json(data)).await.unwrap().0;
+        let output = serde_json::to_value(&output).unwrap();
+        assert_eq!(output["result"][0]["proteins"].as_array().unwrap().len(), 6);
+        assert_eq!(output["result"][0]["truncated"], false);
+    }
+
+    #[tokio::test]
+    async fn test_search_msgpack_response() {
+        let lookup = get_protein_lookup();
+        let data = json!({"peptides": ["G", "TCIDT", "WWWWW"], "include_sequences": true});
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, "application/json;q=0.5, application/x-msgpack".parse().unwrap());
+        let response = search(State(lookup.searcher.clone()), headers, Json(serde_json::from_value(data.clone()).unwrap()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], MSGPACK_MEDIA_TYPE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        // the MessagePack response decodes to the same output as the JSON response
+        let json_response = search(State(lookup.searcher), HeaderMap::new(), Json(serde_json::from_value(data).unwrap()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(json_response.headers()[CONTENT_TYPE], "application/json");
+        let json_body = axum::body::to_bytes(json_response.into_body(), usize::MAX).await.unwrap();
+
+        let decoded: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded, serde_json::from_slice::<serde_json::Value>(&json_body).unwrap());
+        assert_eq!(decoded["result"][1]["proteins"].as_array().unwrap().len(), 1);
+        assert!(body.len() < json_body.len());
+    }
+
+    #[tokio::test]
+    async fn test_analyse_msgpack_response() {
+        let lookup = get_protein_lookup();
+        let data = json!({"peptides": ["G", "TCIDT", "WWWWW"], "include_index": true});
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, MSGPACK_MEDIA_TYPE.parse().unwrap());
+        let response = analyse(State(lookup.searcher.clone()), headers, Json(serde_json::from_value(data.clone()).unwrap()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], MSGPACK_MEDIA_TYPE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        // the response decodes back into the output of the analysis, including the optional fields that are left out
+        let decoded: OutputData<SearchResultWithAnalysis> = rmp_serde::from_slice(&body).unwrap();
+        let expected = analyse(State(lookup.searcher), HeaderMap::new(), Json(serde_json::from_value(data).unwrap())).await.unwrap().0;
+        assert_eq!(decoded, expected);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let lookup = get_protein_lookup();
+        let server_metrics = Arc::new(ServerMetrics::try_new(&lookup.searcher.statistics()).unwrap());
+        let app = create_app(lookup.searcher, lookup.accession_index, server_metrics);
+
+        let scrape = |app: axum::Router| async move {
+            let response = app.oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap()
+        };
+
+        let body = scrape(app.clone()).await;
+        assert!(body.contains("suffixarray_server_index_proteins 3"));
+        assert!(!body.contains("suffixarray_server_requests_total{endpoint=\"/search\"}"));
+
+        let request = Request::post("/search")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"peptides": ["TCIDT"]}).to_string()))
+            .unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        let request = Request::get("/protein/Q00000").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        let body = scrape(app).await;
+        assert!(body.contains("suffixarray_server_requests_total{endpoint=\"/search\"} 1"));
+        assert!(body.contains("suffixarray_server_request_duration_seconds_count{endpoint=\"/search\"} 1"));
+        // the route is used as label instead of the requested accession
+        assert!(body.contains("suffixarray_server_request_errors_total{endpoint=\"/protein/:accession\",status=\"404\"} 1"));
+        assert!(!body.contains("suffixarray_server_request_errors_total{endpoint=\"/search\""));
+    }
 }
@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::io;
-use std::io::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{BufRead, Write};
+use std::time::Instant;
 
 use clap::{arg, Parser, ValueEnum};
 
@@ -11,6 +11,7 @@ use crate::searcher::Searcher;
 use crate::tree_taxon_id_calculator::TreeTaxonIdCalculator;
 use crate::tree::Tree;
 use crate::tree_builder::{TreeBuilder, UkkonenBuilder};
+use crate::verbose_record::{DEFAULT_SEPARATOR, VerboseRecord};
 
 mod tree_builder;
 mod tree;
@@ -18,22 +19,27 @@ mod cursor;
 mod search_cursor;
 mod searcher;
 mod tree_taxon_id_calculator;
+mod verbose_record;
 
 
 /// Enum that represents the 2 kinds of search that we support
 /// - Search until match and return boolean that indicates if there is a match
 /// - Search until match, if there is a match search the whole subtree to find all matching proteins
 /// - Search until match, there we can immediately retrieve the taxonId that represents all the children
+/// - Search until match, if there is a match search the whole subtree to find the position of every match in its protein
+/// - Search until match, if there is a match count the matches in the subtree without collecting them
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 pub enum SearchMode {
     Match,
     AllOccurrences,
     TaxonId,
+    Positions,
+    Count,
 }
 
 #[derive(Parser, Debug)]
 pub struct Arguments {
-    /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
+    /// File with the proteins used to build the suffix tree. The proteins are concatenated using the `-` separation character.
     #[arg(short, long)]
     database_file: String,
     /// A file that contains sequences that we want to search in the tree. Every line contains a new sequence.
@@ -45,12 +51,24 @@ pub struct Arguments {
     /// `match` will only look if there is match.
     /// While `all-occurrences` will search for the match and look for all the different matches in the subtree.
     /// `Taxon-id` will search for the matching taxon id using lca*
+    /// `positions` will search for all the matches and output the protein and the offset in that protein of every match
+    /// `count` will only output the number of matches
     #[arg(short, long, value_enum)]
     mode: Option<SearchMode>,
     /// This will change the output to <found (0 or 1)>;<protein length>;<search time in ms>
     /// The given num will be used to run the search x times and the average of these x runs will be given as search time
     #[arg(short, long)]
     verbose: Option<u8>,
+    /// The separator between the fields of the verbose output, e.g. `,` for CSV or `$'\t'` (in bash) for TSV
+    #[arg(long, default_value = DEFAULT_SEPARATOR)]
+    verbose_separator: String,
+    /// The number of decimals of the search time in the verbose output, by default the search time is not rounded
+    #[arg(long)]
+    time_precision: Option<usize>,
+    /// The search words are already uppercase, so they are not uppercased again before searching.
+    /// The first words of the search file are checked and the program stops if they contain lowercase characters
+    #[arg(long)]
+    assume_uppercase: bool,
     #[arg(short, long)]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
     taxonomy: String,
@@ -61,41 +79,53 @@ pub struct Arguments {
 
 
 
-fn time_execution(searcher: &mut Searcher, f: &dyn Fn(&mut Searcher) -> bool) -> (bool, f64) {
-    let start_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards").as_nanos() as f64 * 1e-6;
+/// The number of words at the start of the search file that are checked to be uppercase when `--assume-uppercase` is set
+const UPPERCASE_SAMPLE_SIZE: usize = 1000;
+
+/// Times how long the function `f` takes, using a monotonic clock so the measured time can never be negative
+fn time_execution(searcher: &Searcher, f: &dyn Fn(&Searcher) -> bool) -> (bool, f64) {
+    let start = Instant::now();
     let found = f(searcher);
-    let end_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards").as_nanos() as f64 * 1e-6;
-    (found, end_ms - start_ms, )
+    (found, start.elapsed().as_secs_f64() * 1e3)
 }
 
 
+/// The commandline arguments that determine how every search word is searched and how the result is printed
+struct SearchWordOptions<'a> {
+    search_mode: &'a SearchMode,
+    verbose: Option<u8>,
+    verbose_separator: &'a str,
+    time_precision: Option<usize>,
+    assume_uppercase: bool,
+}
+
 /// Executes the kind of search indicated by the commandline arguments
-fn handle_search_word(searcher: &mut Searcher, proteins: &Proteins, word: String, search_mode: &SearchMode, verbose: Option<u8>, verbose_output: &mut Vec<String>) {
+fn handle_search_word(searcher: &Searcher, proteins: &Proteins, word: String, options: &SearchWordOptions, verbose_output: &mut Vec<String>) {
     let word = match word.strip_suffix('\n') {
         None => word,
         Some(stripped) => String::from(stripped)
-    }.to_uppercase();
-    if let Some(num_iter) = verbose {
+    };
+    let word = if options.assume_uppercase { word } else { word.to_uppercase() };
+    if let Some(num_iter) = options.verbose {
         let mut found_total: bool = false;
         let mut total_time: f64 = 0.0;
         for _ in 0..num_iter {
-            let (found, execution_time) = match *search_mode {
+            let (found, execution_time) = match *options.search_mode {
                 SearchMode::Match => time_execution(searcher, &|searcher| searcher.search_if_match(word.as_bytes())),
                 SearchMode::AllOccurrences => time_execution(searcher, &|searcher| !searcher.find_all_suffix_indices(word.as_bytes()).is_empty()),
                 SearchMode::TaxonId => time_execution(searcher, &|searcher| searcher.search_taxon_id(word.as_bytes()).is_some()),
+                SearchMode::Positions => time_execution(searcher, &|searcher| !searcher.find_all_positions(word.as_bytes()).is_empty()),
+                SearchMode::Count => time_execution(searcher, &|searcher| searcher.subtree_suffix_count(word.as_bytes()) > 0),
             };
             total_time += execution_time;
             found_total = found;
         }
         let avg = total_time / (num_iter as f64);
 
-        verbose_output.push(format!("{};{};{}", found_total as u8, word.len(), avg));
+        let record = VerboseRecord { found: found_total, length: word.len(), avg_time_ms: avg };
+        verbose_output.push(record.format(options.verbose_separator, options.time_precision));
     } else {
-        match *search_mode {
+        match *options.search_mode {
             SearchMode::Match => println!("{}", searcher.search_if_match(word.as_bytes())),
             SearchMode::AllOccurrences => {
                 let results = searcher.search_protein(word.as_bytes());
@@ -109,6 +139,13 @@ fn handle_search_word(searcher: &mut Searcher, proteins: &Proteins, word: String
                     None => println!("/"),
                 }
             }
+            SearchMode::Positions => {
+                let positions = searcher.find_all_positions(word.as_bytes());
+                println!("found {} matches", positions.len());
+                positions.into_iter()
+                    .for_each(|(protein_index, offset)| println!("* {}\t{}", proteins.proteins[protein_index].uniprot_id, offset));
+            }
+            SearchMode::Count => println!("{}", searcher.subtree_suffix_count(word.as_bytes())),
         }
     }
 }
@@ -123,7 +160,7 @@ pub fn run(args: Arguments) -> Result<(), Box<dyn Error>> {
     let data = &proteins.input_string;
 
     // build the tree
-    let mut tree = Tree::new(data, UkkonenBuilder::new());
+    let mut tree = Tree::new(data, UkkonenBuilder::new())?;
     // fill in the Taxon Ids in the tree using the LCA implementations from UMGAP
     tree_taxon_id_calculator.calculate_taxon_ids(&mut tree, &proteins.proteins);
 
@@ -147,31 +184,70 @@ pub fn run(args: Arguments) -> Result<(), Box<dyn Error>> {
 }
 
 /// Perform the search as set with the commandline arguments
-fn execute_search(mut searcher: Searcher, proteins: &Proteins, args: &Arguments) {
-    let mode = args.mode.as_ref().unwrap();
-    let verbose = args.verbose;
+fn execute_search(searcher: Searcher, proteins: &Proteins, args: &Arguments) {
+    let options = SearchWordOptions {
+        search_mode: args.mode.as_ref().unwrap(),
+        verbose: args.verbose,
+        verbose_separator: &args.verbose_separator,
+        time_precision: args.time_precision,
+        assume_uppercase: args.assume_uppercase,
+    };
     let mut verbose_output: Vec<String> = vec![];
     if let Some(search_file) = &args.search_file {
         // File `search_file` must exist in the current path
         if let Ok(lines) = read_lines(search_file) {
-            for line in lines.into_iter().map_while(Result::ok) {
-                handle_search_word(&mut searcher, proteins, line, mode, verbose, &mut verbose_output);
+            for (i, line) in lines.into_iter().map_while(Result::ok).enumerate() {
+                if args.assume_uppercase && i < UPPERCASE_SAMPLE_SIZE && line.bytes().any(|c| c.is_ascii_lowercase()) {
+                    eprintln!("Search word {} contains lowercase characters, but the search words are assumed to be uppercase", line);
+                    std::process::exit(1);
+                }
+                handle_search_word(&searcher, proteins, line, &options, &mut verbose_output);
             }
         } else {
             eprintln!("File {} could not be opened!", search_file);
             std::process::exit(1);
         }
     } else {
-        loop {
-            print!("Input your search string: ");
-            io::stdout().flush().unwrap();
-            let mut word = String::new();
+        read_search_words(io::stdin().lock(), |word| {
+            handle_search_word(&searcher, proteins, word, &options, &mut verbose_output);
+        });
+    }
+    verbose_output.iter().for_each(|val| println!("{}", val));
+}
 
-            if io::stdin().read_line(&mut word).is_err() {
-                continue;
-            }
-            handle_search_word(&mut searcher, proteins, word, mode, verbose, &mut verbose_output);
+/// Interactively reads search words from `input` until the end of the input is reached
+///
+/// # Arguments
+/// * `input` - The input from which the search words are read, one word per line
+/// * `handle_word` - Called with every search word that is read, including its line ending
+fn read_search_words(mut input: impl BufRead, mut handle_word: impl FnMut(String)) {
+    loop {
+        print!("Input your search string: ");
+        io::stdout().flush().unwrap();
+        let mut word = String::new();
+
+        match input.read_line(&mut word) {
+            // the end of the input (Ctrl-D or a closed pipe) is reached, reading again would return immediately forever
+            Ok(0) => break,
+            Ok(_) => handle_word(word),
+            Err(_) => continue,
         }
     }
-    verbose_output.iter().for_each(|val| println!("{}", val));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::read_search_words;
+
+    #[test]
+    fn test_read_search_words_stops_at_end_of_input() {
+        // an empty or closed input terminates the loop without handling a word
+        read_search_words(io::empty(), |word| panic!("unexpected search word {}", word));
+
+        let mut words = vec![];
+        read_search_words("AC\nKCRLZ\nBLA".as_bytes(), |word| words.push(word));
+        assert_eq!(words, vec!["AC\n", "KCRLZ\n", "BLA"]);
+    }
 }
\ No newline at end of file
@@ -38,9 +38,4 @@ impl<'a> SearchCursor<'a> {
 
         None
     }
-
-    pub fn reset(&mut self) {
-        self.index = 0;
-        self.current_node = &self.tree.arena[0];
-    }
 }
\ No newline at end of file
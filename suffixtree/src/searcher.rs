@@ -4,8 +4,10 @@ use crate::search_cursor::SearchCursor;
 use crate::tree_taxon_id_calculator::TreeTaxonIdCalculator;
 use crate::tree::{Node, Nullable, Tree};
 
+/// Searches the tree without mutating it, every search uses its own `SearchCursor`
+/// The searcher can therefore be shared between threads to search multiple words in parallel
 pub struct Searcher<'a> {
-    cursor: SearchCursor<'a>,
+    tree: &'a Tree,
     original_input_string: &'a [u8],
     proteins: &'a Vec<Protein>,
     taxon_id_calculator: &'a TreeTaxonIdCalculator, // used to snap taxon_id
@@ -15,7 +17,7 @@ pub struct Searcher<'a> {
 impl<'a> Searcher<'a> {
     pub fn new(tree: &'a Tree, original_input_string: &'a [u8], proteins: &'a Vec<Protein>, taxon_id_calculator: &'a TreeTaxonIdCalculator) -> Self {
         Self {
-            cursor: SearchCursor::new(tree),
+            tree,
             original_input_string,
             proteins,
             taxon_id_calculator,
@@ -24,30 +26,27 @@ impl<'a> Searcher<'a> {
 
     /// Return true as first value of the tuple if we have a valid match until the end
     /// the second value of the tuple is the index of the last current node in the arena during search
-    fn find_end_node(&mut self, search_string: &[u8]) -> (bool, &'a Node) {
+    fn find_end_node(&self, search_string: &[u8]) -> (bool, &'a Node) {
         if search_string.is_empty() {
-            return (true, &self.cursor.tree.arena[0]);
+            return (true, &self.tree.arena[0]);
         }
         let string_length = search_string.len();
         let mut index_in_string: usize = 0;
+        // the cursor starts at the root, so it never has to be reset after a search
+        let mut cursor = SearchCursor::new(self.tree);
 
-        while self.cursor.next(search_string[index_in_string], self.original_input_string).is_some() {
+        while cursor.next(search_string[index_in_string], self.original_input_string).is_some() {
             index_in_string += 1;
             if index_in_string == string_length {
-                let end_node = self.cursor.current_node;
-                self.cursor.reset(); // prepare cursor for next search
-                return (true, end_node);
+                return (true, cursor.current_node);
             }
         }
 
-        let end_node = self.cursor.current_node;
-        self.cursor.reset(); // prepare cursor for next search
-
-        (false, end_node)
+        (false, cursor.current_node)
     }
 
 
-    pub fn search_protein(&mut self, search_string: &[u8]) -> Vec<&Protein> {
+    pub fn search_protein(&self, search_string: &[u8]) -> Vec<&Protein> {
         let suffix_indices_list = self.find_all_suffix_indices(search_string);
 
         let mut solutions_list: Vec<&Protein> = vec![];
@@ -57,7 +56,7 @@ impl<'a> Searcher<'a> {
         solutions_list
     }
 
-    pub fn find_all_suffix_indices(&mut self, search_string: &[u8]) -> Vec<usize> {
+    pub fn find_all_suffix_indices(&self, search_string: &[u8]) -> Vec<usize> {
         let (match_found, end_node) = self.find_end_node(search_string);
         if !match_found {
             return vec![];
@@ -70,7 +69,7 @@ impl<'a> Searcher<'a> {
             } else {
                 current_node.children.iter().for_each(|&child| {
                     if !child.is_null() {
-                        stack.push(&self.cursor.tree.arena[child]);
+                        stack.push(&self.tree.arena[child]);
                     }
                 });
             }
@@ -78,11 +77,71 @@ impl<'a> Searcher<'a> {
         suffix_indices_list
     }
 
-    pub fn search_if_match(&mut self, search_string: &[u8]) -> bool {
+    /// Counts the leaves under the match of `search_string` without collecting them
+    /// This uses the same explicit stack as `find_all_suffix_indices`, so it also works for arbitrarily deep trees
+    pub fn subtree_suffix_count(&self, search_string: &[u8]) -> usize {
+        let (match_found, end_node) = self.find_end_node(search_string);
+        if !match_found {
+            return 0;
+        }
+        let mut count: usize = 0;
+        let mut stack = vec![end_node];
+        while let Some(current_node) = stack.pop() {
+            if !current_node.suffix_index.is_null() {
+                count += 1;
+            } else {
+                current_node.children.iter().for_each(|&child| {
+                    if !child.is_null() {
+                        stack.push(&self.tree.arena[child]);
+                    }
+                });
+            }
+        }
+        count
+    }
+
+    /// Finds all the positions where `search_string` occurs in the proteins
+    /// Every position is a tuple of the index of the protein and the offset of the match in that protein.
+    /// The offset is calculated from the depth of the leaf, since every leaf ends at the end of the concatenated proteins.
+    pub fn find_all_positions(&self, search_string: &[u8]) -> Vec<(usize, usize)> {
+        let (match_found, end_node) = self.find_end_node(search_string);
+        if !match_found {
+            return vec![];
+        }
+
+        // the depth of the end node is the length of the path from the root to the end of its edge
+        let mut end_node_depth = 0;
+        let mut current_node = end_node;
+        while !current_node.parent.is_null() {
+            end_node_depth += current_node.range.length();
+            current_node = &self.tree.arena[current_node.parent];
+        }
+
+        let mut positions: Vec<(usize, usize)> = vec![];
+        let mut stack = vec![(end_node, end_node_depth)];
+        while let Some((current_node, depth)) = stack.pop() {
+            if !current_node.suffix_index.is_null() {
+                let protein_index = current_node.suffix_index;
+                let suffix_start = current_node.range.end - depth;
+                positions.push((protein_index, suffix_start - self.proteins[protein_index].sequence.0));
+            } else {
+                current_node.children.iter().for_each(|&child| {
+                    if !child.is_null() {
+                        let child_node = &self.tree.arena[child];
+                        stack.push((child_node, depth + child_node.range.length()));
+                    }
+                });
+            }
+        }
+        positions.sort();
+        positions
+    }
+
+    pub fn search_if_match(&self, search_string: &[u8]) -> bool {
         self.find_end_node(search_string).0
     }
 
-    pub fn search_taxon_id(&mut self, search_string: &[u8]) -> Option<TaxonId> {
+    pub fn search_taxon_id(&self, search_string: &[u8]) -> Option<TaxonId> {
         let (match_found, end_node) = self.find_end_node(search_string);
         if match_found {
             Some(self.taxon_id_calculator.snap_taxon_id(end_node.taxon_id))
@@ -90,4 +149,78 @@ impl<'a> Searcher<'a> {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::Protein;
+    use crate::searcher::Searcher;
+    use crate::tree::Tree;
+    use crate::tree_builder::{TreeBuilder, UkkonenBuilder};
+    use crate::tree_taxon_id_calculator::TreeTaxonIdCalculator;
+
+    fn get_example_data() -> (Vec<u8>, Vec<Protein>, Tree, TreeTaxonIdCalculator) {
+        let input = "ACACACGT-CACA$".as_bytes().to_vec();
+        let proteins = vec![
+            Protein {
+                uniprot_id: "P1".to_string(),
+                sequence: (0, 8),
+                id: 2,
+            },
+            Protein {
+                uniprot_id: "P2".to_string(),
+                sequence: (9, 4),
+                id: 6,
+            },
+        ];
+        let tree = Tree::new(&input, UkkonenBuilder::new()).unwrap();
+        let taxon_id_calculator = TreeTaxonIdCalculator::new("../testfiles/small_taxonomy.tsv");
+        (input, proteins, tree, taxon_id_calculator)
+    }
+
+    #[test]
+    fn test_find_all_positions() {
+        let (input, proteins, tree, taxon_id_calculator) = get_example_data();
+        let searcher = Searcher::new(&tree, &input, &proteins, &taxon_id_calculator);
+
+        assert_eq!(searcher.find_all_positions("AC".as_bytes()), vec![(0, 0), (0, 2), (0, 4), (1, 1)]);
+        assert_eq!(searcher.find_all_positions("ACG".as_bytes()), vec![(0, 4)]);
+        assert_eq!(searcher.find_all_positions("GTC".as_bytes()), vec![]);
+    }
+
+    #[test]
+    fn test_subtree_suffix_count() {
+        let (input, proteins, tree, taxon_id_calculator) = get_example_data();
+        let searcher = Searcher::new(&tree, &input, &proteins, &taxon_id_calculator);
+
+        for search_string in ["A", "AC", "CA", "ACG", "T", "GTC"] {
+            assert_eq!(
+                searcher.subtree_suffix_count(search_string.as_bytes()),
+                searcher.find_all_suffix_indices(search_string.as_bytes()).len()
+            );
+        }
+        assert_eq!(searcher.subtree_suffix_count("AC".as_bytes()), 4);
+    }
+
+    #[test]
+    fn test_concurrent_searches() {
+        let (input, proteins, tree, taxon_id_calculator) = get_example_data();
+        let searcher = Searcher::new(&tree, &input, &proteins, &taxon_id_calculator);
+
+        // both threads share the same searcher, every search uses its own cursor
+        let (positions, counts) = std::thread::scope(|scope| {
+            let positions = scope.spawn(|| {
+                (0..100).map(|_| searcher.find_all_positions("AC".as_bytes())).collect::<Vec<_>>()
+            });
+            let counts = scope.spawn(|| {
+                (0..100).map(|_| searcher.subtree_suffix_count("CA".as_bytes())).collect::<Vec<_>>()
+            });
+            (positions.join().unwrap(), counts.join().unwrap())
+        });
+
+        assert!(positions.iter().all(|positions| positions == &vec![(0, 0), (0, 2), (0, 4), (1, 1)]));
+        assert!(counts.iter().all(|&count| count == searcher.find_all_suffix_indices("CA".as_bytes()).len()));
+        assert!(!searcher.search_if_match("GTC".as_bytes()));
+    }
+}
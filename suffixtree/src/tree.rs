@@ -1,8 +1,15 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use tsv_utils::AminoAcidAlphabet;
 use umgap::taxon::TaxonId;
-use tsv_utils::{END_CHARACTER, SEPARATION_CHARACTER};
 use crate::tree_builder::TreeBuilder;
 
-pub const MAX_CHILDREN: usize = 28;
+/// All the characters supported by the suffix tree: the uppercase residues, the SEPARATION_CHARACTER and the END_CHARACTER
+/// The position of a character in this alphabet is the index of its child in the children array of a node
+pub const ALPHABET: &[u8] = AminoAcidAlphabet::SYMBOLS;
+
+pub const MAX_CHILDREN: usize = ALPHABET.len();
 
 /// Custom trait implemented by types that have a value that represents NULL
 pub trait Nullable<T> {
@@ -26,19 +33,49 @@ impl Nullable<NodeIndex> for NodeIndex {
 }
 
 
+/// The reasons why a `Tree` could not be built over a text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeError {
+    /// The text contains a character that is not part of the `ALPHABET` supported by the tree (e.g. `*`)
+    UnsupportedCharacter { character: u8, position: usize },
+}
+
+impl Display for TreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::UnsupportedCharacter { character, position } => write!(
+                f,
+                "the character {:?} at position {} is not supported in the suffix tree",
+                *character as char, position
+            ),
+        }
+    }
+}
+
+impl Error for TreeError {}
+
 #[derive(Debug, PartialEq)]
 pub struct Tree {
     pub arena: Vec<Node>,
 }
 
 impl Tree {
-    pub fn new(data: &Vec<u8>, builder: impl TreeBuilder) -> Self {
-        builder.build(
+    /// Builds the suffix tree over `data`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TreeError::UnsupportedCharacter` for the first character of `data` that is not part of the `ALPHABET`
+    pub fn new(data: &Vec<u8>, builder: impl TreeBuilder) -> Result<Self, TreeError> {
+        if let Some(position) = data.iter().position(|&character| !AminoAcidAlphabet::is_valid(character)) {
+            return Err(TreeError::UnsupportedCharacter { character: data[position], position });
+        }
+
+        Ok(builder.build(
             data,
             Tree {
                 arena: vec![Node::create_root()],
             },
-        )
+        ))
     }
 }
 
@@ -89,24 +126,27 @@ impl Node {
         node
     }
 
-    fn char_to_child_index(character: u8) -> usize {
-        if character == SEPARATION_CHARACTER {
-            26
-        } else if character == END_CHARACTER {
-            27
-        } else {
-            character as usize - 65 // 65 is 'A' in ascii
-        }
+    /// Returns the index in the children array for `character`
+    /// Returns None if the character is not part of the `ALPHABET` supported by the tree
+    fn char_to_child_index(character: u8) -> Option<usize> {
+        AminoAcidAlphabet::index(character)
     }
 
+    /// Adds `child` as the child for `character`
+    /// Panics if `character` is not supported by the tree, `Tree::new` rejects such characters before building the tree
     pub fn add_child(&mut self, character: u8, child: NodeIndex) {
-        self.children[Self::char_to_child_index(character)] = child;
+        let index = Self::char_to_child_index(character)
+            .unwrap_or_else(|| panic!("Character {:?} is not supported in the suffix tree", character as char));
+        self.children[index] = child;
     }
 
-    // TODO: mogelijks rekening houden met feit dat er tekens ingeput kunnen worden die niet in de array zitten?
-    //  (bv ?*^), dit gaat er nu voor zorgen dat alles crasht als we zo'n teken mee geven
+    /// Returns the child for `character`, or NULL if there is no such child
+    /// Characters that are not supported by the tree (e.g. `*`) can never have a child, so NULL is returned for them
     pub fn get_child(&self, character: u8) -> NodeIndex {
-        self.children[Self::char_to_child_index(character)]
+        match Self::char_to_child_index(character) {
+            Some(index) => self.children[index],
+            None => NodeIndex::NULL,
+        }
     }
 
     pub fn set_new_children(&mut self, new_children: Vec<(u8, NodeIndex)>) {
@@ -132,14 +172,16 @@ impl Range {
 
 #[cfg(test)]
 mod tests {
-    use crate::tree::{MAX_CHILDREN, Node, NodeIndex, Nullable, Range, Tree};
+    use tsv_utils::{AminoAcidAlphabet, END_CHARACTER, SEPARATION_CHARACTER};
+
+    use crate::tree::{ALPHABET, MAX_CHILDREN, Node, NodeIndex, Nullable, Range, Tree, TreeError};
     use crate::tree_builder::{TreeBuilder, UkkonenBuilder};
 
     #[test]
     fn total_test() {
         let input = "ACACACGT$".as_bytes().to_vec();
 
-        let tree = Tree::new(&input, UkkonenBuilder::new());
+        let tree = Tree::new(&input, UkkonenBuilder::new()).unwrap();
         let mut control_tree = Tree { arena: vec![] };
         for _ in 0..14 {
             control_tree.arena.push(Node::new(
@@ -218,4 +260,61 @@ mod tests {
 
         assert_eq!(tree, control_tree);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_child_unsupported_character() {
+        let mut root = Node::create_root();
+        root.add_child(b'U', 1);
+        root.add_child(b'O', 2);
+
+        assert_eq!(root.get_child(b'U'), 1);
+        assert_eq!(root.get_child(b'O'), 2);
+        // characters outside the alphabet should not panic, but simply have no child
+        assert!(root.get_child(b'*').is_null());
+        assert!(root.get_child(b'a').is_null());
+    }
+
+    #[test]
+    fn test_alphabet_contains_separators() {
+        assert!(ALPHABET.contains(&SEPARATION_CHARACTER));
+        assert!(ALPHABET.contains(&END_CHARACTER));
+        assert_eq!(MAX_CHILDREN, 28);
+    }
+
+    #[test]
+    fn test_child_index_agrees_with_alphabet() {
+        for character in 0..=u8::MAX {
+            assert_eq!(Node::char_to_child_index(character).is_some(), AminoAcidAlphabet::is_valid(character));
+        }
+    }
+
+    #[test]
+    fn test_tree_with_other_separator() {
+        // a text concatenated with another separation character is rejected instead of silently giving wrong results
+        let input = "ACG#CAG$".as_bytes().to_vec();
+        assert_eq!(
+            Tree::new(&input, UkkonenBuilder::new()),
+            Err(TreeError::UnsupportedCharacter { character: b'#', position: 3 })
+        );
+    }
+
+    #[test]
+    fn test_tree_with_stop_codon() {
+        // translated stop codons are not part of the alphabet, this is reported instead of aborting the construction
+        let input = "MKT*-AC*G$".as_bytes().to_vec();
+        let error = Tree::new(&input, UkkonenBuilder::new()).unwrap_err();
+        assert_eq!(error, TreeError::UnsupportedCharacter { character: b'*', position: 3 });
+        assert_eq!(error.to_string(), "the character '*' at position 3 is not supported in the suffix tree");
+    }
+
+    #[test]
+    fn test_tree_with_selenocysteine() {
+        let input = "MUCU-AUG$".as_bytes().to_vec();
+
+        let tree = Tree::new(&input, UkkonenBuilder::new()).unwrap();
+
+        let child = tree.arena[0].get_child(b'U');
+        assert!(!child.is_null());
+        assert_eq!(input[tree.arena[child].range.start], b'U');
+    }
+}
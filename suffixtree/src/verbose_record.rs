@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The separator between the fields of the verbose output when no other separator is configured
+pub const DEFAULT_SEPARATOR: &str = ";";
+
+/// A single line of the verbose benchmark output, formatted as `{found};{length};{avg_time_ms}`
+/// where `found` is written as 0 or 1, the `;` can be replaced by another separator with `format`
+/// The precision of the formatter (e.g. `{:.3}`) is used as the number of decimals of `avg_time_ms`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerboseRecord {
+    pub found: bool,
+    pub length: usize,
+    pub avg_time_ms: f64,
+}
+
+impl VerboseRecord {
+    /// Formats the record as a line of the verbose output
+    ///
+    /// # Arguments
+    /// * `separator` - The separator between the fields, e.g. `,` or a tab for tools that expect CSV or TSV
+    /// * `precision` - The number of decimals of `avg_time_ms`, None if the time is not rounded
+    ///
+    /// # Returns
+    ///
+    /// Returns the formatted line, without a newline
+    pub fn format(&self, separator: &str, precision: Option<usize>) -> String {
+        match precision {
+            Some(precision) => format!("{}{separator}{}{separator}{:.*}", self.found as u8, self.length, precision, self.avg_time_ms),
+            None => format!("{}{separator}{}{separator}{}", self.found as u8, self.length, self.avg_time_ms),
+        }
+    }
+
+    /// Parses a line of the verbose output with the given separator back into a `VerboseRecord`
+    ///
+    /// # Arguments
+    /// * `s` - The line of the verbose output
+    /// * `separator` - The separator between the fields
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed record
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line does not consist of exactly 3 fields separated by `separator`,
+    /// or if one of the fields can not be parsed
+    pub fn parse(s: &str, separator: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = s.trim_end().split(separator).collect();
+        let [found, length, avg_time_ms] = fields[..] else {
+            return Err(format!("Expected 3 fields in verbose record, found {}: {}", fields.len(), s).into());
+        };
+
+        let found = match found {
+            "0" => false,
+            "1" => true,
+            _ => return Err(format!("Invalid found value in verbose record: {}", found).into()),
+        };
+
+        Ok(Self {
+            found,
+            length: length.parse()?,
+            avg_time_ms: avg_time_ms.parse()?,
+        })
+    }
+}
+
+impl Display for VerboseRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format(DEFAULT_SEPARATOR, f.precision()))
+    }
+}
+
+impl FromStr for VerboseRecord {
+    type Err = Box<dyn Error>;
+
+    /// Parses a line of the verbose output with the default separator back into a `VerboseRecord`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line does not consist of exactly 3 fields separated by `;`,
+    /// or if one of the fields can not be parsed
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, DEFAULT_SEPARATOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::verbose_record::{DEFAULT_SEPARATOR, VerboseRecord};
+
+    #[test]
+    fn test_round_trip() {
+        let record = VerboseRecord { found: true, length: 12, avg_time_ms: 0.0123456 };
+
+        let formatted = record.to_string();
+        assert_eq!(formatted, "1;12;0.0123456");
+        assert_eq!(formatted.parse::<VerboseRecord>().unwrap(), record);
+
+        let record = VerboseRecord { found: false, length: 0, avg_time_ms: 3.0 };
+        assert_eq!(record.to_string().parse::<VerboseRecord>().unwrap(), record);
+    }
+
+    #[test]
+    fn test_precision() {
+        let record = VerboseRecord { found: true, length: 12, avg_time_ms: 0.0123456789 };
+
+        assert_eq!(format!("{:.3}", record), "1;12;0.012");
+        assert_eq!(format!("{:.0}", record), "1;12;0");
+        assert_eq!(format!("{:.*}", 5, record).parse::<VerboseRecord>().unwrap().avg_time_ms, 0.01235);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("1;12".parse::<VerboseRecord>().is_err());
+        assert!("2;12;0.5".parse::<VerboseRecord>().is_err());
+        assert!("1;-3;0.5".parse::<VerboseRecord>().is_err());
+        assert!("1;12;fast".parse::<VerboseRecord>().is_err());
+    }
+
+    #[test]
+    fn test_separator() {
+        let record = VerboseRecord { found: true, length: 12, avg_time_ms: 0.0123456789 };
+
+        assert_eq!(record.format(",", None), "1,12,0.0123456789");
+        assert_eq!(record.format("\t", Some(3)), "1\t12\t0.012");
+        assert_eq!(record.format(DEFAULT_SEPARATOR, Some(3)), format!("{:.3}", record));
+        assert_eq!(VerboseRecord::parse(&record.format("\t", None), "\t").unwrap(), record);
+        assert!(VerboseRecord::parse(&record.format(",", None), ";").is_err());
+    }
+}
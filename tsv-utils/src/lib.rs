@@ -5,13 +5,16 @@ use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::path::Path;
+use sa_mappings::proteins::check_skip_ratio;
 use umgap::taxon::TaxonId;
 use crate::taxon_id_calculator::{TaxonIdVerifier};
 
+// The separation and end characters are the ones of `sa_mappings`, so the suffix tree and the suffix array
+// always concatenate the proteins in the same way and a text can be moved between both tools
 // END_CHARACTER should ALWAYS be lexicographically than SEPARATION_CHARACTER
 // otherwise the building of the suffix array will not happen correctly
-pub static SEPARATION_CHARACTER: u8 = b'-';
-pub static END_CHARACTER: u8 = b'$';
+pub use sa_mappings::proteins::{SEPARATION_CHARACTER, TERMINATION_CHARACTER as END_CHARACTER};
+pub use sa_mappings::alphabet::AminoAcidAlphabet;
 
 
 // The output is wrapped in a Result to allow matching on errors
@@ -45,14 +48,51 @@ pub struct Protein {
 
 /// Parse the given database tsv file into a Vector of Proteins with the data from the tsv file
 pub fn get_proteins_from_database_file(database_file: &str, taxon_id_calculator: &dyn TaxonIdVerifier) -> Result<Proteins, Box<dyn Error>> {
+    let (proteins, _) = parse_database_file(database_file, taxon_id_calculator, None)?;
+    Ok(proteins)
+}
+
+/// Parse the given database tsv file into a Vector of Proteins, skipping the malformed lines instead of returning an error
+/// Returns the proteins together with the number of skipped lines, or a `MalformedLinesError` if the ratio of skipped lines exceeds `max_skip_ratio`
+pub fn get_proteins_from_database_file_skip_malformed(
+    database_file: &str,
+    taxon_id_calculator: &dyn TaxonIdVerifier,
+    max_skip_ratio: f64
+) -> Result<(Proteins, usize), Box<dyn Error>> {
+    parse_database_file(database_file, taxon_id_calculator, Some(max_skip_ratio))
+}
+
+/// Parse a single line of the database tsv file into the uniprot accession, taxon id and sequence
+/// Any extra columns after the sequence are ignored, so the database can carry extra metadata
+fn parse_database_line(line: &str) -> Result<(String, TaxonId, String), Box<dyn Error>> {
+    let parts: Vec<String> = line.split('\t').take(3).map(str::to_string).collect();
+    let [uniprot_id, protein_id_str, protein_sequence]: [String; 3] = parts.try_into().map_err(|e| DatabaseFormatError{ error: e})?;
+    let protein_id_as_taxon_id = protein_id_str.parse::<TaxonId>()?;
+    Ok((uniprot_id, protein_id_as_taxon_id, protein_sequence))
+}
+
+/// Parse the database tsv file, if `max_skip_ratio` is set the malformed lines are skipped and counted instead of returning an error
+fn parse_database_file(
+    database_file: &str,
+    taxon_id_calculator: &dyn TaxonIdVerifier,
+    max_skip_ratio: Option<f64>
+) -> Result<(Proteins, usize), Box<dyn Error>> {
     let mut input_string: String = "".to_string();
     let mut proteins: Vec<Protein> = vec![];
     let mut begin_index: usize = 0;
+    let mut total_lines: usize = 0;
+    let mut skipped_lines: usize = 0;
     let lines = read_lines(database_file)?;
     for line in lines.into_iter().map_while(Result::ok) {
-        let parts: Vec<String> = line.split('\t').map(str::to_string).collect();
-        let [uniprot_id, protein_id_str, protein_sequence]: [String; 3] = parts.try_into().map_err(|e| DatabaseFormatError{ error: e})?;
-        let protein_id_as_taxon_id = protein_id_str.parse::<TaxonId>()?;
+        total_lines += 1;
+        let (uniprot_id, protein_id_as_taxon_id, protein_sequence) = match parse_database_line(&line) {
+            Ok(fields) => fields,
+            Err(_) if max_skip_ratio.is_some() => {
+                skipped_lines += 1;
+                continue;
+            }
+            Err(e) => return Err(e)
+        };
         // if the taxon ID is not a valid ID in our NCBI taxonomy, skip this protein
         if !taxon_id_calculator.taxon_id_exists(protein_id_as_taxon_id) {
             // eprintln!("Skipped protein with taxon id {}!", protein_id_as_taxon_id);
@@ -72,11 +112,14 @@ pub fn get_proteins_from_database_file(database_file: &str, taxon_id_calculator:
         );
         begin_index += protein_sequence.len() + 1;
     }
+    if let Some(max_skip_ratio) = max_skip_ratio {
+        check_skip_ratio(skipped_lines, total_lines, max_skip_ratio)?;
+    }
     input_string.push(END_CHARACTER as char);
-    Ok(Proteins {
+    Ok((Proteins {
         input_string: input_string.into_bytes(),
         proteins
-    })
+    }, skipped_lines))
 }
 
 #[derive(Debug)]
@@ -86,8 +129,72 @@ struct DatabaseFormatError {
 
 impl std::fmt::Display for DatabaseFormatError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Expected the protein database file to have the following fields separated by a tab: <Uniprot_accession> <protein id> <sequence>\nBut tried to unpack following vector in 3 variables: {:?}", self.error)
+        write!(f, "Expected the protein database file to start with the following fields separated by a tab: <Uniprot_accession> <protein id> <sequence>\nBut tried to unpack following vector in 3 variables: {:?}", self.error)
     }
 }
 
-impl Error for DatabaseFormatError {}
\ No newline at end of file
+impl Error for DatabaseFormatError {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use sa_mappings::proteins::MalformedLinesError;
+    use tempdir::TempDir;
+    use umgap::taxon::TaxonId;
+
+    use crate::{get_proteins_from_database_file, get_proteins_from_database_file_skip_malformed};
+    use crate::taxon_id_calculator::TaxonIdVerifier;
+
+    /// Verifier that accepts every taxon id
+    struct AllTaxaExist;
+
+    impl TaxonIdVerifier for AllTaxaExist {
+        fn taxon_id_exists(&self, _id: TaxonId) -> bool {
+            true
+        }
+    }
+
+    fn create_database_file(tmp_dir: &TempDir) -> PathBuf {
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = File::create(&database_file).unwrap();
+        file.write_all("P12345\t1\tMLPGLALLL\n".as_bytes()).unwrap();
+        // a truncated line
+        file.write_all("P54321\t2\n".as_bytes()).unwrap();
+        file.write_all("P67890\t6\tKWDSDPSG\n".as_bytes()).unwrap();
+        database_file
+    }
+
+    #[test]
+    fn test_skip_malformed_lines() {
+        let tmp_dir = TempDir::new("test_skip_malformed_lines").unwrap();
+        let database_file = create_database_file(&tmp_dir);
+        let database_file = database_file.to_str().unwrap();
+
+        assert!(get_proteins_from_database_file(database_file, &AllTaxaExist).is_err());
+
+        let (proteins, skipped_lines) = get_proteins_from_database_file_skip_malformed(database_file, &AllTaxaExist, 0.5).unwrap();
+        assert_eq!(skipped_lines, 1);
+        assert_eq!(proteins.input_string, b"MLPGLALLL-KWDSDPSG$");
+        assert_eq!(proteins.get_sequence(&proteins.proteins[1]), "KWDSDPSG");
+
+        // 1 malformed line out of 3 exceeds a ratio of 0.25
+        let error = get_proteins_from_database_file_skip_malformed(database_file, &AllTaxaExist, 0.25).err().unwrap();
+        assert!(error.downcast_ref::<MalformedLinesError>().is_some());
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        let tmp_dir = TempDir::new("test_extra_columns").unwrap();
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = File::create(&database_file).unwrap();
+        file.write_all("P12345\t1\tMLPGLALLL\tGO:0009279\textra\tcolumns\n".as_bytes()).unwrap();
+
+        let proteins = get_proteins_from_database_file(database_file.to_str().unwrap(), &AllTaxaExist).unwrap();
+        assert_eq!(proteins.input_string, b"MLPGLALLL$");
+        assert_eq!(proteins.proteins[0].uniprot_id, "P12345");
+        assert_eq!(proteins.proteins[0].id, 1);
+    }
+}